@@ -21,7 +21,7 @@ use crate::checker::removed_response_property_check::RemovedResponsePropertyChec
 use crate::checker::removed_schema_enum_value_check::RemovedSchemaEnumValueCheck;
 use crate::checker::updated_schema_type_check::UpdatedSchemaTypeCheck;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ValidationIssue {
     pub path: PathPointer,
     pub breaking: bool,
@@ -60,43 +60,41 @@ trait ValidationIssuer<'s> {
     fn issues(&self) -> Option<Vec<ValidationIssue>>;
 }
 
+/// All available checkers, in the order their issues are reported
+fn available_issuers<'s>() -> Vec<Box<dyn ValidationIssuer<'s>>> {
+    vec![
+        Box::<RemovedOperationCheck>::default(),
+        Box::<RemovedMediaTypeCheck>::default(),
+        Box::<UpdatedSchemaTypeCheck>::default(),
+        Box::<AddedRequiredParameterCheck>::default(),
+        Box::<RemovedResponsePropertyCheck>::default(),
+        Box::<RemovedSchemaEnumValueCheck>::default(),
+        Box::<AddedRequiredRequestBodyCheck>::default(),
+        Box::<AddedRequiredBodyPropertyCheck>::default(),
+    ]
+}
+
+/// Ids of all available checkers which could be passed to `validate`
+pub fn checker_ids() -> Vec<&'static str> {
+    available_issuers()
+        .iter()
+        .map(|issuer| issuer.id())
+        .collect()
+}
+
 pub fn validate(
     diff: &HttpSchemaDiff,
     checkers: &[&str],
 ) -> Vec<ValidationIssue> {
-    let removed_operation = Box::<RemovedOperationCheck>::default();
-    let removed_media_type = Box::<RemovedMediaTypeCheck>::default();
-    let changed_schema_type = Box::<UpdatedSchemaTypeCheck>::default();
-    let removed_schema_enum_value =
-        Box::<RemovedSchemaEnumValueCheck>::default();
-    let added_required_parameter =
-        Box::<AddedRequiredParameterCheck>::default();
-    let removed_response_property =
-        Box::<RemovedResponsePropertyCheck>::default();
-    let added_required_request_body =
-        Box::<AddedRequiredRequestBodyCheck>::default();
-    let added_required_body_property =
-        Box::<AddedRequiredBodyPropertyCheck>::default();
-
-    let available_issuers: Vec<&dyn ValidationIssuer> = vec![
-        &*removed_operation,
-        &*removed_media_type,
-        &*changed_schema_type,
-        &*added_required_parameter,
-        &*removed_response_property,
-        &*removed_schema_enum_value,
-        &*added_required_request_body,
-        &*added_required_body_property,
-    ];
-
-    let issuers: Vec<_> = if checkers.contains(&"*") {
-        available_issuers
-    } else {
-        available_issuers
-            .into_iter()
-            .filter(|issuer| checkers.contains(&issuer.id()))
-            .collect()
-    };
+    let available_issuers = available_issuers();
+
+    let issuers: Vec<&dyn ValidationIssuer> = available_issuers
+        .iter()
+        .map(|issuer| issuer.as_ref())
+        .filter(|issuer| {
+            checkers.contains(&"*") || checkers.contains(&issuer.id())
+        })
+        .collect();
 
     let visitors: Vec<_> = issuers.iter().map(|v| v.visitor()).collect();
 
@@ -0,0 +1,112 @@
+//! Compact change-only representation of serialized diffs.
+//!
+//! Every `DiffResult` is serialized as `{"t": <tag>, "v": <value>}`, so
+//! unchanged (`=`) subtrees could be dropped without knowing the diff types.
+//! Scalar unchanged values (names, types, descriptions, ...) are kept since
+//! they are required to render changed nodes. Dropped subtrees are marked with
+//! `{"t": "=", "c": true}`. They are not restored from the compact diff, full
+//! diffs are recomputed from the source and target schemas instead.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub const COMPACT_MARKER: &str = "c";
+
+const TAG_KEY: &str = "t";
+const VALUE_KEY: &str = "v";
+const SAME_TAG: &str = "=";
+
+fn is_diff_result(map: &Map<String, Value>) -> bool {
+    let tag = map.get(TAG_KEY).and_then(|t| t.as_str());
+    matches!(tag, Some("n" | "=" | "+" | "~" | "-"))
+        && map.keys().all(|k| k == TAG_KEY || k == VALUE_KEY)
+}
+
+fn compact_value(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) => {
+            if is_diff_result(&map) {
+                let is_same = map.get(TAG_KEY).and_then(|t| t.as_str())
+                    == Some(SAME_TAG);
+                let is_container = matches!(
+                    map.get(VALUE_KEY),
+                    Some(Value::Object(_) | Value::Array(_))
+                );
+
+                if is_same && is_container {
+                    map.remove(VALUE_KEY);
+                    map.insert(COMPACT_MARKER.to_owned(), Value::Bool(true));
+                    return Value::Object(map);
+                }
+            }
+
+            Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, compact_value(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => {
+            Value::Array(values.into_iter().map(compact_value).collect())
+        }
+        value => value,
+    }
+}
+
+/// Serializes diff keeping only changed subtrees
+pub fn compact<T: Serialize>(diff: &T) -> serde_json::Result<Value> {
+    let value = serde_json::to_value(diff)?;
+    Ok(compact_value(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compact::compact;
+    use crate::get_schema_diff;
+    use crate::try_deserialize_schema;
+    use serde_json::json;
+
+    #[test]
+    fn test_compact_diff() {
+        let src_content = r#"{
+            "openapi": "3.0.3",
+            "info": {"title": "Test", "version": "1.0"},
+            "paths": {
+                "/same": {"get": {"summary": "Same", "responses": {}}},
+                "/updated": {"get": {"summary": "Old", "responses": {}}}
+            }
+        }"#;
+
+        let tgt_content = r#"{
+            "openapi": "3.0.3",
+            "info": {"title": "Test", "version": "1.0"},
+            "paths": {
+                "/same": {"get": {"summary": "Same", "responses": {}}},
+                "/updated": {"get": {"summary": "New", "responses": {}}}
+            }
+        }"#;
+
+        let (src, tgt) =
+            try_deserialize_schema(src_content, tgt_content).unwrap();
+        let diff = get_schema_diff(src, tgt);
+        let diff = diff.get().unwrap();
+
+        let full = serde_json::to_value(diff).unwrap();
+        let compacted = compact(diff).unwrap();
+
+        assert_eq!(full["info"]["t"], "=");
+        assert!(full["info"]["v"].is_object());
+
+        // updated diff result value is serialized as `[new, old]`
+        let paths = &compacted["paths"]["v"][0];
+        assert_eq!(paths["/same"], json!({"t": "=", "c": true}));
+        let path = &paths["/updated"]["v"][0]["v"]["v"][0];
+        let operation = &path["get"]["v"][0];
+        assert_eq!(
+            operation["summary"],
+            json!({"t": "~", "v": ["New", "Old"]})
+        );
+        assert_eq!(operation["responses"], json!({"t": "=", "c": true}));
+        assert_eq!(compacted["info"], json!({"t": "=", "c": true}));
+    }
+}
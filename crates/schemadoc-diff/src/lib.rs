@@ -1,4 +1,5 @@
 pub mod checker;
+pub mod compact;
 pub mod context;
 pub mod core;
 pub mod diff_own_changes;
@@ -602,7 +602,7 @@ pub struct MergedVisitor<'a, 's> {
 }
 
 impl<'a, 's> MergedVisitor<'a, 's> {
-    pub fn new(visitors: &'s [&'a dyn DiffVisitor<'s>]) -> Self {
+    pub fn new(visitors: &'a [&'a dyn DiffVisitor<'s>]) -> Self {
         let config = RefCell::new(vec![None; visitors.len()]);
 
         Self { config, visitors }
@@ -4,4 +4,6 @@ mod google_chats;
 mod slack;
 mod utils;
 
-pub use services::{get_deps_alerts_info, get_own_alerts_info, send_alert};
+pub use services::{
+    get_deps_alerts_info, get_own_alerts_info, send_alert, validate_service_config, AlertInfo,
+};
@@ -1,29 +1,34 @@
 use anyhow::anyhow;
 use serde_yaml::{Mapping, Value};
+use tracing::Instrument;
 
 use schemadoc_diff::checker::ValidationIssue;
 use schemadoc_diff::exporters::{Exporter, Markdown};
 use schemadoc_diff::schema_diff::HttpSchemaDiff;
 
 use crate::alerts::{google_chats, slack};
-use crate::models::{AlertKind, Project};
+use crate::models::{AlertKind, GlobalAlert, Project, ProjectSlug};
 use crate::settings::Settings;
+use crate::usage::UsedOperations;
+use crate::{metrics, secrets};
 
-pub struct AlertInfo<'s> {
+pub struct AlertInfo {
     pub markdown: Markdown,
-    pub service: &'s String,
-    pub service_config: &'s Mapping,
+    pub service: String,
+    pub service_config: Mapping,
 }
 
-pub async fn get_own_alerts_info<'s>(
+pub async fn get_own_alerts_info(
     settings: &Settings,
-    project: &'s Project,
+    project: &Project,
+    global_alerts: &[GlobalAlert],
     branch_name: &str,
     tgt_version_id: u32,
     diff: &HttpSchemaDiff,
     validations: &[ValidationIssue],
-) -> Result<Vec<AlertInfo<'s>>, anyhow::Error> {
-    if project.alerts.is_empty() {
+) -> Result<Vec<AlertInfo>, anyhow::Error> {
+    let alerts = project.effective_alerts(global_alerts);
+    if alerts.is_empty() {
         return Ok(vec![]);
     };
 
@@ -31,9 +36,9 @@ pub async fn get_own_alerts_info<'s>(
 
     let mut info = Vec::new();
 
-    for alert in &project.alerts {
+    for alert in alerts {
         if !alert.is_active {
-            println!("Alert is not active");
+            tracing::debug!(alert = %alert.name, "Alert is not active");
             continue;
         }
 
@@ -52,14 +57,14 @@ pub async fn get_own_alerts_info<'s>(
         let markdown = diff.export(fields, &version_url, breaking_only, None, Some(validations));
 
         if markdown.is_empty() {
-            println!("Alert markdown is empty");
+            tracing::debug!(alert = %alert.name, "Alert markdown is empty");
             continue;
         }
 
-        let service_config = match &alert.service_config {
-            Value::Mapping(m) => m,
+        let service_config = match &alert.service_config.value {
+            Value::Mapping(m) => m.clone(),
             _ => {
-                println!("Alert was not properly configured");
+                tracing::warn!(alert = %alert.name, "Alert was not properly configured");
                 continue;
             }
         };
@@ -67,27 +72,42 @@ pub async fn get_own_alerts_info<'s>(
         info.push(AlertInfo {
             markdown,
             service_config,
-            service: &alert.service,
+            service: alert.service.clone(),
         });
     }
 
     Ok(info)
 }
 
-pub async fn get_deps_alerts_info<'s>(
+pub async fn get_deps_alerts_info(
     settings: &Settings,
-    project: &'s Project,
+    project: &Project,
     src_branch_name: &str,
     src_version_id: u32,
     tgt_branch_name: &str,
     tgt_version_id: u32,
-    dep_projects: Vec<&'s Project>,
+    dep_projects: Vec<(&Project, Option<UsedOperations>)>,
+    global_alerts: &[GlobalAlert],
     diff: &HttpSchemaDiff,
     validations: &[ValidationIssue],
-) -> Result<Vec<AlertInfo<'s>>, anyhow::Error> {
+) -> Result<Vec<AlertInfo>, anyhow::Error> {
     let mut info = Vec::new();
 
-    for dep in dep_projects {
+    for (dep, used) in dep_projects {
+        // Only issues and changes of the operations used by the dependent project are reported
+        let (validations, endpoints) = match &used {
+            Some(used) if !used.is_changed() => continue,
+            Some(used) => {
+                let validations: Vec<_> = validations
+                    .iter()
+                    .filter(|issue| used.contains(issue))
+                    .cloned()
+                    .collect();
+                (validations, Some(used.endpoints()))
+            }
+            None => (validations.to_vec(), None),
+        };
+
         let version_url = settings.url_to_dependency_compare(
             &dep.slug,
             &project.slug,
@@ -97,13 +117,13 @@ pub async fn get_deps_alerts_info<'s>(
             tgt_version_id,
         );
 
-        for alert in &dep.alerts {
+        for alert in dep.effective_alerts(global_alerts) {
             if !alert.source.is_deps() {
                 continue;
             }
 
             if !alert.is_active {
-                println!("Alert is not active");
+                tracing::debug!(alert = %alert.name, "Alert is not active");
                 continue;
             }
 
@@ -120,18 +140,23 @@ pub async fn get_deps_alerts_info<'s>(
             .into();
 
             let breaking_only = matches!(alert.kind, AlertKind::Breaking);
-            let markdown =
-                diff.export(fields, &version_url, breaking_only, None, Some(validations));
+            let markdown = diff.export(
+                fields,
+                &version_url,
+                breaking_only,
+                endpoints.as_deref(),
+                Some(&validations),
+            );
 
             if markdown.is_empty() {
-                println!("Alert markdown is empty");
+                tracing::debug!(alert = %alert.name, "Alert markdown is empty");
                 continue;
             }
 
-            let service_config = match &alert.service_config {
-                Value::Mapping(m) => m,
+            let service_config = match &alert.service_config.value {
+                Value::Mapping(m) => m.clone(),
                 _ => {
-                    println!("Alert was not properly configured");
+                    tracing::warn!(alert = %alert.name, "Alert was not properly configured");
                     continue;
                 }
             };
@@ -139,7 +164,7 @@ pub async fn get_deps_alerts_info<'s>(
             info.push(AlertInfo {
                 markdown,
                 service_config,
-                service: &alert.service,
+                service: alert.service.clone(),
             });
         }
     }
@@ -147,25 +172,75 @@ pub async fn get_deps_alerts_info<'s>(
     Ok(info)
 }
 
-pub async fn send_alert(alert_info: AlertInfo<'_>) -> Result<(), anyhow::Error> {
-    match alert_info.service.as_ref() {
+/// Checks that alert service is supported and its config could be used to send alerts
+pub fn validate_service_config(service: &str, service_config: &Value) -> anyhow::Result<()> {
+    let Value::Mapping(config) = service_config else {
+        return Err(anyhow!("Field 'service_config' must be a mapping."));
+    };
+
+    match service {
+        "GoogleChats" => google_chats::GoogleChatsIntegrationConfig::try_from(config).map(|_| ()),
+        "Slack" => slack::SlackIntegrationConfig::try_from(config).map(|_| ()),
+        _ => Err(anyhow!("Invalid alert service provided")),
+    }
+}
+
+pub async fn send_alert(
+    project_slug: &ProjectSlug,
+    alert_info: AlertInfo,
+) -> Result<(), anyhow::Error> {
+    let result = spawn_alert(project_slug, alert_info.service.clone(), &alert_info);
+    if result.is_err() {
+        metrics::record_alert(project_slug, &alert_info.service, false);
+    }
+    result
+}
+
+fn log_alert_result(result: &anyhow::Result<()>) {
+    match result {
+        Ok(_) => tracing::info!("Alert sent"),
+        // Service url could contain resolved secrets
+        Err(err) => tracing::warn!(
+            error = %secrets::redact(&err.to_string()),
+            "Alert could not be sent"
+        ),
+    }
+}
+
+fn spawn_alert(
+    project_slug: &ProjectSlug,
+    service: String,
+    alert_info: &AlertInfo,
+) -> Result<(), anyhow::Error> {
+    let span = tracing::info_span!("alert.send", project = %project_slug, service = %service);
+    let project_slug = project_slug.clone();
+    let message = alert_info.markdown.as_str().to_owned();
+
+    match service.as_ref() {
         "GoogleChats" => {
             let config =
-                google_chats::GoogleChatsIntegrationConfig::try_from(alert_info.service_config)?;
-            let message = alert_info.markdown.as_str().to_owned();
-            tokio::spawn(async move {
-                // TODO: handler errors during message sending
-                let _r = google_chats::send_message(&message, &config).await;
-            });
+                google_chats::GoogleChatsIntegrationConfig::try_from(&alert_info.service_config)?;
+            tokio::spawn(
+                async move {
+                    let result = google_chats::send_message(&message, &config).await;
+                    log_alert_result(&result);
+                    metrics::record_alert(&project_slug, &service, result.is_ok());
+                }
+                .instrument(span),
+            );
 
             Ok(())
         }
         "Slack" => {
-            let config = slack::SlackIntegrationConfig::try_from(alert_info.service_config)?;
-            let message = alert_info.markdown.as_str().to_owned();
-            tokio::spawn(async move {
-                let _r = slack::send_message(&message, &config).await;
-            });
+            let config = slack::SlackIntegrationConfig::try_from(&alert_info.service_config)?;
+            tokio::spawn(
+                async move {
+                    let result = slack::send_message(&message, &config).await;
+                    log_alert_result(&result);
+                    metrics::record_alert(&project_slug, &service, result.is_ok());
+                }
+                .instrument(span),
+            );
 
             Ok(())
         }
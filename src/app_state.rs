@@ -1,29 +1,47 @@
 use crate::constants;
-use anyhow::bail;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+use crate::compatibility::CompatibilityCache;
 use crate::dependencies::setup_project_dependencies;
 use crate::models::{
-    Alert, AlertKind, AlertSource, DataSource, DataSourceSource, Dependency, Link, Project,
-    ProjectKind, ProjectSlug,
+    Alert, AlertKind, AlertSource, DataSource, DataSourceHook, DataSourceSource, Dependency,
+    DependencyUsage, GlobalAlert, Link, Owner, Project, ProjectKind, ProjectSlug, RetentionPolicy,
 };
-use crate::persistence::{load_data_file, PersistentData, Versioned};
+use crate::persistence::{PersistentData, Versioned};
+use crate::secrets::Interpolated;
 use crate::settings::Settings;
-use crate::storage::{LocalStorage, Storage};
+use crate::storage::{LocalStorage, Storage, Storer};
+use crate::versions::services::DiffCache;
+use crate::{config_check, config_file, scheduler, versions};
+
+pub(crate) const CONFIG_FILE_NAME: &str = "schemadoc.yaml";
 
 #[derive(Debug)]
 pub struct AppState {
     pub storage: Storage,
     pub config_storage: Option<Storage>,
     pub projects: IndexMap<ProjectSlug, Project>,
+    /// Alerts applied to all projects or projects matching labels
+    pub alerts: Vec<GlobalAlert>,
+    pub compatibility_cache: CompatibilityCache,
+    pub diff_cache: DiffCache,
+    /// Last successful load or reload of `schemadoc.yaml`
+    pub config_loaded_at: DateTime<Utc>,
+    /// Error of the last reload, if it failed the previous configuration is kept
+    pub config_reload_error: Option<String>,
 }
 
 impl AppState {
     pub async fn from_settings(settings: &Settings) -> anyhow::Result<Self> {
         let storage = if settings.persistence.is_local() {
-            Storage::Local(LocalStorage::new(&settings.persistence_path))
+            Storage::Local(LocalStorage::new(
+                &settings.persistence_path,
+                settings.persistence_compression,
+            ))
         } else {
             bail!("Persistence {:?} not supported", settings.persistence)
         };
@@ -34,167 +52,447 @@ impl AppState {
             None
         } else {
             let storage = if settings.config_persistence.is_local() {
-                Storage::Local(LocalStorage::new(&settings.config_persistence_path))
+                Storage::Local(LocalStorage::new(
+                    &settings.config_persistence_path,
+                    settings.persistence_compression,
+                ))
             } else {
                 bail!("Persistence {:?} not supported", settings.persistence)
             };
             Some(storage)
         };
 
+        for storage in [Some(&storage), config_storage.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            let recovered = storage.recover().await?;
+            for path in recovered {
+                tracing::warn!("Removed partially written file: {:?}", path);
+            }
+        }
+
         AppState::read(storage, config_storage).await
     }
 
     pub async fn read(storage: Storage, config_storage: Option<Storage>) -> anyhow::Result<Self> {
-        let state_data = load_data_file::<AppStatePersistentData, _, _, PersistentProjectsFile<_>>(
-            config_storage.as_ref().unwrap_or(&storage),
-            "schemadoc.yaml",
-        )
-        .await?;
-
-        let default_branches: HashMap<_, _> = state_data
-            .0
-            .iter()
-            .map(|(slug, config)| {
-                (
-                    slug.clone(),
-                    config
-                        .default_branch
-                        .as_deref()
-                        .unwrap_or(constants::BRANCH_DEFAULT_NAME)
-                        .to_owned(),
-                )
-            })
-            .collect();
-
-        let mut projects: IndexMap<_, _> = state_data
-            .0
-            .into_iter()
-            .map(|(slug, config)| {
-                let default_branch = config
-                    .default_branch
-                    .unwrap_or_else(|| constants::BRANCH_DEFAULT_NAME.to_owned());
-
-                let dependencies = config.dependencies.map(|deps| {
-                    deps.into_iter()
-                        .filter_map(|(project, def)| {
-                            let Some(branch) = default_branches.get(&project).cloned() else {
-                                return None;
-                            };
-
-                            let dependency = if let Ok(version) =
-                                serde_yaml::from_value::<u32>(def.clone())
-                            {
-                                Dependency {
-                                    branch,
-                                    project,
-                                    version,
-                                    breaking: None,
-                                    outdated: None,
-                                }
-                            } else if let Ok(version) =
-                                serde_yaml::from_value::<String>(def.clone())
-                            {
-                                Dependency {
-                                    branch,
-                                    project,
-                                    version: version.parse().unwrap_or(0),
-                                    breaking: None,
-                                    outdated: None,
-                                }
-                            } else if let Ok(config) =
-                                serde_yaml::from_value::<ProjectDependencyConfig>(def.clone())
-                            {
-                                Dependency {
-                                    branch,
-                                    project,
-                                    version: config.version.parse().unwrap_or(0),
-                                    breaking: None,
-                                    outdated: None,
-                                }
-                            } else {
-                                panic!("In {} dependency {} has unknown structure", slug, project)
-                            };
-
-                            Some(dependency)
-                        })
-                        .collect()
-                });
-
-                let kind = config.kind.unwrap_or(ProjectKind::Server);
-
-                let alerts = config
-                    .alerts
-                    .map(|alerts| {
-                        alerts
-                            .into_iter()
-                            .map(|alert| Alert {
-                                name: alert.name,
-                                kind: alert.kind,
-                                source: alert.source,
-                                branches: alert.branches.unwrap_or_default(),
-                                is_active: alert.is_active,
-                                service: alert.service,
-                                service_config: alert.service_config,
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_default();
-
-                let data_sources = config
-                    .data_sources
-                    .map(|dsc| {
-                        dsc.into_iter()
-                            .map(|ds| DataSource {
-                                status: None,
-                                name: ds.name,
-                                source: ds.source,
-                                branch: ds.branch.unwrap_or(default_branch.clone()),
-                            })
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
-
-                let project = Project {
-                    kind,
-                    alerts,
-                    data_sources,
-                    default_branch,
-                    branches: vec![],
-                    slug: slug.clone(),
-                    name: config.name,
-                    links: config.links,
-                    description: config.description,
-                    dependencies: dependencies.unwrap_or_default(),
-                };
-
-                (slug, project)
-            })
-            .collect();
-
-        // validate
-        for project in projects.values() {
-            validate_project(project)?;
-        }
+        let Config {
+            mut projects,
+            alerts,
+        } = load_config(config_storage.as_ref().unwrap_or(&storage)).await?;
 
         for project in projects.values_mut() {
             project.load_persistent_data(&storage).await?;
         }
 
+        for project in projects.values() {
+            validate_dependencies_branches(project, |slug| projects.get(slug))?;
+        }
+
         let mut state = Self {
             projects,
+            alerts,
             storage,
             config_storage,
+            compatibility_cache: CompatibilityCache::default(),
+            diff_cache: DiffCache::default(),
+            config_loaded_at: Utc::now(),
+            config_reload_error: None,
         };
 
+        let slugs: Vec<_> = state.projects.keys().cloned().collect();
+        for slug in &slugs {
+            versions::services::backfill_breaking_changes(&mut state, slug).await?;
+        }
+
         setup_project_dependencies(&mut state).await?;
 
         Ok(state)
     }
+
+    pub fn get_config_storage(&self) -> &Storage {
+        self.config_storage.as_ref().unwrap_or(&self.storage)
+    }
+
+    /// Adds or replaces project configuration in `schemadoc.yaml`
+    pub async fn persist_project_config(
+        &self,
+        slug: &ProjectSlug,
+        config: &ProjectConfig,
+    ) -> anyhow::Result<()> {
+        self.update_projects_config(
+            |content| config_file::upsert_project(content, slug.as_str(), config),
+            |projects| {
+                projects.insert(slug.clone(), config.clone());
+            },
+        )
+        .await
+    }
+
+    /// Removes project configuration from `schemadoc.yaml`
+    pub async fn remove_project_config(&self, slug: &ProjectSlug) -> anyhow::Result<()> {
+        self.update_projects_config(
+            |content| config_file::remove_project(content, slug.as_str()),
+            |projects| {
+                projects.shift_remove(slug);
+            },
+        )
+        .await
+    }
+
+    /// Edits `schemadoc.yaml` in place to keep comments and formatting of other projects.
+    /// Falls back to full file rewrite if in place edit is not possible.
+    async fn update_projects_config<E, U>(&self, edit: E, update: U) -> anyhow::Result<()>
+    where
+        E: FnOnce(&str) -> Option<String>,
+        U: FnOnce(&mut IndexMap<ProjectSlug, ProjectConfig>),
+    {
+        let storage = self.get_config_storage();
+
+        let _lock = storage.lock(CONFIG_FILE_NAME, true).await?;
+
+        let content = String::from_utf8(storage.read_file(CONFIG_FILE_NAME).await?)?;
+        let file: PersistentProjectsFile<AppStatePersistentData> = serde_yaml::from_str(&content)?;
+
+        let version = file.version;
+        let alerts = file.alerts;
+        let mut projects = file.projects.0;
+        update(&mut projects);
+
+        let edited = edit(&content).filter(|edited| {
+            serde_yaml::from_str::<PersistentProjectsFile<AppStatePersistentData>>(edited)
+                .map(|file| file.projects.0 == projects)
+                .unwrap_or(false)
+        });
+
+        let content = match edited {
+            Some(edited) => edited,
+            None => {
+                tracing::warn!("Could not edit {CONFIG_FILE_NAME} in place, rewriting it");
+                serde_yaml::to_string(&PersistentProjectsFile {
+                    version,
+                    alerts,
+                    projects: AppStatePersistentData(projects),
+                })?
+            }
+        };
+
+        storage
+            .put_file(CONFIG_FILE_NAME, content.as_bytes())
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Projects and global alerts from `schemadoc.yaml`
+pub(crate) struct Config {
+    pub projects: IndexMap<ProjectSlug, Project>,
+    pub alerts: Vec<GlobalAlert>,
+}
+
+/// Reads and validates `schemadoc.yaml`, persistent data of projects is not loaded
+pub(crate) async fn load_config(config_storage: &Storage) -> anyhow::Result<Config> {
+    let file = read_config_file(config_storage).await?;
+
+    let default_branches: HashMap<_, _> = file
+        .projects
+        .0
+        .iter()
+        .map(|(slug, config)| {
+            (
+                slug.clone(),
+                config
+                    .default_branch
+                    .as_deref()
+                    .unwrap_or(constants::BRANCH_DEFAULT_NAME)
+                    .to_owned(),
+            )
+        })
+        .collect();
+
+    let projects: IndexMap<_, _> = file
+        .projects
+        .0
+        .into_iter()
+        .map(|(slug, config)| {
+            let project = project_from_config(slug.clone(), config, &default_branches)?;
+            Ok((slug, project))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    // validate
+    for project in projects.values() {
+        validate_project(project)?;
+    }
+
+    let alerts = file
+        .alerts
+        .unwrap_or_default()
+        .into_iter()
+        .map(|config| {
+            let routes = config
+                .routes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(value, service_config)| {
+                    let service_config =
+                        Interpolated::resolve(service_config).with_context(|| {
+                            format!("global alert {} route {}", config.alert.name, value)
+                        })?;
+                    Ok((value, service_config))
+                })
+                .collect::<anyhow::Result<_>>()?;
+
+            Ok(GlobalAlert {
+                selector: config.selector.unwrap_or_default(),
+                route_by: config.route_by,
+                routes,
+                alert: alert_from_config("global", config.alert)?,
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(Config { projects, alerts })
+}
+
+async fn read_config_file(
+    storage: &Storage,
+) -> anyhow::Result<PersistentProjectsFile<AppStatePersistentData>> {
+    let _lock = storage.lock(CONFIG_FILE_NAME, false).await?;
+
+    if !storage.exists(CONFIG_FILE_NAME).await? {
+        return Ok(PersistentProjectsFile::new(
+            AppStatePersistentData::latest(),
+            Default::default(),
+        ));
+    }
+
+    let content = String::from_utf8(storage.read_file(CONFIG_FILE_NAME).await?)?;
+
+    let issues = config_check::check_config(&content);
+    if !issues.is_empty() {
+        let issues = issues
+            .iter()
+            .map(|issue| format!("{CONFIG_FILE_NAME}: {issue}"))
+            .collect::<Vec<_>>();
+        bail!("Improperly configured:\n{}", issues.join("\n"))
+    }
+
+    Ok(serde_yaml::from_str(&content)?)
+}
+
+fn alert_from_config(owner: &str, alert: AlertConfig) -> anyhow::Result<Alert> {
+    let service_config = Interpolated::resolve(alert.service_config)
+        .with_context(|| format!("{} alert {}", owner, alert.name))?;
+
+    Ok(Alert {
+        name: alert.name,
+        kind: alert.kind,
+        source: alert.source,
+        branches: alert.branches.unwrap_or_default(),
+        is_active: alert.is_active,
+        service: alert.service,
+        service_config,
+    })
+}
+
+pub(crate) fn project_from_config(
+    slug: ProjectSlug,
+    config: ProjectConfig,
+    default_branches: &HashMap<ProjectSlug, String>,
+) -> anyhow::Result<Project> {
+    let default_branch = config
+        .default_branch
+        .unwrap_or_else(|| constants::BRANCH_DEFAULT_NAME.to_owned());
+
+    let dependencies = config
+        .dependencies
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(project, def)| {
+            let default_branch = default_branches.get(&project)?;
+            Some((project, def, default_branch))
+        })
+        .map(|(project, def, default_branch)| {
+            let Some(config) = ProjectDependencyConfig::from_value(&def) else {
+                bail!("In {} dependency {} has unknown structure", slug, project)
+            };
+
+            // Not numeric version is a semver range resolved to the version id later
+            let (version, range) = match config.version.parse::<u32>() {
+                Ok(version) => (Some(version), None),
+                Err(_) => (None, Some(config.version)),
+            };
+
+            Ok(Dependency {
+                branch: config.branch.unwrap_or_else(|| default_branch.clone()),
+                project,
+                version,
+                range,
+                usage: config.usage,
+                breaking: None,
+                outdated: None,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let kind = config.kind.unwrap_or(ProjectKind::Server);
+
+    let alerts = config
+        .alerts
+        .unwrap_or_default()
+        .into_iter()
+        .map(|alert| alert_from_config(slug.as_str(), alert))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let data_sources = config
+        .data_sources
+        .unwrap_or_default()
+        .into_iter()
+        .map(|ds| {
+            let source = Interpolated::resolve(ds.source)
+                .with_context(|| format!("{} data source {}", slug, ds.name))?;
+            let hook = ds
+                .hook
+                .map(Interpolated::resolve)
+                .transpose()
+                .with_context(|| format!("{} data source {} hook", slug, ds.name))?;
+
+            if let Some(cron) = &ds.pull_cron {
+                if ds.pull_interval_minutes.is_some() {
+                    bail!(
+                        "{} data source {} must have either `pull_cron` or `pull_interval_minutes`",
+                        slug,
+                        ds.name
+                    )
+                }
+                scheduler::parse_cron(cron)
+                    .with_context(|| format!("{} data source {}", slug, ds.name))?;
+            }
+
+            Ok(DataSource {
+                status: None,
+                name: ds.name,
+                source,
+                pull_interval_minutes: ds.pull_interval_minutes,
+                pull_cron: ds.pull_cron,
+                hook,
+                branch: ds.branch.unwrap_or(default_branch.clone()),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let retention = config
+        .retention
+        .map(|retention| {
+            retention
+                .into_iter()
+                .map(|policy| RetentionPolicy {
+                    branches: policy.branches.unwrap_or_default(),
+                    keep_last: policy.keep_last,
+                    keep_days: policy.keep_days,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Project {
+        kind,
+        retention,
+        rules: config.rules.unwrap_or_default(),
+        labels: config.labels.unwrap_or_default(),
+        owners: config.owners.unwrap_or_default(),
+        inherit_alerts: config.inherit_alerts.unwrap_or(true),
+        alerts,
+        data_sources,
+        default_branch,
+        branches: vec![],
+        slug,
+        name: config.name,
+        links: config.links,
+        description: config.description,
+        dependencies,
+    })
 }
 
 #[derive(Serialize, Deserialize, Default)]
 struct AppStatePersistentData(pub IndexMap<ProjectSlug, ProjectConfig>);
 
+impl From<&Project> for ProjectConfig {
+    fn from(project: &Project) -> Self {
+        let dependencies = project
+            .dependencies
+            .iter()
+            .map(|d| {
+                let config = ProjectDependencyConfig {
+                    version: d
+                        .range
+                        .clone()
+                        .unwrap_or_else(|| d.version.unwrap_or_default().to_string()),
+                    branch: Some(d.branch.clone()),
+                    usage: d.usage.clone(),
+                };
+                let value = serde_yaml::to_value(config).unwrap_or(serde_yaml::Value::Null);
+                (d.project.clone(), value)
+            })
+            .collect::<IndexMap<_, _>>();
+
+        let alerts = project
+            .alerts
+            .iter()
+            .map(|alert| AlertConfig {
+                name: alert.name.clone(),
+                kind: alert.kind,
+                source: alert.source,
+                branches: Some(alert.branches.clone()).filter(|b| !b.is_empty()),
+                is_active: alert.is_active,
+                service: alert.service.clone(),
+                service_config: alert.service_config.redacted().clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let data_sources = project
+            .data_sources
+            .iter()
+            .map(|ds| DataSourceConfig {
+                name: ds.name.clone(),
+                branch: Some(ds.branch.clone()),
+                source: ds.source.redacted().clone(),
+                pull_interval_minutes: ds.pull_interval_minutes,
+                pull_cron: ds.pull_cron.clone(),
+                hook: ds.hook.as_ref().map(|hook| hook.redacted().clone()),
+            })
+            .collect::<Vec<_>>();
+
+        let retention = project
+            .retention
+            .iter()
+            .map(|policy| RetentionConfig {
+                branches: Some(policy.branches.clone()).filter(|b| !b.is_empty()),
+                keep_last: policy.keep_last,
+                keep_days: policy.keep_days,
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            name: project.name.clone(),
+            kind: Some(project.kind.clone()),
+            description: project.description.clone(),
+            links: project.links.clone(),
+            alerts: Some(alerts).filter(|a| !a.is_empty()),
+            data_sources: Some(data_sources).filter(|ds| !ds.is_empty()),
+            dependencies: Some(dependencies).filter(|d| !d.is_empty()),
+            retention: Some(retention).filter(|r| !r.is_empty()),
+            rules: Some(project.rules.clone()).filter(|r| !r.is_empty()),
+            labels: Some(project.labels.clone()).filter(|l| !l.is_empty()),
+            owners: Some(project.owners.clone()).filter(|o| !o.is_empty()),
+            inherit_alerts: Some(project.inherit_alerts).filter(|inherit| !inherit),
+            default_branch: Some(project.default_branch.clone()),
+        }
+    }
+}
+
 impl Versioned for AppStatePersistentData {
     fn latest() -> &'static str {
         "0.1"
@@ -204,19 +502,39 @@ impl Versioned for AppStatePersistentData {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProjectConfig {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kind: Option<ProjectKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub links: Option<Vec<Link>>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub alerts: Option<Vec<AlertConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data_sources: Option<Vec<DataSourceConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dependencies: Option<IndexMap<ProjectSlug, serde_yaml::Value>>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Vec<RetentionConfig>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<IndexMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owners: Option<Vec<Owner>>,
+    /// Set to `false` to opt out of global alerts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherit_alerts: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub default_branch: Option<String>,
 }
 
-fn validate_project(project: &Project) -> anyhow::Result<()> {
+pub(crate) fn validate_project(project: &Project) -> anyhow::Result<()> {
     let unique_data_sources_count = project
         .data_sources
         .iter()
@@ -234,12 +552,35 @@ fn validate_project(project: &Project) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Checks that branches of dependencies exist, so it is called after persistent data is loaded
+pub(crate) fn validate_dependencies_branches<'p>(
+    project: &Project,
+    find_project: impl Fn(&ProjectSlug) -> Option<&'p Project>,
+) -> anyhow::Result<()> {
+    for dependency in &project.dependencies {
+        let Some(target) = find_project(&dependency.project) else {
+            continue;
+        };
+        if !target.has_branch(&dependency.branch) {
+            bail!(
+                "Improperly configured: {} dependency {} branch `{}` not found",
+                project.slug,
+                dependency.project,
+                dependency.branch
+            )
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AlertConfig {
     pub name: String,
     pub kind: AlertKind,
     pub source: AlertSource,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub branches: Option<Vec<String>>,
 
     pub is_active: bool,
@@ -248,22 +589,90 @@ pub struct AlertConfig {
     pub service_config: serde_yaml::Value,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GlobalAlertConfig {
+    /// Project labels to match, alert is applied to all projects if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<IndexMap<String, String>>,
+    /// Project label which value selects service config from `routes`,
+    /// `service_config` is used for projects without a route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routes: Option<IndexMap<String, serde_yaml::Value>>,
+    #[serde(flatten)]
+    pub alert: AlertConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branches: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_days: Option<u32>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DataSourceConfig {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub branch: Option<String>,
     pub source: DataSourceSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_interval_minutes: Option<u32>,
+    /// Standard 5 fields cron expression in UTC, e.g. `0 */2 * * *`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_cron: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook: Option<DataSourceHook>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProjectDependencyConfig {
+    /// Version id or semver range of `info.version`, e.g. `^2.3`
     pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<DependencyUsage>,
+}
+
+impl ProjectDependencyConfig {
+    /// Dependency could be defined as a version or a mapping with `version` and `branch`
+    pub fn from_value(def: &serde_yaml::Value) -> Option<Self> {
+        if let Ok(version) = serde_yaml::from_value::<u32>(def.clone()) {
+            Some(Self {
+                version: version.to_string(),
+                branch: None,
+                usage: None,
+            })
+        } else if let Ok(version) = serde_yaml::from_value::<String>(def.clone()) {
+            Some(Self {
+                version,
+                branch: None,
+                usage: None,
+            })
+        } else {
+            let mut def = def.clone();
+            // Version id could be set as a number in mapping too
+            if let Some(version) = def.get_mut("version") {
+                if let Some(id) = version.as_u64() {
+                    *version = id.to_string().into();
+                }
+            }
+            serde_yaml::from_value::<Self>(def).ok()
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PersistentProjectsFile<T> {
     pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alerts: Option<Vec<GlobalAlertConfig>>,
     pub projects: T,
 }
 
@@ -279,7 +688,55 @@ impl<T> PersistentData<T> for PersistentProjectsFile<T> {
     fn new(version: impl Into<String>, data: T) -> Self {
         PersistentProjectsFile {
             version: version.into(),
+            alerts: None,
             projects: data,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::app_state::{project_from_config, validate_dependencies_branches, ProjectConfig};
+    use crate::models::ProjectSlug;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_dependencies_versions_and_branches() {
+        let orders = ProjectSlug::new("orders".to_owned());
+        let payments = ProjectSlug::new("payments".to_owned());
+        let default_branches = HashMap::from([(payments.clone(), "main".to_owned())]);
+
+        let project = |dependencies: &str| {
+            let config: ProjectConfig =
+                serde_yaml::from_str(&format!("name: Orders\ndependencies: {dependencies}"))
+                    .unwrap();
+            project_from_config(orders.clone(), config, &default_branches).unwrap()
+        };
+
+        let payments_project = project_from_config(
+            payments.clone(),
+            serde_yaml::from_str(
+                "name: Payments\ndata_sources:\n  - name: Dev\n    branch: dev\n    \
+                 source: !Url { url: https://payments/openapi.json }",
+            )
+            .unwrap(),
+            &default_branches,
+        )
+        .unwrap();
+        let find_project =
+            |slug: &ProjectSlug| Some(&payments_project).filter(|_| slug == &payments);
+
+        let pinned = project("{ payments: 3 }");
+        assert_eq!(pinned.dependencies[0].version, Some(3));
+        assert!(validate_dependencies_branches(&pinned, find_project).is_ok());
+
+        // Range is resolved to the version id later
+        let range = project("{ payments: { version: '^2.3', branch: dev } }");
+        assert_eq!(range.dependencies[0].version, None);
+        assert_eq!(range.dependencies[0].range.as_deref(), Some("^2.3"));
+        assert!(validate_dependencies_branches(&range, find_project).is_ok());
+
+        let missing = project("{ payments: { version: 1, branch: release } }");
+        assert!(validate_dependencies_branches(&missing, find_project).is_err());
+    }
+}
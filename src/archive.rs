@@ -0,0 +1,354 @@
+use anyhow::{anyhow, bail};
+use base64::Engine;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::app_state::{project_from_config, validate_project, AppState, ProjectConfig};
+use crate::dependencies::setup_project_dependencies;
+use crate::models::{Branch, DataSourceStatus, Project, ProjectSlug};
+use crate::persistence::{PersistentDataFile, Versioned};
+use crate::storage::Storer;
+
+const MAX_SLUG_REMAP_ATTEMPTS: u32 = 100;
+
+/// Self-contained project snapshot used to move projects between instances.
+/// Files paths are relative to the project folder, so archive can be imported with other slug.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectArchive {
+    pub slug: ProjectSlug,
+    pub config: ProjectConfig,
+    pub branches: Vec<Branch>,
+    pub data_sources: IndexMap<String, DataSourceStatus>,
+    pub files: IndexMap<String, String>,
+}
+
+impl Versioned for ProjectArchive {
+    fn latest() -> &'static str {
+        "0.1"
+    }
+}
+
+pub type ProjectArchiveFile = PersistentDataFile<ProjectArchive>;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub slug: ProjectSlug,
+    pub created: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+fn project_prefix(slug: &ProjectSlug) -> String {
+    format!("projects/{}/", slug)
+}
+
+pub async fn export_project(
+    state: &AppState,
+    project_slug: &ProjectSlug,
+) -> Result<ProjectArchiveFile, ExportError> {
+    let Some(project) = state.projects.get(project_slug) else {
+        return Err(ExportError::NotFound(format!(
+            "Project {project_slug} not found"
+        )));
+    };
+
+    let prefix = project_prefix(project_slug);
+
+    let mut files = IndexMap::new();
+
+    let paths = project
+        .branches
+        .iter()
+        .flat_map(|b| &b.versions)
+        .flat_map(|v| [&v.file_path, &v.diff_file_path]);
+
+    for path in paths {
+        let Some(relative_path) = path.strip_prefix(&prefix) else {
+            return Err(
+                anyhow!("File {path} is not stored in {project_slug} project folder").into(),
+            );
+        };
+
+        if files.contains_key(relative_path) {
+            continue;
+        }
+
+        let content = state
+            .storage
+            .read_file(path)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        files.insert(
+            relative_path.to_owned(),
+            base64::engine::general_purpose::STANDARD.encode(content),
+        );
+    }
+
+    let data_sources = project
+        .data_sources
+        .iter()
+        .filter_map(|ds| ds.status.clone().map(|status| (ds.branch.clone(), status)))
+        .collect();
+
+    let archive = ProjectArchive {
+        files,
+        data_sources,
+        slug: project.slug.clone(),
+        config: ProjectConfig::from(project),
+        branches: project.branches.clone(),
+    };
+
+    Ok(PersistentDataFile {
+        version: ProjectArchive::latest().to_owned(),
+        data: archive,
+    })
+}
+
+/// Relative path without `.`, `..` or empty segments, so it could not leave the project folder
+fn is_safe_relative_path(path: &str) -> bool {
+    !path.contains('\\')
+        && path
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+/// Returns decoded archive files, only files of the archive versions are accepted
+fn validate_archive(archive: &ProjectArchiveFile) -> anyhow::Result<HashMap<&str, Vec<u8>>> {
+    if archive.version != ProjectArchive::latest() {
+        bail!("Archive version {} is not supported", archive.version)
+    }
+
+    let archive = &archive.data;
+
+    let prefix = project_prefix(&archive.slug);
+
+    let mut referenced = HashSet::new();
+    for branch in &archive.branches {
+        for version in &branch.versions {
+            for path in [&version.file_path, &version.diff_file_path] {
+                let relative_path = path
+                    .strip_prefix(&prefix)
+                    .filter(|relative_path| is_safe_relative_path(relative_path))
+                    .ok_or(anyhow!(
+                        "File {path} of {} version {} is not stored in project folder",
+                        branch.name,
+                        version.id
+                    ))?;
+
+                if !archive.files.contains_key(relative_path) {
+                    bail!("Archive does not contain file {path}")
+                }
+
+                referenced.insert(relative_path);
+            }
+        }
+
+        if let Some(base) = &branch.base {
+            let exists = archive
+                .branches
+                .iter()
+                .find(|b| b.name == base.name)
+                .map(|b| b.versions.iter().any(|v| v.id == base.version_id))
+                .unwrap_or(false);
+
+            if !exists {
+                bail!(
+                    "Base version {}/{} of branch {} not found in archive",
+                    base.name,
+                    base.version_id,
+                    branch.name
+                )
+            }
+        }
+    }
+
+    archive
+        .files
+        .iter()
+        .map(|(path, content)| {
+            if !referenced.contains(path.as_str()) {
+                bail!("Archive file {path} is not used by any version")
+            }
+
+            base64::engine::general_purpose::STANDARD
+                .decode(content)
+                .map(|content| (path.as_str(), content))
+                .map_err(|_| anyhow!("Archive file {path} is not valid base64"))
+        })
+        .collect()
+}
+
+fn remap_branches(branches: &[Branch], src: &ProjectSlug, tgt: &ProjectSlug) -> Vec<Branch> {
+    let src_prefix = project_prefix(src);
+    let tgt_prefix = project_prefix(tgt);
+
+    let remap = |path: &str| match path.strip_prefix(&src_prefix) {
+        Some(relative_path) => format!("{tgt_prefix}{relative_path}"),
+        None => path.to_owned(),
+    };
+
+    branches
+        .iter()
+        .cloned()
+        .map(|mut branch| {
+            for version in &mut branch.versions {
+                version.file_path = remap(&version.file_path);
+                version.diff_file_path = remap(&version.diff_file_path);
+            }
+            branch
+        })
+        .collect()
+}
+
+fn is_same_project(existing: &Project, imported: &Project) -> bool {
+    existing.branches == imported.branches
+        && ProjectConfig::from(existing) == ProjectConfig::from(imported)
+}
+
+/// Imports project archive. If project slug is already taken by other project,
+/// the archive is imported with `-{n}` suffix. Importing the same archive twice is no-op.
+pub async fn import_project(
+    state: &mut AppState,
+    archive: ProjectArchiveFile,
+    slug: Option<ProjectSlug>,
+) -> anyhow::Result<ImportResult> {
+    let files = validate_archive(&archive)?;
+
+    let archive_slug = &archive.data.slug;
+    let slug = slug.unwrap_or_else(|| archive_slug.clone());
+
+    let default_branches: HashMap<_, _> = state
+        .projects
+        .values()
+        .map(|p| (p.slug.clone(), p.default_branch.clone()))
+        .collect();
+
+    let mut attempt = 0;
+
+    let mut project = loop {
+        let candidate = if attempt == 0 {
+            slug.clone()
+        } else {
+            ProjectSlug::new(format!("{}-{}", slug, attempt))
+        };
+
+        let mut project = project_from_config(
+            candidate.clone(),
+            archive.data.config.clone(),
+            &default_branches,
+        )?;
+        project.branches = remap_branches(&archive.data.branches, archive_slug, &candidate);
+
+        match state.projects.get(&candidate) {
+            None => break project,
+            Some(existing) if is_same_project(existing, &project) => {
+                tracing::info!("Project {} was already imported", candidate);
+                return Ok(ImportResult {
+                    slug: candidate,
+                    created: false,
+                });
+            }
+            Some(_) => {
+                attempt += 1;
+                if attempt > MAX_SLUG_REMAP_ATTEMPTS {
+                    bail!("Could not find free slug to import project {slug}")
+                }
+            }
+        }
+    };
+
+    validate_project(&project)?;
+
+    let prefix = project_prefix(&project.slug);
+    for (path, content) in files {
+        state
+            .storage
+            .put_file(format!("{prefix}{path}"), &content)
+            .await?;
+    }
+
+    for data_source in &mut project.data_sources {
+        data_source.status = archive.data.data_sources.get(&data_source.branch).cloned();
+    }
+
+    project.persist_branches(&state.storage).await?;
+    for data_source in &project.data_sources {
+        project
+            .persist_datasource(&state.storage, &data_source.branch)
+            .await?;
+    }
+
+    project.load_persistent_data(&state.storage).await?;
+
+    state
+        .persist_project_config(&project.slug, &archive.data.config)
+        .await?;
+
+    let slug = project.slug.clone();
+
+    tracing::info!("Import project {} as {}", archive_slug, slug);
+
+    state.projects.insert(slug.clone(), project);
+
+    setup_project_dependencies(state).await?;
+
+    Ok(ImportResult {
+        slug,
+        created: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::{validate_archive, ProjectArchiveFile};
+    use serde_json::json;
+
+    fn archive(files: serde_json::Value) -> ProjectArchiveFile {
+        serde_json::from_value(json!({
+            "version": "0.1",
+            "data": {
+                "slug": "payments",
+                "config": {"name": "Payments"},
+                "branches": [{"name": "main", "base": null, "versions": [{
+                    "id": 0, "version": null, "message": null,
+                    "file_path": "projects/payments/versions/a.json",
+                    "diff_file_path": "projects/payments/branches/main/diffs/0.json",
+                    "diff_file_version": "0.1",
+                    "statistics": {"total": 0, "added": 0, "removed": 0, "updated": 0},
+                    "created_at": "2024-05-01T10:00:00Z"
+                }]}],
+                "data_sources": {},
+                "files": files
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_archive_files() {
+        let files = json!({"versions/a.json": "e30=", "branches/main/diffs/0.json": "e30="});
+        assert_eq!(validate_archive(&archive(files)).unwrap().len(), 2);
+
+        let files = json!({
+            "versions/a.json": "e30=",
+            "branches/main/diffs/0.json": "e30=",
+            "../../tokens.yaml": "e30="
+        });
+        assert!(validate_archive(&archive(files)).is_err());
+
+        let mut traversal =
+            archive(json!({"versions/a.json": "e30=", "../../tokens.yaml": "e30="}));
+        traversal.data.branches[0].versions[0].diff_file_path =
+            "projects/payments/../../tokens.yaml".to_owned();
+        assert!(validate_archive(&traversal).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! Append-only audit log of mutating operations, stored as JSON lines
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::models::ProjectSlug;
+use crate::secrets;
+use crate::storage::Storer;
+
+pub const AUDIT_FILE_NAME: &str = "audit/audit.jsonl";
+
+/// The log is read backwards by chunks, so only the newest entries are read
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    /// Unauthorized or forbidden
+    Denied,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectSlug>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Action specific details, like name of the created token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl AuditTarget {
+    pub fn project(project: &ProjectSlug) -> Self {
+        Self {
+            project: Some(project.clone()),
+            ..Default::default()
+        }
+    }
+
+    pub fn branch(project: &ProjectSlug, branch: &str) -> Self {
+        Self {
+            branch: Some(branch.to_owned()),
+            ..Self::project(project)
+        }
+    }
+
+    pub fn details(details: impl Into<String>) -> Self {
+        Self {
+            details: Some(details.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_version(self, version: Option<u32>) -> Self {
+        Self { version, ..self }
+    }
+
+    pub fn with_details(self, details: impl Into<String>) -> Self {
+        Self {
+            details: Some(details.into()),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Authorization identity: `basic`, `token:{name}` or `jwt:{subject}`
+    pub actor: String,
+    pub action: String,
+    #[serde(flatten)]
+    pub target: AuditTarget,
+    pub outcome: AuditOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Appends the operation result to the audit log. Audit failures are logged
+/// and do not fail the operation itself.
+pub async fn record<S, T, E>(
+    storage: &S,
+    actor: String,
+    action: &str,
+    target: AuditTarget,
+    result: &Result<T, E>,
+) where
+    S: Storer,
+    E: Display,
+{
+    let outcome = match result {
+        Ok(_) => AuditOutcome::Success,
+        Err(_) => AuditOutcome::Failure,
+    };
+    let error = result.as_ref().err().map(|err| err.to_string());
+
+    append(storage, actor, action, target, outcome, error).await
+}
+
+/// Appends the entry to the audit log, secrets are redacted from the error
+pub async fn append<S: Storer>(
+    storage: &S,
+    actor: String,
+    action: &str,
+    target: AuditTarget,
+    outcome: AuditOutcome,
+    error: Option<String>,
+) {
+    let entry = AuditEntry {
+        timestamp: Utc::now(),
+        actor,
+        action: action.to_owned(),
+        target,
+        outcome,
+        error: error.map(|err| secrets::redact(&err).into_owned()),
+    };
+
+    if let Err(err) = append_entry(storage, &entry).await {
+        tracing::error!("Could not write audit log entry {:?}: {}", entry, err);
+    }
+}
+
+async fn append_entry<S: Storer>(storage: &S, entry: &AuditEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let _lock = storage.lock(AUDIT_FILE_NAME, true).await?;
+    storage.append_file(AUDIT_FILE_NAME, &line).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub project: Option<ProjectSlug>,
+    pub branch: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().is_none_or(|a| a == &entry.actor)
+            && self.action.as_ref().is_none_or(|a| a == &entry.action)
+            && self
+                .project
+                .as_ref()
+                .is_none_or(|p| entry.target.project.as_ref() == Some(p))
+            && self
+                .branch
+                .as_ref()
+                .is_none_or(|b| entry.target.branch.as_ref() == Some(b))
+            && self.outcome.is_none_or(|o| o == entry.outcome)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+    }
+}
+
+/// Returns matching entries, newest first
+pub async fn query<S: Storer>(storage: &S, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+    read_entries(storage, query, READ_CHUNK_SIZE).await
+}
+
+async fn read_entries<S: Storer>(
+    storage: &S,
+    query: &AuditQuery,
+    chunk_size: u64,
+) -> anyhow::Result<Vec<AuditEntry>> {
+    let _lock = storage.lock(AUDIT_FILE_NAME, false).await?;
+    if !storage.exists(AUDIT_FILE_NAME).await? {
+        return Ok(vec![]);
+    }
+
+    let limit = query.limit.unwrap_or(100);
+    let mut entries = vec![];
+
+    let mut end = storage.file_size(AUDIT_FILE_NAME).await?;
+    // Beginning of the line which is continued in the previously read chunk
+    let mut rest = vec![];
+
+    'chunks: while end > 0 && entries.len() < limit {
+        let start = end.saturating_sub(chunk_size);
+        let mut chunk = storage
+            .read_file_range(AUDIT_FILE_NAME, start, (end - start) as usize)
+            .await?;
+        chunk.append(&mut rest);
+        end = start;
+
+        // The first line could start in the preceding chunk
+        let lines_start = match chunk.iter().position(|byte| *byte == b'\n') {
+            _ if start == 0 => 0,
+            Some(position) => position + 1,
+            None => {
+                rest = chunk;
+                continue;
+            }
+        };
+        rest = chunk[..lines_start].to_vec();
+
+        // Partially written last line after a crash is skipped
+        for line in chunk[lines_start..].split(|byte| *byte == b'\n').rev() {
+            let Ok(entry) = serde_json::from_slice::<AuditEntry>(line) else {
+                continue;
+            };
+            // Entries are appended in time order, so the rest ones are older
+            if query.since.is_some_and(|since| entry.timestamp < since) {
+                break 'chunks;
+            }
+            if query.matches(&entry) {
+                entries.push(entry);
+                if entries.len() == limit {
+                    break 'chunks;
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::{query, read_entries, record, AuditOutcome, AuditQuery, AuditTarget};
+    use crate::models::ProjectSlug;
+    use crate::storage::{Compression, LocalStorage};
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        let location = std::env::temp_dir().join(format!("sd-audit-{}", std::process::id()));
+        let storage = LocalStorage::new(&location, Compression::Gzip);
+
+        let payments = ProjectSlug::new("payments".to_owned());
+
+        let ok: Result<(), String> = Ok(());
+        let failed: Result<(), String> = Err("Branch main could not be deleted".to_owned());
+
+        let target = AuditTarget::branch(&payments, "feature");
+        record(
+            &storage,
+            "token:ci".to_owned(),
+            "branch.create",
+            target,
+            &ok,
+        )
+        .await;
+        let target = AuditTarget::branch(&payments, "main");
+        record(
+            &storage,
+            "basic".to_owned(),
+            "branch.delete",
+            target,
+            &failed,
+        )
+        .await;
+        let target = AuditTarget::branch(&payments, "feature");
+        record(
+            &storage,
+            "jwt:alice".to_owned(),
+            "branch.delete",
+            target,
+            &ok,
+        )
+        .await;
+
+        let entries = query(
+            &storage,
+            &AuditQuery {
+                action: Some("branch.delete".to_owned()),
+                project: Some(payments.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].actor, "jwt:alice");
+        assert_eq!(entries[0].target.branch.as_deref(), Some("feature"));
+        assert_eq!(entries[1].outcome, AuditOutcome::Failure);
+        assert_eq!(
+            entries[1].error.as_deref(),
+            Some("Branch main could not be deleted")
+        );
+
+        let entries = query(
+            &storage,
+            &AuditQuery {
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(entries[0].action, "branch.delete");
+
+        // Entries split between chunks are read as well
+        let all = query(&storage, &AuditQuery::default()).await.unwrap();
+        let chunked = read_entries(&storage, &AuditQuery::default(), 16)
+            .await
+            .unwrap();
+        assert_eq!(chunked.len(), 3);
+        let actors = |entries: &[crate::audit::AuditEntry]| {
+            entries.iter().map(|e| e.actor.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(actors(&chunked), actors(&all));
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
@@ -61,14 +61,17 @@ where
         name: branch_name.to_string(),
     };
 
-    project.branches.push(branch);
-
-    let branch = project
-        .branches
-        .last()
-        .expect("Just inserted one to branches");
+    project
+        .update_branches(&state.storage, |branches| {
+            // Could be created by another process sharing the persistence
+            if !branches.iter().any(|b| b.name == branch.name) {
+                branches.push(branch);
+            }
+            Ok(())
+        })
+        .await?;
 
-    project.persist_branches(&state.storage).await?;
+    let branch = get_branch(project, branch_name).expect("Just inserted one to branches");
 
     Ok(Some(branch))
 }
@@ -85,6 +88,9 @@ pub async fn delete_branch(
         bail!("Project {} not found", project_slug);
     };
 
+    // Branches removed with forks are removed from the persisted ones at the end
+    let branches_before: Vec<_> = project.branches.iter().map(|b| b.name.clone()).collect();
+
     let forks: Vec<_> = project
         .branches
         .iter()
@@ -96,7 +102,7 @@ pub async fn delete_branch(
         })
         .collect();
 
-    println!(
+    tracing::debug!(
         "Forks {} of {}/{} with force={}",
         forks.len(),
         project_slug,
@@ -152,7 +158,16 @@ pub async fn delete_branch(
     }
 
     if persist {
-        project.persist_branches(&state.storage).await?;
+        let removed: Vec<_> = branches_before
+            .into_iter()
+            .filter(|name| get_branch(project, name).is_none())
+            .collect();
+        project
+            .update_branches(&state.storage, |branches| {
+                branches.retain(|b| !removed.contains(&b.name));
+                Ok(())
+            })
+            .await?;
     }
 
     Ok(())
@@ -0,0 +1,398 @@
+//! Compatibility matrix of the project versions: whether a client built against
+//! one version keeps working with another one
+
+use indexmap::IndexMap;
+use schemadoc_diff::checker::validate;
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::app_state::AppState;
+use crate::models::{DependencyUsage, Project, ProjectSlug, Version};
+use crate::{branches, usage, versions};
+
+/// The least recently used entries are evicted once the limit is reached
+const CACHE_CAPACITY: usize = 10_000;
+
+/// Matrix of `limit` versions takes `limit²` diffs, so the number of versions is bounded
+pub const MAX_MATRIX_VERSIONS: usize = 50;
+
+/// Version files are content addressed, so cached results never get stale
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct CacheKey {
+    src_file_path: String,
+    tgt_file_path: String,
+    usage: Option<String>,
+    /// Project rules, which could change with the configuration
+    rules: Vec<String>,
+}
+
+/// Compatibility of version pairs, entries are ordered from the least recently used
+#[derive(Debug, Default)]
+pub struct CompatibilityCache(Mutex<IndexMap<CacheKey, bool>>);
+
+impl CompatibilityCache {
+    fn get(&self, key: &CacheKey) -> Option<bool> {
+        let mut cache = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let (key, compatible) = cache.shift_remove_entry(key)?;
+        cache.insert(key, compatible);
+        Some(compatible)
+    }
+
+    fn set(&self, key: CacheKey, compatible: bool) {
+        let mut cache = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        if cache.len() >= CACHE_CAPACITY {
+            cache.shift_remove_index(0);
+        }
+        cache.insert(key, compatible);
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CompatibilityError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Project {client} does not depend on {project}/{branch}")]
+    NotDependent {
+        client: ProjectSlug,
+        project: ProjectSlug,
+        branch: String,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatibilityRow {
+    /// Version the client is built against
+    pub version: u32,
+    /// Compatibility with each of the matrix versions
+    pub compatible: Vec<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientCompatibility {
+    pub project: ProjectSlug,
+    /// Pinned version of the dependency
+    pub version: u32,
+    pub compatible: Vec<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatibilityMatrix {
+    pub project: ProjectSlug,
+    pub branch: String,
+    pub versions: Vec<u32>,
+    pub rows: Vec<CompatibilityRow>,
+    pub clients: Vec<ClientCompatibility>,
+}
+
+struct Client<'s> {
+    project: &'s Project,
+    usage: Option<&'s DependencyUsage>,
+}
+
+impl Client<'_> {
+    /// Usage with the consumer schema it is resolved from
+    fn cache_key(&self) -> Option<String> {
+        let usage = self.usage?;
+        let spec = usage
+            .spec
+            .then(|| {
+                branches::get_branch(self.project, &self.project.default_branch)
+                    .and_then(|branch| branch.versions.last())
+                    .map(|version| version.file_path.as_str())
+            })
+            .flatten();
+        Some(format!(
+            "{}:{}",
+            serde_json::to_string(usage).unwrap_or_default(),
+            spec.unwrap_or_default()
+        ))
+    }
+}
+
+async fn is_compatible(
+    state: &AppState,
+    project: &Project,
+    branch_name: &str,
+    src_version: &Version,
+    tgt_version: &Version,
+    client: Option<&Client<'_>>,
+) -> anyhow::Result<bool> {
+    if src_version.file_path == tgt_version.file_path {
+        return Ok(true);
+    }
+
+    let key = CacheKey {
+        src_file_path: src_version.file_path.clone(),
+        tgt_file_path: tgt_version.file_path.clone(),
+        usage: client.and_then(|client| client.cache_key()),
+        rules: project.rules.clone(),
+    };
+    if let Some(compatible) = state.compatibility_cache.get(&key) {
+        return Ok(compatible);
+    }
+
+    let diff = versions::services::compare_versions(
+        state,
+        &project.slug,
+        branch_name,
+        src_version.id,
+        branch_name,
+        tgt_version.id,
+    )
+    .await?;
+
+    let compatible = match diff.get() {
+        None => true,
+        Some(diff) => {
+            let used = match client.and_then(|c| c.usage.map(|usage| (c.project, usage))) {
+                Some((consumer, usage)) => {
+                    Some(usage::used_operations(&state.storage, consumer, usage, diff).await?)
+                }
+                None => None,
+            };
+
+            let mut issues = validate(diff, &project.checkers());
+            if let Some(used) = &used {
+                issues.retain(|issue| used.contains(issue));
+            }
+            issues.is_empty()
+        }
+    };
+
+    state.compatibility_cache.set(key, compatible);
+
+    Ok(compatible)
+}
+
+/// Computes compatibility of the last `limit` branch versions with each other
+/// and of the dependent projects pinned versions, filtered by their usage
+pub async fn get_compatibility_matrix(
+    state: &AppState,
+    project_slug: &ProjectSlug,
+    branch_name: Option<&str>,
+    client_slug: Option<&ProjectSlug>,
+    limit: usize,
+) -> Result<CompatibilityMatrix, CompatibilityError> {
+    let Some(project) = state.projects.get(project_slug) else {
+        return Err(CompatibilityError::NotFound(format!(
+            "Project {project_slug} not found"
+        )));
+    };
+
+    let branch_name = branch_name.unwrap_or(&project.default_branch);
+
+    let Some(branch) = branches::get_branch(project, branch_name) else {
+        return Err(CompatibilityError::NotFound(format!(
+            "Project branch {project_slug}/{branch_name} not found"
+        )));
+    };
+
+    if let Some(slug) = client_slug.filter(|slug| !state.projects.contains_key(*slug)) {
+        return Err(CompatibilityError::NotFound(format!(
+            "Project {slug} not found"
+        )));
+    }
+
+    let dependents: Vec<_> = state
+        .projects
+        .values()
+        .filter(|p| client_slug.is_none_or(|slug| &p.slug == slug))
+        .flat_map(|p| {
+            p.dependencies
+                .iter()
+                .filter(|d| &d.project == project_slug && d.branch == branch_name)
+                .map(move |d| (p, d))
+        })
+        .collect();
+
+    let client = match client_slug {
+        Some(slug) => match dependents.first() {
+            Some((project, dependency)) => Some(Client {
+                project,
+                usage: dependency.usage.as_ref(),
+            }),
+            None => {
+                return Err(CompatibilityError::NotDependent {
+                    client: slug.clone(),
+                    project: project_slug.clone(),
+                    branch: branch_name.to_owned(),
+                })
+            }
+        },
+        None => None,
+    };
+
+    let matrix_versions = &branch.versions[branch.versions.len().saturating_sub(limit)..];
+
+    let mut rows = vec![];
+    for src_version in matrix_versions {
+        let mut compatible = vec![];
+        for tgt_version in matrix_versions {
+            compatible.push(
+                is_compatible(
+                    state,
+                    project,
+                    branch_name,
+                    src_version,
+                    tgt_version,
+                    client.as_ref(),
+                )
+                .await?,
+            );
+        }
+        rows.push(CompatibilityRow {
+            version: src_version.id,
+            compatible,
+        });
+    }
+
+    let mut clients = vec![];
+    for (dependent, dependency) in dependents {
+        let src_version = dependency
+            .version
+            .and_then(|id| branch.versions.iter().find(|v| v.id == id));
+        let Some(src_version) = src_version else {
+            continue;
+        };
+
+        let client = Client {
+            project: dependent,
+            usage: dependency.usage.as_ref(),
+        };
+
+        let mut compatible = vec![];
+        for tgt_version in matrix_versions {
+            compatible.push(
+                is_compatible(
+                    state,
+                    project,
+                    branch_name,
+                    src_version,
+                    tgt_version,
+                    Some(&client),
+                )
+                .await?,
+            );
+        }
+        clients.push(ClientCompatibility {
+            project: dependent.slug.clone(),
+            version: src_version.id,
+            compatible,
+        });
+    }
+
+    Ok(CompatibilityMatrix {
+        project: project_slug.clone(),
+        branch: branch_name.to_owned(),
+        versions: matrix_versions.iter().map(|v| v.id).collect(),
+        rows,
+        clients,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::compatibility::{get_compatibility_matrix, CompatibilityError};
+    use crate::models::ProjectSlug;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::versions::services::create_version;
+
+    const CONFIG: &str = r#"
+version: "0.1"
+projects:
+  payments:
+    name: Payments
+"#;
+
+    fn schema(version: &str, paths: &[&str]) -> String {
+        let paths = paths
+            .iter()
+            .map(|path| format!(r#""{path}": {{"get": {{"responses": {{}}}}}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{"openapi": "3.0.0", "info": {{"title": "Payments", "version": "{version}"}},
+                "paths": {{{paths}}}}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn test_compatibility_matrix() {
+        let location = std::env::temp_dir().join(format!("sd-compat-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage.clone(), None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+        let payments = ProjectSlug::new("payments".to_owned());
+        let orders = ProjectSlug::new("orders".to_owned());
+
+        // Adding an operation is not breaking, removing one is
+        for content in [
+            schema("1", &["/users", "/orders"]),
+            schema("2", &["/users", "/orders", "/items"]),
+            schema("3", &["/users", "/items"]),
+        ] {
+            create_version(&settings, &state, &payments, "main", None, &content)
+                .await
+                .unwrap();
+        }
+
+        // Client pinned to the first version uses only `/users`
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            format!(
+                "{CONFIG}  orders:\n    name: Orders\n    dependencies:\n      \
+                 payments: {{ version: 0, usage: {{ operations: [ GET /users ] }} }}\n"
+            ),
+        )
+        .unwrap();
+        let state = AppState::read(storage, None).await.unwrap();
+
+        let matrix = get_compatibility_matrix(&state, &payments, None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(matrix.versions, vec![0, 1, 2]);
+        let rows: Vec<_> = matrix.rows.iter().map(|r| r.compatible.clone()).collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![true, true, false],
+                vec![false, true, false],
+                vec![false, true, true]
+            ]
+        );
+        assert_eq!(matrix.clients.len(), 1);
+        assert_eq!(matrix.clients[0].version, 0);
+        assert_eq!(matrix.clients[0].compatible, vec![true, true, true]);
+
+        // Rows are filtered by the client usage as well
+        let matrix = get_compatibility_matrix(&state, &payments, None, Some(&orders), 2)
+            .await
+            .unwrap();
+        assert_eq!(matrix.versions, vec![1, 2]);
+        let rows: Vec<_> = matrix.rows.iter().map(|r| r.compatible.clone()).collect();
+        assert_eq!(rows, vec![vec![true, true], vec![true, true]]);
+
+        let not_found = get_compatibility_matrix(&state, &payments, Some("dev"), None, 10).await;
+        assert!(matches!(not_found, Err(CompatibilityError::NotFound(_))));
+        let not_dependent =
+            get_compatibility_matrix(&state, &payments, None, Some(&payments), 10).await;
+        assert!(matches!(
+            not_dependent,
+            Err(CompatibilityError::NotDependent { .. })
+        ));
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
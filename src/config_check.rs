@@ -0,0 +1,467 @@
+use indexmap::IndexMap;
+use schemadoc_diff::checker::checker_ids;
+use semver::VersionReq;
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::alerts::validate_service_config;
+use crate::app_state::{AlertConfig, GlobalAlertConfig, ProjectConfig, ProjectDependencyConfig};
+use crate::config_file::{find_line, format_path, PathSegment};
+use crate::constants;
+use crate::models::ProjectSlug;
+use crate::projects::validate_slug;
+use crate::scheduler;
+use crate::secrets::Interpolated;
+use crate::usage::validate_operation_pattern;
+
+#[derive(Debug)]
+pub struct ConfigIssue {
+    pub path: String,
+    /// 1-based line in `schemadoc.yaml`
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) if self.path.is_empty() => write!(f, "line {}: {}", line, self.message),
+            Some(line) => write!(f, "line {}: {}: {}", line, self.path, self.message),
+            None if self.path.is_empty() => write!(f, "{}", self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+struct Checker<'c> {
+    content: &'c str,
+    issues: Vec<ConfigIssue>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, path: Vec<PathSegment>, message: impl Into<String>) {
+        self.issues.push(ConfigIssue {
+            line: find_line(self.content, &path),
+            path: format_path(&path),
+            message: message.into(),
+        });
+    }
+}
+
+fn key(key: &str) -> PathSegment {
+    PathSegment::Key(key.to_owned())
+}
+
+fn error_path(base: &[PathSegment], path: &serde_path_to_error::Path) -> Vec<PathSegment> {
+    let mut result = base.to_vec();
+    for segment in path.iter() {
+        match segment {
+            serde_path_to_error::Segment::Seq { index } => result.push(PathSegment::Index(*index)),
+            serde_path_to_error::Segment::Map { key } => result.push(PathSegment::Key(key.clone())),
+            // Enum variants are YAML tags, not separate nodes
+            serde_path_to_error::Segment::Enum { .. } => {}
+            serde_path_to_error::Segment::Unknown => break,
+        }
+    }
+    result
+}
+
+/// Validates `schemadoc.yaml` content and returns all found problems
+pub fn check_config(content: &str) -> Vec<ConfigIssue> {
+    let mut checker = Checker {
+        content,
+        issues: vec![],
+    };
+
+    let file: Value = match serde_yaml::from_str(content) {
+        Ok(file) => file,
+        Err(err) => {
+            checker.issues.push(ConfigIssue {
+                path: String::new(),
+                line: err.location().map(|l| l.line()),
+                message: err.to_string(),
+            });
+            return checker.issues;
+        }
+    };
+
+    if let Some(alerts) = file.get("alerts") {
+        let path = vec![key("alerts")];
+        match serde_path_to_error::deserialize::<_, Vec<GlobalAlertConfig>>(alerts.clone()) {
+            Ok(alerts) => {
+                for (idx, alert) in alerts.iter().enumerate() {
+                    let mut path = path.clone();
+                    path.push(PathSegment::Index(idx));
+                    check_global_alert(&mut checker, path, alert);
+                }
+            }
+            Err(err) => {
+                let path = error_path(&path, err.path());
+                checker.report(path, err.into_inner().to_string());
+            }
+        }
+    }
+
+    let projects = match file.get("projects") {
+        None | Some(Value::Null) => return checker.issues,
+        Some(Value::Mapping(projects)) => projects,
+        Some(_) => {
+            checker.report(vec![key("projects")], "Projects must be a mapping");
+            return checker.issues;
+        }
+    };
+
+    let mut configs = IndexMap::new();
+
+    for (slug, value) in projects {
+        let Value::String(slug) = slug else {
+            checker.report(
+                vec![key("projects")],
+                format!("Invalid project slug {slug:?}"),
+            );
+            continue;
+        };
+
+        let path = vec![key("projects"), key(slug)];
+
+        let slug = ProjectSlug::new(slug.clone());
+        if let Err(err) = validate_slug(&slug) {
+            checker.report(path.clone(), err.to_string());
+        }
+
+        match serde_path_to_error::deserialize::<_, ProjectConfig>(value.clone()) {
+            Ok(config) => {
+                configs.insert(slug, (path, config));
+            }
+            Err(err) => {
+                let path = error_path(&path, err.path());
+                checker.report(path, err.into_inner().to_string());
+            }
+        }
+    }
+
+    let known_projects = projects
+        .keys()
+        .filter_map(|slug| slug.as_str())
+        .collect::<HashSet<_>>();
+
+    for (slug, (path, config)) in &configs {
+        check_project(&mut checker, path, slug, config, &known_projects);
+    }
+
+    checker.issues
+}
+
+/// Validates single project configuration, issues have no lines
+pub fn check_project_config(
+    slug: &ProjectSlug,
+    config: &ProjectConfig,
+    known_projects: &HashSet<&str>,
+) -> Vec<ConfigIssue> {
+    let mut checker = Checker {
+        content: "",
+        issues: vec![],
+    };
+
+    check_project(&mut checker, &[], slug, config, known_projects);
+
+    checker.issues
+}
+
+fn check_project(
+    checker: &mut Checker,
+    path: &[PathSegment],
+    slug: &ProjectSlug,
+    config: &ProjectConfig,
+    known_projects: &HashSet<&str>,
+) {
+    check_dependencies(checker, path, slug, config, known_projects);
+    check_alerts(checker, path, config);
+    check_rules(checker, path, config);
+    check_data_sources(checker, path, config);
+}
+
+fn check_dependencies(
+    checker: &mut Checker,
+    path: &[PathSegment],
+    slug: &ProjectSlug,
+    config: &ProjectConfig,
+    known_projects: &HashSet<&str>,
+) {
+    let Some(dependencies) = &config.dependencies else {
+        return;
+    };
+
+    for (dependency, def) in dependencies {
+        let mut path = path.to_vec();
+        path.extend([key("dependencies"), key(dependency.as_str())]);
+
+        if dependency == slug {
+            checker.report(path.clone(), "Project could not depend on itself");
+        } else if !known_projects.contains(dependency.as_str()) {
+            checker.report(
+                path.clone(),
+                format!("Dependency project `{}` not found", dependency),
+            );
+        }
+
+        let Some(config) = ProjectDependencyConfig::from_value(def) else {
+            checker.report(
+                path,
+                "Dependency must be a version or a mapping with `version`, `branch` \
+                 and `usage` fields",
+            );
+            continue;
+        };
+
+        let operations = config.usage.iter().flat_map(|usage| &usage.operations);
+        for (idx, pattern) in operations.enumerate() {
+            if let Err(err) = validate_operation_pattern(pattern) {
+                let mut path = path.clone();
+                path.extend([key("usage"), key("operations"), PathSegment::Index(idx)]);
+                checker.report(path, err.to_string());
+            }
+        }
+
+        if def.is_mapping() {
+            path.push(key("version"));
+        }
+
+        let version = config.version;
+        if version.parse::<u32>().is_err() && VersionReq::parse(&version).is_err() {
+            checker.report(
+                path,
+                format!("Version `{version}` must be a version id or a semver range"),
+            );
+        }
+    }
+}
+
+fn check_alerts(checker: &mut Checker, path: &[PathSegment], config: &ProjectConfig) {
+    let Some(alerts) = &config.alerts else {
+        return;
+    };
+
+    for (idx, alert) in alerts.iter().enumerate() {
+        let mut path = path.to_vec();
+        path.extend([key("alerts"), PathSegment::Index(idx)]);
+        check_alert(checker, path, alert);
+    }
+}
+
+fn check_global_alert(checker: &mut Checker, path: Vec<PathSegment>, alert: &GlobalAlertConfig) {
+    check_alert(checker, path.clone(), &alert.alert);
+
+    let routes = alert.routes.as_ref();
+    if alert.route_by.is_some() != routes.is_some() {
+        let field = if routes.is_some() {
+            "routes"
+        } else {
+            "route_by"
+        };
+        let mut path = path.clone();
+        path.push(key(field));
+        checker.report(path, "`route_by` and `routes` must be set together");
+    }
+
+    for (value, service_config) in routes.into_iter().flatten() {
+        let mut path = path.clone();
+        path.extend([key("routes"), key(value)]);
+        check_service_config(checker, path, &alert.alert.service, service_config);
+    }
+}
+
+fn check_alert(checker: &mut Checker, mut path: Vec<PathSegment>, alert: &AlertConfig) {
+    let is_service_known = matches!(alert.service.as_str(), "GoogleChats" | "Slack");
+    if !is_service_known {
+        path.push(key("service"));
+        checker.report(
+            path,
+            format!("{} service: Invalid alert service provided", alert.service),
+        );
+        return;
+    }
+
+    path.push(key("service_config"));
+    check_service_config(checker, path, &alert.service, &alert.service_config);
+}
+
+fn check_service_config(
+    checker: &mut Checker,
+    path: Vec<PathSegment>,
+    service: &str,
+    service_config: &Value,
+) {
+    let service_config = match Interpolated::resolve(service_config.clone()) {
+        Ok(service_config) => service_config.value,
+        Err(err) => {
+            checker.report(path, err.to_string());
+            return;
+        }
+    };
+
+    if let Err(err) = validate_service_config(service, &service_config) {
+        checker.report(path, format!("{} service: {}", service, err));
+    }
+}
+
+fn check_rules(checker: &mut Checker, path: &[PathSegment], config: &ProjectConfig) {
+    let Some(rules) = &config.rules else {
+        return;
+    };
+
+    let available = checker_ids();
+
+    for (idx, rule) in rules.iter().enumerate() {
+        if rule != "*" && !available.contains(&rule.as_str()) {
+            let mut path = path.to_vec();
+            path.extend([key("rules"), PathSegment::Index(idx)]);
+            checker.report(
+                path,
+                format!(
+                    "Unknown rule `{rule}`, available rules: {}",
+                    available.join(", ")
+                ),
+            );
+        }
+    }
+}
+
+fn check_data_sources(checker: &mut Checker, path: &[PathSegment], config: &ProjectConfig) {
+    let Some(data_sources) = &config.data_sources else {
+        return;
+    };
+
+    let default_branch = config
+        .default_branch
+        .as_deref()
+        .unwrap_or(constants::BRANCH_DEFAULT_NAME);
+
+    let mut branches = HashMap::new();
+
+    for (idx, data_source) in data_sources.iter().enumerate() {
+        if let Err(err) = Interpolated::resolve(data_source.source.clone()) {
+            let mut path = path.to_vec();
+            path.extend([key("data_sources"), PathSegment::Index(idx), key("source")]);
+            checker.report(path, err.to_string());
+        }
+
+        if let Some(cron) = &data_source.pull_cron {
+            let mut path = path.to_vec();
+            path.extend([
+                key("data_sources"),
+                PathSegment::Index(idx),
+                key("pull_cron"),
+            ]);
+            if data_source.pull_interval_minutes.is_some() {
+                checker.report(
+                    path.clone(),
+                    "Either `pull_cron` or `pull_interval_minutes` could be set",
+                );
+            }
+            if let Err(err) = scheduler::parse_cron(cron) {
+                checker.report(path, err.to_string());
+            }
+        }
+
+        let branch = data_source.branch.as_deref().unwrap_or(default_branch);
+
+        if let Some(other) = branches.insert(branch, idx) {
+            let mut path = path.to_vec();
+            path.extend([key("data_sources"), PathSegment::Index(idx)]);
+            if data_source.branch.is_some() {
+                path.push(key("branch"));
+            }
+            checker.report(
+                path,
+                format!("Branch `{branch}` is already pulled by data_sources[{other}]"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config_check::check_config;
+
+    #[test]
+    fn test_check_config() {
+        let content = r#"version: "0.1"
+alerts:
+  - name: Breaking
+    selector: { team: core }
+    kind: breaking
+    source: own
+    is_active: true
+    service: Teams
+    service_config: {}
+projects:
+  payments:
+    name: Payments
+    rules: [removed-operation, removed-everything]
+    alerts:
+      - name: Slack
+        kind: breaking
+        source: own
+        is_active: true
+        service: Slack
+        service_config:
+          url: https://hooks
+      - name: Chats
+        kind: breaking
+        source: own
+        is_active: true
+        service: GoogleChats
+        service_config:
+          url: ${SD_TEST_CHECK_MISSING}
+    data_sources:
+      - name: Main
+        source: !Url { url: https://payments/openapi.json }
+      - name: Main again
+        branch: main
+        source: !Url { url: https://payments/openapi.json }
+    dependencies:
+      orders: 1
+      users: latest
+  users:
+    name: Users
+    data_sources:
+      - name: Git
+        source: !Git { repo: https://git }
+"#;
+
+        let issues: Vec<_> = check_config(content)
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+
+        assert_eq!(
+            issues,
+            vec![
+                "line 8: alerts[0].service: Teams service: Invalid alert service provided",
+                "line 42: projects.users.data_sources[0].source: \
+                 unknown variant `Git`, expected `Url`",
+                "line 36: projects.payments.dependencies.orders: \
+                 Dependency project `orders` not found",
+                "line 37: projects.payments.dependencies.users: \
+                 Version `latest` must be a version id or a semver range",
+                "line 20: projects.payments.alerts[0].service_config: \
+                 Slack service: Field 'hook' must be provided.",
+                "line 27: projects.payments.alerts[1].service_config: \
+                 Environment variable SD_TEST_CHECK_MISSING is not set",
+                "line 13: projects.payments.rules[1]: Unknown rule `removed-everything`, \
+                 available rules: removed-operation, removed-media-type, updated-schema-type, \
+                 added-required-parameter, removed-response-property, \
+                 removed-schema-enum-value, added-required-request-body, \
+                 added-required-body-property",
+                "line 33: projects.payments.data_sources[1].branch: \
+                 Branch `main` is already pulled by data_sources[0]",
+            ]
+        );
+
+        let issues = check_config("version: '0.1'\nprojects:\n\tpayments: {}\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(3));
+    }
+}
@@ -0,0 +1,364 @@
+//! Line based helpers over `schemadoc.yaml` content.
+//! Projects are edited in place, only lines of the edited project are touched,
+//! so comments and ordering of other projects are kept as they are.
+
+use regex::Regex;
+use serde::Serialize;
+use std::ops::Range;
+
+const DEFAULT_INDENT: usize = 2;
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// Key of block mapping entry line
+fn key_of(line: &str) -> Option<String> {
+    let key_re =
+        Regex::new(r#"^\s*(?:"([^"]+)"|'([^']+)'|([^\s"'#:][^#:]*?))\s*:(?:\s|$)"#).unwrap();
+
+    let captures = key_re.captures(line)?;
+    let key = captures.get(1).or(captures.get(2)).or(captures.get(3))?;
+    Some(key.as_str().to_owned())
+}
+
+struct ProjectsSection {
+    /// Lines of the section after `projects:` line
+    lines: Range<usize>,
+    indent: Option<usize>,
+    /// Project slug and lines of its definition
+    blocks: Vec<(String, Range<usize>)>,
+}
+
+fn parse_projects_section(lines: &[&str]) -> Option<ProjectsSection> {
+    let header_re = Regex::new(r"^projects:\s*(#.*)?$").unwrap();
+
+    let header = lines.iter().position(|line| header_re.is_match(line))?;
+
+    let start = header + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|line| !is_blank_or_comment(line) && indent_of(line) == 0)
+        .map(|pos| start + pos)
+        .unwrap_or(lines.len());
+
+    let indent = lines[start..end]
+        .iter()
+        .find(|line| !is_blank_or_comment(line))
+        .map(|line| indent_of(line));
+
+    let Some(indent) = indent else {
+        return Some(ProjectsSection {
+            lines: start..end,
+            indent: None,
+            blocks: vec![],
+        });
+    };
+
+    let mut keys = Vec::new();
+    for (idx, line) in lines.iter().enumerate().take(end).skip(start) {
+        if is_blank_or_comment(line) || indent_of(line) != indent {
+            continue;
+        }
+
+        keys.push((key_of(line)?, idx));
+    }
+
+    let blocks = keys
+        .iter()
+        .enumerate()
+        .map(|(pos, (key, block_start))| {
+            let mut block_end = keys.get(pos + 1).map(|(_, idx)| *idx).unwrap_or(end);
+            // Comments and blank lines before the next project belong to it
+            while block_end > block_start + 1 && is_blank_or_comment(lines[block_end - 1]) {
+                block_end -= 1;
+            }
+            (key.clone(), *block_start..block_end)
+        })
+        .collect();
+
+    Some(ProjectsSection {
+        lines: start..end,
+        indent: Some(indent),
+        blocks,
+    })
+}
+
+fn render_project<T: Serialize>(
+    slug: &str,
+    config: &T,
+    indent: usize,
+) -> serde_yaml::Result<String> {
+    let mut mapping = serde_yaml::Mapping::new();
+    mapping.insert(slug.into(), serde_yaml::to_value(config)?);
+
+    let content = serde_yaml::to_string(&mapping)?;
+
+    let prefix = " ".repeat(indent);
+    Ok(content
+        .lines()
+        .map(|line| format!("{prefix}{line}"))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Replaces project definition or appends it to the end of `projects` section.
+/// Returns `None` if `projects` section has unsupported format.
+pub fn upsert_project<T: Serialize>(content: &str, slug: &str, config: &T) -> Option<String> {
+    let lines: Vec<_> = content.lines().collect();
+
+    let section = parse_projects_section(&lines)?;
+
+    let indent = section.indent.unwrap_or(DEFAULT_INDENT);
+    let rendered = render_project(slug, config, indent).ok()?;
+
+    let (range, replacement) = match section.blocks.iter().find(|(key, _)| key == slug) {
+        Some((_, range)) => (range.clone(), rendered),
+        None => {
+            let position = section
+                .blocks
+                .last()
+                .map(|(_, range)| range.end)
+                .unwrap_or(section.lines.start);
+            (position..position, rendered)
+        }
+    };
+
+    let mut result: Vec<&str> = Vec::with_capacity(lines.len());
+    result.extend(&lines[..range.start]);
+    result.extend(replacement.lines());
+    result.extend(&lines[range.end..]);
+
+    Some(result.join("\n") + "\n")
+}
+
+/// Removes project definition from `projects` section.
+/// Returns `None` if `projects` section has unsupported format.
+pub fn remove_project(content: &str, slug: &str) -> Option<String> {
+    let lines: Vec<_> = content.lines().collect();
+
+    let section = parse_projects_section(&lines)?;
+
+    let Some((_, range)) = section.blocks.iter().find(|(key, _)| key == slug) else {
+        return Some(content.to_owned());
+    };
+
+    // Comments right above the project describe it
+    let mut start = range.start;
+    while start > section.lines.start && lines[start - 1].trim_start().starts_with('#') {
+        start -= 1;
+    }
+
+    let mut end = range.end;
+    while end < section.lines.end && lines[end].trim().is_empty() {
+        end += 1;
+    }
+
+    let mut result: Vec<&str> = Vec::with_capacity(lines.len());
+    result.extend(&lines[..start]);
+    result.extend(&lines[end..]);
+
+    Some(result.join("\n") + "\n")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+pub fn format_path(path: &[PathSegment]) -> String {
+    let mut result = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) if result.is_empty() => result.push_str(key),
+            PathSegment::Key(key) => {
+                result.push('.');
+                result.push_str(key);
+            }
+            PathSegment::Index(idx) => result.push_str(&format!("[{idx}]")),
+        }
+    }
+    result
+}
+
+/// Finds 1-based line number of the value by its path. Only block style collections
+/// are looked into, for flow style ones the line of the closest found parent is returned.
+pub fn find_line(content: &str, path: &[PathSegment]) -> Option<usize> {
+    let mut lines: Vec<String> = content.lines().map(|line| line.to_owned()).collect();
+
+    let mut scope = 0..lines.len();
+    let mut found = None;
+
+    for segment in path {
+        let children: Vec<_> = scope
+            .clone()
+            .filter(|idx| !is_blank_or_comment(&lines[*idx]))
+            .collect();
+
+        let Some(indent) = children.first().map(|idx| indent_of(&lines[*idx])) else {
+            break;
+        };
+
+        let siblings: Vec<_> = children
+            .into_iter()
+            .filter(|idx| indent_of(&lines[*idx]) == indent)
+            .collect();
+
+        let position = match segment {
+            PathSegment::Key(key) => siblings
+                .iter()
+                .position(|idx| key_of(&lines[*idx]).as_ref() == Some(key)),
+            PathSegment::Index(idx) => {
+                let items: Vec<_> = siblings
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, line_idx)| lines[**line_idx].trim_start().starts_with('-'))
+                    .map(|(pos, _)| pos)
+                    .collect();
+                items.get(*idx).copied()
+            }
+        };
+
+        let Some(position) = position else {
+            break;
+        };
+
+        let line_idx = siblings[position];
+        let end = siblings.get(position + 1).copied().unwrap_or(scope.end);
+
+        scope = match segment {
+            PathSegment::Key(_) => line_idx + 1..end,
+            PathSegment::Index(_) => {
+                // Item content is aligned with the text after dash
+                lines[line_idx] = lines[line_idx].replacen('-', " ", 1);
+                line_idx..end
+            }
+        };
+
+        found = Some(line_idx + 1);
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config_file::{find_line, remove_project, upsert_project, PathSegment};
+    use serde_yaml::Value;
+
+    const CONTENT: &str = r#"version: "0.1"
+
+projects:
+  # Payments API
+  payments:
+    name: Payments # inline comment
+    kind: server
+
+  # Orders API
+  "orders":
+    name: Orders
+"#;
+
+    #[test]
+    fn test_upsert_project() {
+        let config: Value = serde_yaml::from_str("name: Payments v2").unwrap();
+
+        let updated = upsert_project(CONTENT, "payments", &config).unwrap();
+        assert_eq!(
+            updated,
+            r#"version: "0.1"
+
+projects:
+  # Payments API
+  payments:
+    name: Payments v2
+
+  # Orders API
+  "orders":
+    name: Orders
+"#
+        );
+
+        let config: Value = serde_yaml::from_str("name: Users").unwrap();
+        let created = upsert_project(CONTENT, "users", &config).unwrap();
+        assert!(created.ends_with("    name: Orders\n  users:\n    name: Users\n"));
+
+        let created = upsert_project("version: '0.1'\nprojects:\n", "users", &config).unwrap();
+        assert_eq!(
+            created,
+            "version: '0.1'\nprojects:\n  users:\n    name: Users\n"
+        );
+
+        assert!(upsert_project("projects: {}", "users", &config).is_none());
+    }
+
+    #[test]
+    fn test_remove_project() {
+        let removed = remove_project(CONTENT, "payments").unwrap();
+        assert_eq!(
+            removed,
+            r#"version: "0.1"
+
+projects:
+  # Orders API
+  "orders":
+    name: Orders
+"#
+        );
+
+        let removed = remove_project(CONTENT, "orders").unwrap();
+        assert!(removed.ends_with("    kind: server\n\n"));
+    }
+
+    #[test]
+    fn test_find_line() {
+        let content = r#"version: "0.1"
+projects:
+  payments:
+    name: Payments
+    alerts:
+      - name: Slack
+        service_config:
+          hook: https://hooks
+      - name: Chats
+        service_config: {}
+    dependencies:
+      orders: 1
+"#;
+        let path = |segments: &[&str]| -> Vec<PathSegment> {
+            segments
+                .iter()
+                .map(|s| match s.parse() {
+                    Ok(idx) => PathSegment::Index(idx),
+                    Err(_) => PathSegment::Key(s.to_string()),
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            find_line(content, &path(&["projects", "payments"])),
+            Some(3)
+        );
+        let alert = path(&["projects", "payments", "alerts", "1", "service_config"]);
+        assert_eq!(find_line(content, &alert), Some(10));
+        let hook = path(&[
+            "projects",
+            "payments",
+            "alerts",
+            "0",
+            "service_config",
+            "hook",
+        ]);
+        assert_eq!(find_line(content, &hook), Some(8));
+        let dep = path(&["projects", "payments", "dependencies", "orders"]);
+        assert_eq!(find_line(content, &dep), Some(12));
+        let missing = path(&["projects", "payments", "links", "0"]);
+        assert_eq!(find_line(content, &missing), Some(3));
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::Utc;
+use notify::{RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, RwLock};
+
+use crate::app_state::{
+    load_config, validate_dependencies_branches, AppState, Config, ProjectConfig, CONFIG_FILE_NAME,
+};
+use crate::models::ProjectSlug;
+use crate::projects::update_project_dependencies;
+use crate::secrets;
+use crate::storage::Storer;
+use crate::versions::services::backfill_breaking_changes;
+
+/// Delay to let editors finish writing the file before reading it
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Default)]
+pub struct ReloadResult {
+    pub added: Vec<ProjectSlug>,
+    pub removed: Vec<ProjectSlug>,
+    pub updated: Vec<ProjectSlug>,
+    pub alerts_updated: bool,
+}
+
+impl ReloadResult {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.updated.is_empty()
+            && !self.alerts_updated
+    }
+}
+
+impl fmt::Display for ReloadResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |slugs: &[ProjectSlug]| {
+            slugs
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "added [{}], removed [{}], updated [{}]",
+            join(&self.added),
+            join(&self.removed),
+            join(&self.updated)
+        )?;
+
+        if self.alerts_updated {
+            write!(f, ", global alerts updated")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Re-reads `schemadoc.yaml` and applies only changed projects and global alerts to the state.
+/// The state is not changed if the configuration is invalid, only the reload error is kept.
+pub async fn reload_config(state: &mut AppState) -> anyhow::Result<ReloadResult> {
+    let result = apply_config(state).await;
+
+    match &result {
+        Ok(_) => {
+            state.config_loaded_at = Utc::now();
+            state.config_reload_error = None;
+        }
+        Err(err) => {
+            state.config_reload_error = Some(secrets::redact(&err.to_string()).into_owned())
+        }
+    }
+
+    result
+}
+
+async fn apply_config(state: &mut AppState) -> anyhow::Result<ReloadResult> {
+    let Config { projects, alerts } = load_config(state.get_config_storage()).await?;
+
+    let mut result = ReloadResult {
+        alerts_updated: state.alerts != alerts,
+        removed: state
+            .projects
+            .keys()
+            .filter(|slug| !projects.contains_key(*slug))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut changed = vec![];
+
+    for (slug, mut project) in projects {
+        // Compare normalized configs, so formatting changes are not treated as updates
+        let is_added = match state.projects.get(&slug) {
+            None => true,
+            Some(existing) if ProjectConfig::from(existing) != ProjectConfig::from(&project) => {
+                false
+            }
+            Some(_) => continue,
+        };
+
+        project.load_persistent_data(&state.storage).await?;
+        changed.push(project);
+
+        if is_added {
+            result.added.push(slug);
+        } else {
+            result.updated.push(slug);
+        }
+    }
+
+    let find_project = |slug: &ProjectSlug| {
+        changed.iter().find(|p| &p.slug == slug).or_else(|| {
+            state
+                .projects
+                .get(slug)
+                .filter(|_| !result.removed.contains(slug))
+        })
+    };
+    for slug in result.added.iter().chain(&result.updated) {
+        validate_dependencies_branches(find_project(slug).expect("Changed project"), find_project)?;
+    }
+    for project in state.projects.values() {
+        if !result.removed.contains(&project.slug) && !result.updated.contains(&project.slug) {
+            validate_dependencies_branches(project, find_project)?;
+        }
+    }
+
+    for slug in &result.removed {
+        state.projects.shift_remove(slug);
+    }
+
+    for project in changed {
+        state.projects.insert(project.slug.clone(), project);
+    }
+
+    state.alerts = alerts;
+
+    for slug in result.added.iter().chain(&result.updated) {
+        backfill_breaking_changes(state, slug).await?;
+        update_project_dependencies(state, slug).await?;
+    }
+
+    Ok(result)
+}
+
+async fn read_config_hash(state: &RwLock<AppState>) -> Option<Vec<u8>> {
+    let state = state.read().await;
+    let content = state
+        .get_config_storage()
+        .read_file(CONFIG_FILE_NAME)
+        .await
+        .ok()?;
+    Some(Sha256::digest(content).to_vec())
+}
+
+/// Watches `schemadoc.yaml` changes if the storage supports it
+/// and polls its hash every `interval` otherwise
+pub async fn watch_config(state: Arc<RwLock<AppState>>, interval: Duration) -> anyhow::Result<()> {
+    let (sender, mut receiver) = mpsc::channel::<()>(1);
+
+    let watch_path = state
+        .read()
+        .await
+        .get_config_storage()
+        .watch_path(CONFIG_FILE_NAME);
+
+    // Watch the folder since editors and atomic writes replace the file
+    let watcher = match watch_path {
+        Some(path) => {
+            let file_name = path.file_name().map(|name| name.to_owned());
+            let mut watcher =
+                notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    let Ok(event) = event else {
+                        return;
+                    };
+                    if event
+                        .paths
+                        .iter()
+                        .any(|p| p.file_name().map(|n| n.to_owned()) == file_name)
+                    {
+                        let _ = sender.try_send(());
+                    }
+                })?;
+
+            let folder = path.parent().unwrap_or(&path).to_owned();
+            watcher.watch(&folder, RecursiveMode::NonRecursive)?;
+            Some(watcher)
+        }
+        None => None,
+    };
+
+    let mut last_hash = read_config_hash(&state).await;
+
+    loop {
+        if watcher.is_some() {
+            receiver.recv().await;
+            tokio::time::sleep(DEBOUNCE_DELAY).await;
+            while receiver.try_recv().is_ok() {}
+        } else {
+            tokio::time::sleep(interval).await;
+        }
+
+        let hash = read_config_hash(&state).await;
+        if hash.is_none() || hash == last_hash {
+            continue;
+        }
+        last_hash = hash;
+
+        let mut state = state.write().await;
+        match reload_config(&mut state).await {
+            Ok(result) if result.is_empty() => {}
+            Ok(result) => tracing::info!("{CONFIG_FILE_NAME} reloaded: {result}"),
+            Err(err) => tracing::warn!(
+                "Could not reload {CONFIG_FILE_NAME}, current configuration is kept: {}",
+                secrets::redact(&err.to_string())
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::config_reload::reload_config;
+    use crate::models::ProjectSlug;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::versions::services::create_version;
+
+    const SCHEMA: &str = r#"{"openapi": "3.0.0", "info": {"title": "API", "version": "1"},
+        "paths": {"/users": {"get": {"responses": {}}}, "/orders": {"get": {"responses": {}}}}}"#;
+
+    #[tokio::test]
+    async fn test_reload_config() {
+        let location = std::env::temp_dir().join(format!("sd-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        let config = |projects: &str| {
+            std::fs::write(
+                location.join("schemadoc.yaml"),
+                format!("version: \"0.1\"\nprojects:\n{projects}"),
+            )
+            .unwrap()
+        };
+
+        config(
+            "  payments: { name: Payments }\n  billing: { name: Billing }\n  \
+             orders: { name: Orders, dependencies: { payments: 0 } }\n",
+        );
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage, None).await.unwrap());
+
+        let settings = Settings::from_env().unwrap();
+        let payments = ProjectSlug::new("payments".to_owned());
+        let billing = ProjectSlug::new("billing".to_owned());
+        let orders = ProjectSlug::new("orders".to_owned());
+        let versions = [
+            (&payments, SCHEMA.to_owned()),
+            (&payments, SCHEMA.replace(r#""/orders""#, r#""/items""#)),
+            (&billing, SCHEMA.to_owned()),
+        ];
+        for (slug, content) in versions {
+            create_version(&settings, &state, slug, "main", None, &content)
+                .await
+                .unwrap();
+        }
+        let mut state = state.into_inner();
+        let dependency = &state.projects[&orders].dependencies[0];
+        assert_eq!(dependency.outdated, Some(true));
+        assert_eq!(dependency.breaking, Some(true));
+
+        // Formatting changes are not updates
+        config(
+            "  payments:\n    name: Payments\n  billing: { name: Billing }\n  \
+             orders: { name: Orders, dependencies: { payments: 0 } }\n",
+        );
+        assert!(reload_config(&mut state).await.unwrap().is_empty());
+
+        // Projects used by dependents are not removed
+        config(
+            "  billing: { name: Billing }\n  \
+             orders: { name: Orders, dependencies: { payments: 0 } }\n",
+        );
+        assert!(reload_config(&mut state).await.is_err());
+        assert!(state.config_reload_error.is_some());
+        assert_eq!(state.projects.len(), 3);
+
+        // Dependents state is recomputed against the new dependency
+        config(
+            "  billing: { name: Billing }\n  \
+             orders: { name: Orders, dependencies: { billing: 0 } }\n  \
+             shipping: { name: Shipping }\n",
+        );
+        let result = reload_config(&mut state).await.unwrap();
+        assert_eq!(
+            result.to_string(),
+            "added [shipping], removed [payments], updated [orders]"
+        );
+        assert!(state.config_reload_error.is_none());
+        assert_eq!(
+            state
+                .projects
+                .keys()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>(),
+            vec!["billing", "orders", "shipping"]
+        );
+        let dependency = &state.projects[&orders].dependencies[0];
+        assert_eq!(dependency.project, billing);
+        assert_eq!(dependency.outdated, Some(false));
+        assert_eq!(dependency.breaking, Some(false));
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
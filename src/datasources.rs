@@ -1,29 +1,98 @@
+//! Data sources pulling. Schemas are fetched and parsed outside of the app state
+//! lock, which is only taken to update statuses and commit new versions.
+
 use crate::app_state::AppState;
+use crate::events::{self, Event};
 use crate::models::{DataSourceSource, ProjectSlug};
 use crate::settings::Settings;
-use crate::{branches, versions};
+use crate::web::AppStateType;
+use crate::{branches, metrics, scheduler, secrets, versions};
 use anyhow::anyhow;
-use chrono::{Duration, Utc};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedMutexGuard, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tracing::Instrument;
 
-pub async fn pull_project_datasource(
-    settings: &Settings,
+/// Serializes pulls of the same project and limits concurrent fetches,
+/// so pulls of independent projects progress in parallel
+pub struct PullLocks {
+    projects: Mutex<HashMap<ProjectSlug, Arc<tokio::sync::Mutex<()>>>>,
+    fetches: Arc<Semaphore>,
+}
+
+impl PullLocks {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            projects: Mutex::new(HashMap::new()),
+            fetches: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    fn project_lock(&self, project_slug: &ProjectSlug) -> Arc<tokio::sync::Mutex<()>> {
+        let mut projects = self.projects.lock().unwrap_or_else(|err| err.into_inner());
+        projects.entry(project_slug.clone()).or_default().clone()
+    }
+
+    pub async fn lock_project(&self, project_slug: &ProjectSlug) -> OwnedMutexGuard<()> {
+        self.project_lock(project_slug).lock_owned().await
+    }
+
+    /// Returns `None` if the project is being pulled
+    pub fn try_lock_project(&self, project_slug: &ProjectSlug) -> Option<OwnedMutexGuard<()>> {
+        self.project_lock(project_slug).try_lock_owned().ok()
+    }
+
+    /// Removes locks of deleted projects, locks which are held or awaited are kept
+    pub fn prune(&self, state: &AppState) {
+        let mut projects = self.projects.lock().unwrap_or_else(|err| err.into_inner());
+        projects
+            .retain(|slug, lock| state.projects.contains_key(slug) || Arc::strong_count(lock) > 1);
+    }
+
+    async fn acquire_fetch(&self) -> OwnedSemaphorePermit {
+        self.fetches
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Fetches semaphore is never closed")
+    }
+}
+
+/// Data source due to be pulled
+struct PullTask {
+    branch_name: String,
+    url: String,
+}
+
+enum PullOutcome {
+    Fetched(String),
+    Failed(String),
+}
+
+/// Marks due data sources as pulled now and returns them,
+/// only the data source of the branch is returned if it is set
+async fn get_pull_tasks(
     state: &mut AppState,
     project_slug: &ProjectSlug,
-    branch_name: &str,
     force: bool,
-) -> anyhow::Result<()> {
-    let (content, branch_name) = {
-        let Some(project) = state.projects.get_mut(project_slug) else {
-            return Err(anyhow!("Project not found"));
-        };
+    branch_name: Option<&str>,
+) -> anyhow::Result<Vec<PullTask>> {
+    let Some(project) = state.projects.get_mut(project_slug) else {
+        return Err(anyhow!("Project not found"));
+    };
 
-        let Some(datasource) = project
-            .data_sources
-            .iter_mut()
-            .find(|datasource| datasource.branch == branch_name)
-        else {
-            return Ok(());
-        };
+    let now = Utc::now();
+
+    let mut tasks = vec![];
+    for datasource in project.data_sources.iter_mut() {
+        if branch_name.is_some_and(|branch_name| branch_name != datasource.branch) {
+            continue;
+        }
+
+        let next_pull_at = scheduler::next_pull_at(datasource);
 
         let status = datasource
             .status
@@ -31,58 +100,90 @@ pub async fn pull_project_datasource(
             .expect("Datasource status must be loaded from persistent storage");
 
         if !status.pull_enabled && !force {
-            return Ok(());
+            continue;
         }
 
-        let now = Utc::now();
-
-        if !force {
-            if let Some(pull_last_at) = status.pull_last_at {
-                if pull_last_at + Duration::minutes(status.pull_interval_minutes as i64) > now {
-                    println!(
-                        "Skip pulling: {}::{}::{}",
-                        &project.slug, &datasource.branch, &datasource.name
-                    );
-                    return Ok(());
-                }
-            }
+        if !force && next_pull_at.is_some_and(|next_pull_at| next_pull_at > now) {
+            tracing::debug!(
+                "Skip pulling: {}::{}::{}",
+                &project.slug,
+                &datasource.branch,
+                &datasource.name
+            );
+            continue;
         }
 
         status.pull_last_at = Some(now);
 
-        let content = match &datasource.source {
-            DataSourceSource::Url { url } => {
-                let resp = reqwest::get(url).await?;
-                if resp.status().is_success() {
-                    status.pull_attempt = 0;
-                    status.pull_error = false;
-                    status.pull_error_message = None;
-
-                    Some(resp.text().await?)
-                } else {
-                    status.pull_attempt += 1;
-                    status.pull_error = true;
-                    status.pull_error_message = Some(resp.status().as_str().to_string());
-
-                    None
-                }
-            }
+        let url = match &datasource.source.value {
+            DataSourceSource::Url { url } => url.clone(),
         };
 
-        if content.is_none()
-            && settings.pull_disable_after_attempt != 0
-            && settings.pull_disable_after_attempt <= status.pull_attempt
-        {
-            status.pull_enabled = false;
-        }
-
-        let branch_name = datasource.branch.to_owned();
+        tasks.push(PullTask {
+            branch_name: datasource.branch.clone(),
+            url,
+        });
+    }
 
+    for task in &tasks {
         project
-            .persist_datasource(&state.storage, &branch_name)
+            .persist_datasource(&state.storage, &task.branch_name)
+            .await?;
+    }
+
+    Ok(tasks)
+}
+
+async fn fetch_schema(url: &str) -> PullOutcome {
+    let resp = match reqwest::get(url).await {
+        Ok(resp) => resp,
+        // Url could contain resolved secrets
+        Err(err) => return PullOutcome::Failed(secrets::redact(&err.to_string()).into_owned()),
+    };
+
+    if !resp.status().is_success() {
+        return PullOutcome::Failed(resp.status().as_str().to_string());
+    }
+
+    let content = match resp.text().await {
+        Ok(content) => content,
+        Err(err) => return PullOutcome::Failed(secrets::redact(&err.to_string()).into_owned()),
+    };
+
+    if let Err(err) = schemadoc_diff::try_deserialize_schema(&content, &content) {
+        return PullOutcome::Failed(err.to_string());
+    }
+
+    PullOutcome::Fetched(content)
+}
+
+/// Updates the data source status with the pull outcome and creates a version from the fetched schema,
+/// the state write lock is only taken to update the status and to add the version
+async fn commit_pull(
+    settings: &Settings,
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    outcome: PullOutcome,
+) -> anyhow::Result<()> {
+    let content = {
+        let mut state = state.write().await;
+
+        let content =
+            update_pull_status(settings, &mut state, project_slug, branch_name, outcome).await?;
+
+        if content.is_some() {
+            let _ = branches::create_branch_if_not_exists(
+                &mut state,
+                project_slug,
+                branch_name,
+                None::<String>,
+                None,
+            )
             .await?;
+        }
 
-        (content, branch_name)
+        content
     };
 
     if let Some(content) = content {
@@ -91,7 +192,7 @@ pub async fn pull_project_datasource(
             settings,
             state,
             project_slug,
-            &branch_name,
+            branch_name,
             message,
             &content,
         )
@@ -101,35 +202,303 @@ pub async fn pull_project_datasource(
     Ok(())
 }
 
-pub async fn pull_project_datasources(
+/// Updates the data source status with the pull outcome, returns the fetched schema
+async fn update_pull_status(
     settings: &Settings,
     state: &mut AppState,
     project_slug: &ProjectSlug,
+    branch_name: &str,
+    outcome: PullOutcome,
+) -> anyhow::Result<Option<String>> {
+    let content = {
+        let Some(project) = state.projects.get_mut(project_slug) else {
+            return Err(anyhow!("Project not found"));
+        };
+
+        let Some((datasource_name, status)) = project
+            .data_sources
+            .iter_mut()
+            .find(|datasource| datasource.branch == branch_name)
+            .and_then(|datasource| Some((&datasource.name, datasource.status.as_mut()?)))
+        else {
+            // Data source was removed during the pull
+            return Ok(None);
+        };
+
+        let content = match outcome {
+            PullOutcome::Fetched(content) => {
+                status.pull_attempt = 0;
+                status.pull_error = false;
+                status.pull_error_message = None;
+
+                Some(content)
+            }
+            PullOutcome::Failed(message) => {
+                status.pull_attempt += 1;
+                status.pull_error = true;
+                status.pull_error_message = Some(message);
+
+                None
+            }
+        };
+
+        if content.is_none()
+            && settings.pull_disable_after_attempt != 0
+            && settings.pull_disable_after_attempt <= status.pull_attempt
+        {
+            status.pull_enabled = false;
+        }
+
+        if content.is_none() {
+            events::publish(Event::DataSourceFailed {
+                project: project_slug.clone(),
+                branch: branch_name.to_owned(),
+                datasource: datasource_name.clone(),
+                error: status.pull_error_message.clone().unwrap_or_default(),
+                attempt: status.pull_attempt,
+                disabled: !status.pull_enabled,
+            });
+        }
+
+        project
+            .persist_datasource(&state.storage, branch_name)
+            .await?;
+
+        content
+    };
+
+    Ok(content)
+}
+
+pub async fn pull_project_datasources(
+    settings: &Settings,
+    locks: &PullLocks,
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
     force: bool,
 ) -> anyhow::Result<()> {
-    let Some(project) = state.projects.get(project_slug) else {
-        return Err(anyhow!("Project not found"));
+    let project_lock = locks.lock_project(project_slug).await;
+
+    pull_locked_project_datasources(settings, locks, state, project_slug, force, project_lock).await
+}
+
+/// Pulls the data source of the branch regardless of its schedule
+pub async fn pull_project_datasource(
+    settings: &Settings,
+    locks: &PullLocks,
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+) -> anyhow::Result<()> {
+    let _project_lock = locks.lock_project(project_slug).await;
+
+    pull_datasources(
+        settings,
+        locks,
+        state,
+        project_slug,
+        true,
+        Some(branch_name),
+    )
+    .await
+}
+
+/// Pulls project data sources holding the project pull lock
+pub async fn pull_locked_project_datasources(
+    settings: &Settings,
+    locks: &PullLocks,
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
+    force: bool,
+    _project_lock: OwnedMutexGuard<()>,
+) -> anyhow::Result<()> {
+    pull_datasources(settings, locks, state, project_slug, force, None).await
+}
+
+#[tracing::instrument(
+    name = "pull",
+    skip_all,
+    fields(project = %project_slug, force = force, branch = branch_name)
+)]
+async fn pull_datasources(
+    settings: &Settings,
+    locks: &PullLocks,
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
+    force: bool,
+    branch_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let tasks = {
+        let mut state = state.write().await;
+        get_pull_tasks(&mut state, project_slug, force, branch_name).await?
     };
 
-    let branches_names = project
-        .data_sources
-        .iter()
-        .map(|ds| ds.branch.clone())
-        .collect::<Vec<String>>();
+    if tasks.is_empty() {
+        return Ok(());
+    }
 
-    // run sequent for now
-    for branch_name in &branches_names {
-        let _ = branches::create_branch_if_not_exists(
-            state,
-            project_slug,
-            branch_name,
-            None::<String>,
-            None,
-        )
-        .await?;
+    let mut fetches = JoinSet::new();
+    for (idx, task) in tasks.into_iter().enumerate() {
+        let permit = locks.acquire_fetch().await;
+        let project_slug = project_slug.clone();
+        let span = tracing::info_span!("fetch", branch = %task.branch_name);
+        fetches.spawn(
+            async move {
+                let started_at = Instant::now();
+                let outcome = fetch_schema(&task.url).await;
+                drop(permit);
+
+                let success = matches!(outcome, PullOutcome::Fetched(_));
+                metrics::record_pull(&project_slug, success, started_at.elapsed());
 
-        pull_project_datasource(settings, state, project_slug, branch_name, force).await?;
+                (idx, task.branch_name, outcome)
+            }
+            .instrument(span),
+        );
+    }
+
+    let mut outcomes = vec![];
+    while let Some(result) = fetches.join_next().await {
+        outcomes.push(result?);
+    }
+    // Branches are created in the data sources order, so the default branch gets its version first
+    outcomes.sort_by_key(|(idx, _, _)| *idx);
+
+    // Fetch failures are only recorded in data sources statuses,
+    // other outcomes are committed even if committing of one of them failed
+    let mut errors = vec![];
+    for (_, branch_name, outcome) in outcomes {
+        if let PullOutcome::Failed(message) = &outcome {
+            tracing::warn!(branch = %branch_name, error = %message, "Data source pull failed");
+        }
+        if let Err(err) = commit_pull(settings, state, project_slug, &branch_name, outcome).await {
+            errors.push(format!("{branch_name}: {err}"));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "Could not commit pulled schemas of {}",
+            errors.join(", ")
+        ));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::app_state::AppState;
+    use crate::branches;
+    use crate::datasources::{pull_project_datasources, PullLocks};
+    use crate::models::ProjectSlug;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_pull_locks() {
+        let locks = PullLocks::new(2);
+
+        let payments = ProjectSlug::new("payments".to_owned());
+        let orders = ProjectSlug::new("orders".to_owned());
+
+        let _payments_lock = locks.lock_project(&payments).await;
+
+        // Other projects are not blocked
+        let orders_lock =
+            tokio::time::timeout(Duration::from_millis(100), locks.lock_project(&orders));
+        assert!(orders_lock.await.is_ok());
+
+        let payments_lock =
+            tokio::time::timeout(Duration::from_millis(100), locks.lock_project(&payments));
+        assert!(payments_lock.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pull_datasources() {
+        const SCHEMA: &str =
+            r#"{"openapi": "3.0.0", "info": {"title": "Payments", "version": "1.0"}, "paths": {}}"#;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{SCHEMA}",
+                    SCHEMA.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let location = std::env::temp_dir().join(format!("sd-pull-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            format!(
+                r#"
+version: "0.1"
+projects:
+  payments:
+    name: Payments
+    data_sources:
+      - name: Main
+        source: !Url {{ url: http://{address}/spec.json }}
+      - name: Unavailable
+        branch: dev
+        source: !Url {{ url: http://127.0.0.1:1/spec.json }}
+"#
+            ),
+        )
+        .unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage, None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+        let locks = PullLocks::new(2);
+        let payments = ProjectSlug::new("payments".to_owned());
+
+        // Fetch failures do not fail the pull, they are recorded in the data source status
+        pull_project_datasources(&settings, &locks, &state, &payments, true)
+            .await
+            .unwrap();
+
+        let state_lock = state;
+        let state = state_lock.read().await;
+        let project = &state.projects[&payments];
+        let main = branches::get_branch(project, "main").unwrap();
+        assert_eq!(main.versions.len(), 1);
+        assert!(branches::get_branch(project, "dev").is_none());
+
+        let status = |branch: &str| {
+            project
+                .data_sources
+                .iter()
+                .find(|ds| ds.branch == branch)
+                .and_then(|ds| ds.status.clone())
+                .unwrap()
+        };
+        assert!(!status("main").pull_error);
+        assert!(status("dev").pull_error);
+        assert_eq!(status("dev").pull_attempt, 1);
+
+        // Locks of deleted projects are pruned
+        let lock_count = || locks.projects.lock().unwrap().len();
+        locks.prune(&state);
+        assert_eq!(lock_count(), 1);
+        drop(state);
+        let mut state = state_lock.write().await;
+        state.projects.clear();
+        locks.prune(&state);
+        assert_eq!(lock_count(), 0);
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
@@ -1,8 +1,33 @@
 use schemadoc_diff::checker::validate;
+use semver::VersionReq;
 
 use crate::app_state::AppState;
-use crate::models::{Dependency, ProjectSlug};
-use crate::versions;
+use crate::events::{self, Event};
+use crate::models::{Dependency, ProjectSlug, Version};
+use crate::{usage, versions};
+
+/// Parses `info.version`, `v` prefix and missing minor or patch numbers are allowed
+pub fn parse_version(version: &str) -> Option<semver::Version> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+
+    if let Ok(version) = semver::Version::parse(version) {
+        return Some(version);
+    }
+
+    let missing = 2usize.checked_sub(version.matches('.').count())?;
+    semver::Version::parse(&format!("{version}{}", ".0".repeat(missing))).ok()
+}
+
+/// Newest version which `info.version` satisfies the range
+pub fn resolve_range<'v>(versions: &'v [Version], range: &VersionReq) -> Option<&'v Version> {
+    versions.iter().rev().find(|v| {
+        v.version
+            .as_deref()
+            .and_then(parse_version)
+            .is_some_and(|version| range.matches(&version))
+    })
+}
 
 pub async fn get_dependency<'s>(
     state: &'s AppState,
@@ -33,15 +58,47 @@ pub async fn setup_project_dependencies(state: &mut AppState) -> anyhow::Result<
     Ok(())
 }
 
+/// State of the project dependency computed by comparing its version with the latest one
+#[derive(Debug)]
+pub struct DependencyUpdate {
+    project: ProjectSlug,
+    index: usize,
+    branch: String,
+    version: u32,
+    latest_version: u32,
+    outdated: Option<bool>,
+    breaking: Option<bool>,
+}
+
 pub async fn update_dependent_projects(
     state: &mut AppState,
     tgt_project_slug: &ProjectSlug,
     tgt_branch_name: Option<&str>,
 ) -> anyhow::Result<Vec<ProjectSlug>> {
+    let (affected_project_slugs, updates) =
+        get_dependency_updates(state, tgt_project_slug, tgt_branch_name).await?;
+
+    apply_dependency_updates(state, tgt_project_slug, updates);
+
+    Ok(affected_project_slugs)
+}
+
+/// Computes states of the dependencies on the target project without modifying the state,
+/// so versions are compared under the state read lock. Returns dependent projects and updates.
+pub async fn get_dependency_updates(
+    state: &AppState,
+    tgt_project_slug: &ProjectSlug,
+    tgt_branch_name: Option<&str>,
+) -> anyhow::Result<(Vec<ProjectSlug>, Vec<DependencyUpdate>)> {
     let mut state_updates = vec![];
 
     let mut affected_project_slugs = vec![];
 
+    let Some(tgt_project) = state.projects.get(tgt_project_slug) else {
+        return Ok((affected_project_slugs, state_updates));
+    };
+    let checkers = tgt_project.checkers();
+
     // collect dependencies state information
     for src_project in state.projects.values() {
         let dependencies = src_project
@@ -71,7 +128,7 @@ pub async fn update_dependent_projects(
         affected_project_slugs.push(src_project.slug.clone());
 
         for (idx, dependency) in dependencies.into_iter() {
-            let (tgt_version_id, branch_name) = {
+            let (src_version_id, tgt_version_id, branch_name) = {
                 let versions =
                     versions::crud::get_versions(state, tgt_project_slug, &dependency.branch);
 
@@ -80,58 +137,133 @@ pub async fn update_dependent_projects(
                 };
 
                 if tgt_versions.is_empty() {
-                    eprintln!(
+                    tracing::warn!(
                         "Dependency latest version was not found for {} of {}",
-                        tgt_project_slug, src_project.slug
+                        tgt_project_slug,
+                        src_project.slug
                     );
                     continue;
                 }
 
                 let tgt_version = tgt_versions.last().expect("At least one item must be");
 
-                (tgt_version.id, dependency.branch.to_owned())
+                let src_version_id = match &dependency.range {
+                    Some(range) => {
+                        let version = VersionReq::parse(range)
+                            .ok()
+                            .and_then(|range| resolve_range(tgt_versions, &range));
+                        let Some(version) = version else {
+                            tracing::warn!(
+                                "Dependency version matching {} was not found for {} of {}",
+                                range,
+                                tgt_project_slug,
+                                src_project.slug
+                            );
+                            continue;
+                        };
+                        version.id
+                    }
+                    None => {
+                        let Some(version) = dependency.version else {
+                            continue;
+                        };
+                        version
+                    }
+                };
+
+                (src_version_id, tgt_version.id, dependency.branch.to_owned())
             };
 
-            let breaking = {
-                let diff = versions::services::compare_versions(
-                    state,
-                    tgt_project_slug,
-                    &branch_name,
-                    dependency.version,
-                    &branch_name,
-                    tgt_version_id,
-                )
-                .await?;
-
-                diff.get().map(|diff| !validate(diff, &["*"]).is_empty())
+            let diff = versions::services::compare_versions(
+                state,
+                tgt_project_slug,
+                &branch_name,
+                src_version_id,
+                &branch_name,
+                tgt_version_id,
+            )
+            .await?;
+
+            let used = match (&dependency.usage, diff.get()) {
+                (Some(usage), Some(diff)) => {
+                    Some(usage::used_operations(&state.storage, src_project, usage, diff).await?)
+                }
+                _ => None,
             };
 
-            let outdated = dependency.version != tgt_version_id;
+            let breaking = diff.get().map(|diff| {
+                let mut issues = validate(diff, &checkers);
+                if let Some(used) = &used {
+                    issues.retain(|issue| used.contains(issue));
+                }
+                !issues.is_empty()
+            });
+
+            // Version is not outdated if none of the used operations were changed
+            let outdated = src_version_id != tgt_version_id
+                && used.as_ref().is_none_or(|used| used.is_changed());
 
-            state_updates.push((src_project.slug.clone(), idx, Some(outdated), breaking));
+            state_updates.push(DependencyUpdate {
+                project: src_project.slug.clone(),
+                index: idx,
+                branch: branch_name,
+                version: src_version_id,
+                latest_version: tgt_version_id,
+                outdated: Some(outdated),
+                breaking,
+            });
         }
     }
 
-    // apply dependencies state updates
-    state_updates
-        .into_iter()
-        .for_each(|(slug, index, outdated, breaking)| {
-            let Some(project) = state.projects.get_mut(&slug) else {
-                return;
-            };
+    Ok((affected_project_slugs, state_updates))
+}
 
-            let Some((_, dependency)) = project
-                .dependencies
-                .iter_mut()
-                .enumerate()
-                .find(|(idx, _)| idx == &index)
-            else {
-                return;
-            };
+/// Applies dependencies state updates, dependencies changed since they were computed are skipped
+pub fn apply_dependency_updates(
+    state: &mut AppState,
+    tgt_project_slug: &ProjectSlug,
+    updates: Vec<DependencyUpdate>,
+) {
+    for update in updates {
+        let Some(dependency) = state
+            .projects
+            .get_mut(&update.project)
+            .and_then(|project| project.dependencies.get_mut(update.index))
+            .filter(|d| &d.project == tgt_project_slug && d.branch == update.branch)
+        else {
+            continue;
+        };
 
-            dependency.outdated = outdated;
-            dependency.breaking = breaking;
-        });
+        if update.outdated == Some(true) && dependency.outdated == Some(false) {
+            events::publish(Event::DependencyOutdated {
+                project: update.project.clone(),
+                dependency: tgt_project_slug.clone(),
+                branch: dependency.branch.clone(),
+                version: update.version,
+                latest_version: update.latest_version,
+                breaking: update.breaking,
+            });
+        }
 
-    Ok(affected_project_slugs)
+        dependency.version = Some(update.version);
+        dependency.outdated = update.outdated;
+        dependency.breaking = update.breaking;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dependencies::parse_version;
+
+    #[test]
+    fn test_parse_version() {
+        let version = |v: &str| parse_version(v).map(|v| v.to_string());
+
+        assert_eq!(version("2.3.1"), Some("2.3.1".to_owned()));
+        assert_eq!(version("v2.3"), Some("2.3.0".to_owned()));
+        assert_eq!(version("2"), Some("2.0.0".to_owned()));
+        assert_eq!(version("2.3.0-beta.1"), Some("2.3.0-beta.1".to_owned()));
+        assert_eq!(version("2023-08-16"), None);
+        assert_eq!(version("1.2.3.4"), None);
+    }
 }
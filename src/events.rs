@@ -0,0 +1,144 @@
+//! In-process events of versions and data sources, streamed to clients by `GET /api/v1/events`.
+//! Events are not persisted, subscribers only receive events published after subscribing.
+
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use tokio::sync::broadcast;
+
+use crate::models::ProjectSlug;
+use crate::versions::statistics::DiffStatistics;
+
+/// Events buffered for slow subscribers before they start missing them
+const CHANNEL_CAPACITY: usize = 256;
+
+static CHANNEL: LazyLock<broadcast::Sender<Event>> =
+    LazyLock::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum Event {
+    #[serde(rename = "version.created")]
+    VersionCreated {
+        project: ProjectSlug,
+        branch: String,
+        version: u32,
+        /// `info.version` of the schema
+        version_name: Option<String>,
+        statistics: DiffStatistics,
+    },
+    #[serde(rename = "version.breaking")]
+    BreakingChanges {
+        project: ProjectSlug,
+        branch: String,
+        version: u32,
+        breaking_changes: usize,
+    },
+    #[serde(rename = "datasource.failed")]
+    DataSourceFailed {
+        project: ProjectSlug,
+        branch: String,
+        datasource: String,
+        error: String,
+        attempt: u32,
+        /// Pulling was disabled after too many failed attempts
+        disabled: bool,
+    },
+    #[serde(rename = "dependency.outdated")]
+    DependencyOutdated {
+        project: ProjectSlug,
+        dependency: ProjectSlug,
+        branch: String,
+        version: u32,
+        latest_version: u32,
+        breaking: Option<bool>,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::VersionCreated { .. } => "version.created",
+            Event::BreakingChanges { .. } => "version.breaking",
+            Event::DataSourceFailed { .. } => "datasource.failed",
+            Event::DependencyOutdated { .. } => "dependency.outdated",
+        }
+    }
+
+    pub fn project(&self) -> &ProjectSlug {
+        match self {
+            Event::VersionCreated { project, .. }
+            | Event::BreakingChanges { project, .. }
+            | Event::DataSourceFailed { project, .. }
+            | Event::DependencyOutdated { project, .. } => project,
+        }
+    }
+
+    /// Formats the event as a Server-Sent Events message
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).expect("Event must be serializable");
+        format!("event: {}\ndata: {}\n\n", self.kind(), data)
+    }
+}
+
+pub fn publish(event: Event) {
+    tracing::debug!(event = event.kind(), project = %event.project(), "Publish event");
+    // Sending fails only if there are no subscribers
+    let _ = CHANNEL.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    CHANNEL.subscribe()
+}
+
+/// Comma separated projects and event types, all events are matched if not set
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsFilter {
+    pub project: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+impl EventsFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        let contains = |values: &Option<String>, value: &str| {
+            values
+                .as_ref()
+                .is_none_or(|values| values.split(',').any(|v| v.trim() == value))
+        };
+
+        contains(&self.project, &event.project().to_string()) && contains(&self.kind, event.kind())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, EventsFilter};
+    use crate::models::ProjectSlug;
+
+    #[test]
+    fn test_events_filter() {
+        let event = Event::BreakingChanges {
+            project: ProjectSlug::new("payments".to_owned()),
+            branch: "main".to_owned(),
+            version: 3,
+            breaking_changes: 2,
+        };
+
+        assert_eq!(
+            event.to_sse(),
+            "event: version.breaking\ndata: {\"type\":\"version.breaking\",\"project\":\"payments\",\
+             \"branch\":\"main\",\"version\":3,\"breakingChanges\":2}\n\n"
+        );
+
+        let filter = |project: Option<&str>, kind: Option<&str>| EventsFilter {
+            project: project.map(str::to_owned),
+            kind: kind.map(str::to_owned),
+        };
+
+        assert!(filter(None, None).matches(&event));
+        assert!(filter(Some("orders, payments"), None).matches(&event));
+        assert!(!filter(Some("orders"), None).matches(&event));
+        assert!(filter(Some("payments"), Some("version.breaking")).matches(&event));
+        assert!(!filter(None, Some("version.created,datasource.failed")).matches(&event));
+    }
+}
@@ -0,0 +1,523 @@
+//! Projects dependency graph and impact analysis of new versions
+
+use indexmap::IndexSet;
+use schemadoc_diff::checker::validate;
+use schemadoc_diff::exporters::{display_method, display_uri};
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::app_state::AppState;
+use crate::models::{Project, ProjectSlug};
+use crate::{usage, versions};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode<'s> {
+    pub slug: &'s ProjectSlug,
+    pub name: &'s str,
+    pub kind: &'static str,
+}
+
+/// Edge from the dependent project to its dependency
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge<'s> {
+    pub from: &'s ProjectSlug,
+    pub to: &'s ProjectSlug,
+    pub branch: &'s str,
+    /// `None` if the range is not resolved yet
+    pub version: Option<u32>,
+    pub range: Option<&'s str>,
+    pub outdated: Option<bool>,
+    pub breaking: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyGraph<'s> {
+    pub nodes: Vec<GraphNode<'s>>,
+    pub edges: Vec<GraphEdge<'s>>,
+}
+
+impl<'s> DependencyGraph<'s> {
+    pub fn new(state: &'s AppState) -> Self {
+        let nodes = state
+            .projects
+            .values()
+            .map(|project| GraphNode {
+                slug: &project.slug,
+                name: &project.name,
+                kind: project.kind.as_str(),
+            })
+            .collect();
+
+        let edges = state
+            .projects
+            .values()
+            .flat_map(|project| {
+                project.dependencies.iter().map(|dependency| GraphEdge {
+                    from: &project.slug,
+                    to: &dependency.project,
+                    branch: &dependency.branch,
+                    version: dependency.version,
+                    range: dependency.range.as_deref(),
+                    outdated: dependency.outdated,
+                    breaking: dependency.breaking,
+                })
+            })
+            .collect();
+
+        Self { nodes, edges }
+    }
+
+    fn edge_label(edge: &GraphEdge) -> String {
+        let mut label = match edge.range {
+            Some(range) => format!("{}@{}", edge.branch, range),
+            None => format!(
+                "{}@{}",
+                edge.branch,
+                edge.version.map(|v| v.to_string()).unwrap_or_default()
+            ),
+        };
+        if edge.breaking == Some(true) {
+            label.push_str(" breaking");
+        } else if edge.outdated == Some(true) {
+            label.push_str(" outdated");
+        }
+        label
+    }
+
+    fn edge_color(edge: &GraphEdge) -> Option<&'static str> {
+        if edge.breaking == Some(true) {
+            Some("red")
+        } else if edge.outdated == Some(true) {
+            Some("orange")
+        } else {
+            None
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut dot = String::from("digraph dependencies {\n    rankdir=LR;\n");
+
+        for node in &self.nodes {
+            let shape = if node.kind == "client" {
+                "ellipse"
+            } else {
+                "box"
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\", shape={}];",
+                node.slug,
+                escape(node.name),
+                shape
+            );
+        }
+
+        for edge in &self.edges {
+            let color = Self::edge_color(edge)
+                .map(|color| format!(", color={color}, fontcolor={color}"))
+                .unwrap_or_default();
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                edge.from,
+                edge.to,
+                escape(&Self::edge_label(edge)),
+                color
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Mermaid node id of the project, prefixed so slugs like `end` are not parsed as keywords
+    fn mermaid_id(slug: &ProjectSlug) -> String {
+        slug.to_string()
+            .bytes()
+            .fold(String::from("p_"), |mut id, byte| {
+                if byte.is_ascii_alphanumeric() {
+                    id.push(byte as char);
+                } else {
+                    let _ = write!(id, "_{byte:02x}");
+                }
+                id
+            })
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let escape = |value: &str| value.replace('"', "#quot;");
+
+        let mut mermaid = String::from("graph LR\n");
+
+        for node in &self.nodes {
+            let (open, close) = if node.kind == "client" {
+                ("([", "])")
+            } else {
+                ("[", "]")
+            };
+            let _ = writeln!(
+                mermaid,
+                "    {}{}\"{}\"{}",
+                Self::mermaid_id(node.slug),
+                open,
+                escape(node.name),
+                close
+            );
+        }
+
+        for (idx, edge) in self.edges.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    {} -->|\"{}\"| {}",
+                Self::mermaid_id(edge.from),
+                escape(&Self::edge_label(edge)),
+                Self::mermaid_id(edge.to)
+            );
+            if let Some(color) = Self::edge_color(edge) {
+                let _ = writeln!(mermaid, "    linkStyle {idx} stroke:{color}");
+            }
+        }
+
+        mermaid
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpactedProject {
+    pub project: ProjectSlug,
+    /// Dependency chain from the changed project to the impacted one
+    pub path: Vec<ProjectSlug>,
+    /// Breaking issues of the used operations, empty for transitively impacted projects
+    pub issues: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpactResult {
+    pub project: ProjectSlug,
+    pub branch: String,
+    pub version: u32,
+    pub impacted: Vec<ImpactedProject>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImpactError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Breaking issues of the candidate version for the dependent project
+async fn get_breaking_issues(
+    state: &AppState,
+    project: &Project,
+    dependent: &Project,
+    branch_name: &str,
+    version_id: u32,
+) -> anyhow::Result<Vec<String>> {
+    // Dependencies on several branches of the project may have the same issues
+    let mut issues = IndexSet::new();
+
+    for dependency in dependent
+        .dependencies
+        .iter()
+        .filter(|d| d.project == project.slug)
+    {
+        // Unresolved ranges have no version to compare with
+        let Some(dependency_version) = dependency.version else {
+            continue;
+        };
+
+        if dependency.branch == branch_name && dependency_version == version_id {
+            continue;
+        }
+
+        let diff = versions::services::compare_versions(
+            state,
+            &project.slug,
+            &dependency.branch,
+            dependency_version,
+            branch_name,
+            version_id,
+        )
+        .await?;
+
+        let Some(diff) = diff.get() else {
+            continue;
+        };
+
+        let used = match &dependency.usage {
+            Some(usage) => {
+                Some(usage::used_operations(&state.storage, dependent, usage, diff).await?)
+            }
+            None => None,
+        };
+
+        issues.extend(
+            validate(diff, &project.checkers())
+                .iter()
+                .filter(|issue| issue.breaking)
+                .filter(|issue| used.as_ref().is_none_or(|used| used.contains(issue)))
+                .map(|issue| {
+                    format!(
+                        "{} {}: {}",
+                        display_method(&issue.path).to_uppercase(),
+                        display_uri(&issue.path),
+                        issue.kind
+                    )
+                }),
+        );
+    }
+
+    Ok(issues.into_iter().collect())
+}
+
+/// Finds projects which break if the project version is shipped:
+/// direct dependents with breaking changes in the used operations and their dependents
+pub async fn get_version_impact(
+    state: &AppState,
+    project_slug: &ProjectSlug,
+    branch_name: Option<&str>,
+    version_id: Option<u32>,
+) -> Result<ImpactResult, ImpactError> {
+    let Some(project) = state.projects.get(project_slug) else {
+        return Err(ImpactError::NotFound(format!(
+            "Project {project_slug} not found"
+        )));
+    };
+
+    let branch_name = branch_name.unwrap_or(&project.default_branch);
+
+    let Some(versions) = versions::crud::get_versions(state, project_slug, branch_name) else {
+        return Err(ImpactError::NotFound(format!(
+            "Project branch {project_slug}/{branch_name} not found"
+        )));
+    };
+
+    let version = match version_id {
+        Some(id) => versions.iter().find(|v| v.id == id),
+        None => versions.last(),
+    };
+    let Some(version) = version else {
+        return Err(ImpactError::NotFound(format!(
+            "Version of {project_slug}/{branch_name} not found"
+        )));
+    };
+
+    let mut impacted = vec![];
+    let mut visited = HashSet::from([project_slug]);
+    let mut queue = VecDeque::new();
+
+    for dependent in state
+        .projects
+        .values()
+        .filter(|p| p.dependencies.iter().any(|d| &d.project == project_slug))
+    {
+        let issues =
+            get_breaking_issues(state, project, dependent, branch_name, version.id).await?;
+        if issues.is_empty() {
+            continue;
+        }
+
+        visited.insert(&dependent.slug);
+        queue.push_back(vec![project_slug.clone(), dependent.slug.clone()]);
+        impacted.push(ImpactedProject {
+            project: dependent.slug.clone(),
+            path: vec![project_slug.clone(), dependent.slug.clone()],
+            issues,
+        });
+    }
+
+    while let Some(path) = queue.pop_front() {
+        let broken = path.last().expect("Path could not be empty");
+
+        for dependent in state
+            .projects
+            .values()
+            .filter(|p| p.dependencies.iter().any(|d| &d.project == broken))
+        {
+            if !visited.insert(&dependent.slug) {
+                continue;
+            }
+
+            let mut path = path.clone();
+            path.push(dependent.slug.clone());
+
+            queue.push_back(path.clone());
+            impacted.push(ImpactedProject {
+                project: dependent.slug.clone(),
+                path,
+                issues: vec![],
+            });
+        }
+    }
+
+    Ok(ImpactResult {
+        project: project_slug.clone(),
+        branch: branch_name.to_owned(),
+        version: version.id,
+        impacted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::graph::{get_version_impact, DependencyGraph, GraphEdge, GraphNode, ImpactError};
+    use crate::models::ProjectSlug;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::versions::services::create_version;
+
+    #[test]
+    fn test_graph_render() {
+        let client = ProjectSlug::new("web-client".to_owned());
+        let api = ProjectSlug::new("end".to_owned());
+
+        let graph = DependencyGraph {
+            nodes: vec![
+                GraphNode {
+                    slug: &client,
+                    name: "Client",
+                    kind: "client",
+                },
+                GraphNode {
+                    slug: &api,
+                    name: "Payments \"API\"",
+                    kind: "server",
+                },
+            ],
+            edges: vec![GraphEdge {
+                from: &client,
+                to: &api,
+                branch: "main",
+                version: Some(3),
+                range: None,
+                outdated: Some(true),
+                breaking: Some(true),
+            }],
+        };
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph dependencies {\n    rankdir=LR;\n    \
+             \"web-client\" [label=\"Client\", shape=ellipse];\n    \
+             \"end\" [label=\"Payments \\\"API\\\"\", shape=box];\n    \
+             \"web-client\" -> \"end\" [label=\"main@3 breaking\", color=red, fontcolor=red];\n}\n"
+        );
+
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n    p_web_2dclient([\"Client\"])\n    p_end[\"Payments #quot;API#quot;\"]\n    \
+             p_web_2dclient -->|\"main@3 breaking\"| p_end\n    linkStyle 0 stroke:red\n"
+        );
+    }
+    const CONFIG: &str = r#"
+version: "0.1"
+projects:
+  payments:
+    name: Payments
+"#;
+
+    fn schema(version: &str, paths: &[&str]) -> String {
+        let paths = paths
+            .iter()
+            .map(|path| format!(r#""{path}": {{"get": {{"responses": {{}}}}}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{"openapi": "3.0.0", "info": {{"title": "Payments", "version": "{version}"}},
+                "paths": {{{paths}}}}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn test_version_impact() {
+        let location = std::env::temp_dir().join(format!("sd-impact-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage.clone(), None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+        let slug = |slug: &str| ProjectSlug::new(slug.to_owned());
+        let payments = slug("payments");
+
+        for content in [
+            schema("1", &["/users", "/orders"]),
+            schema("2", &["/users", "/orders", "/items"]),
+            schema("3", &["/users", "/items"]),
+        ] {
+            create_version(&settings, &state, &payments, "main", None, &content)
+                .await
+                .unwrap();
+        }
+
+        // Orders uses the removed operation, billing does not,
+        // web and mobile depend on orders directly and mobile through web as well
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            format!(
+                "{CONFIG}  orders:\n    name: Orders\n    dependencies:\n      \
+                 payments: {{ version: 0, usage: {{ operations: [ GET /orders ] }} }}\n  \
+                 billing:\n    name: Billing\n    dependencies:\n      \
+                 payments: {{ version: 0, usage: {{ operations: [ GET /users ] }} }}\n  \
+                 web:\n    name: Web\n    dependencies:\n      orders: 0\n  \
+                 mobile:\n    name: Mobile\n    dependencies:\n      orders: 0\n      web: 0\n"
+            ),
+        )
+        .unwrap();
+        let mut state = AppState::read(storage, None).await.unwrap();
+
+        // The same dependency on several branches reports its issues once
+        let orders = state.projects.get_mut(&slug("orders")).unwrap();
+        orders.dependencies.push(orders.dependencies[0].clone());
+
+        let impact = get_version_impact(&state, &payments, None, None)
+            .await
+            .unwrap();
+        assert_eq!(impact.version, 2);
+        let impacted: Vec<_> = impact
+            .impacted
+            .iter()
+            .map(|i| (i.project.to_string(), i.path.len(), i.issues.clone()))
+            .collect();
+        assert_eq!(
+            impacted,
+            vec![
+                (
+                    "orders".to_owned(),
+                    2,
+                    vec!["GET /orders: removed-operation".to_owned()]
+                ),
+                ("web".to_owned(), 3, vec![]),
+                ("mobile".to_owned(), 3, vec![]),
+            ]
+        );
+        assert_eq!(
+            impact.impacted[2].path,
+            vec![payments.clone(), slug("orders"), slug("mobile")]
+        );
+
+        // Dependents pinned to the candidate version are not impacted
+        let impact = get_version_impact(&state, &payments, None, Some(0))
+            .await
+            .unwrap();
+        assert!(impact.impacted.is_empty());
+
+        let not_found = get_version_impact(&state, &payments, Some("dev"), None).await;
+        assert!(matches!(not_found, Err(ImpactError::NotFound(_))));
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! Inbound push webhooks of git hosts. Pushes are mapped to data sources by their
+//! `hook` repository and ref, which are pulled immediately.
+
+use anyhow::{anyhow, bail};
+use ring::hmac;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::app_state::AppState;
+use crate::models::{DataSourceHook, ProjectSlug};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookProvider {
+    /// `X-Hub-Signature-256` HMAC-SHA256 signature of the body
+    GitHub,
+    /// `X-Gitlab-Token` secret token, GitLab does not sign webhooks
+    GitLab,
+    /// `X-Signature-256` HMAC-SHA256 signature of `{"repository": "...", "ref": "..."}` body
+    Generic,
+}
+
+impl HookProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookProvider::GitHub => "github",
+            HookProvider::GitLab => "gitlab",
+            HookProvider::Generic => "generic",
+        }
+    }
+
+    pub fn signature_header(&self) -> &'static str {
+        match self {
+            HookProvider::GitHub => "X-Hub-Signature-256",
+            HookProvider::GitLab => "X-Gitlab-Token",
+            HookProvider::Generic => "X-Signature-256",
+        }
+    }
+
+    pub fn event_header(&self) -> Option<&'static str> {
+        match self {
+            HookProvider::GitHub => Some("X-GitHub-Event"),
+            HookProvider::GitLab => Some("X-Gitlab-Event"),
+            HookProvider::Generic => None,
+        }
+    }
+
+    pub fn verify(&self, secret: &str, body: &[u8], signature: Option<&str>) -> bool {
+        let Some(signature) = signature else {
+            return false;
+        };
+
+        match self {
+            HookProvider::GitLab => constant_time_eq(secret.as_bytes(), signature.as_bytes()),
+            HookProvider::GitHub | HookProvider::Generic => {
+                let Some(tag) = signature.strip_prefix("sha256=").and_then(from_hex) else {
+                    return false;
+                };
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+                hmac::verify(&key, body, &tag).is_ok()
+            }
+        }
+    }
+
+    /// Returns `None` for events other than branch pushes
+    pub fn parse_push(&self, event: Option<&str>, body: &[u8]) -> anyhow::Result<Option<HookPush>> {
+        let expected_event = match self {
+            HookProvider::GitHub => Some("push"),
+            HookProvider::GitLab => Some("Push Hook"),
+            HookProvider::Generic => None,
+        };
+        if expected_event.is_some() && event != expected_event {
+            return Ok(None);
+        }
+
+        let payload: Value = serde_json::from_slice(body)?;
+
+        // Deleted branches have zero `after` commit
+        let deleted = payload
+            .get("after")
+            .and_then(Value::as_str)
+            .is_some_and(|after| !after.is_empty() && after.bytes().all(|b| b == b'0'));
+        if deleted {
+            return Ok(None);
+        }
+
+        let repository = match self {
+            HookProvider::GitHub => "/repository/full_name",
+            HookProvider::GitLab => "/project/path_with_namespace",
+            HookProvider::Generic => "/repository",
+        };
+
+        let field = |pointer: &str| {
+            payload
+                .pointer(pointer)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .ok_or(anyhow!("Webhook payload has no `{pointer}`"))
+        };
+
+        Ok(Some(HookPush {
+            repository: field(repository)?,
+            git_ref: field("/ref")?,
+        }))
+    }
+}
+
+impl FromStr for HookProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "github" => Ok(HookProvider::GitHub),
+            "gitlab" => Ok(HookProvider::GitLab),
+            "generic" => Ok(HookProvider::Generic),
+            _ => bail!(
+                "Unknown webhook provider `{value}`, expected `github`, `gitlab` or `generic`"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HookPush {
+    pub repository: String,
+    pub git_ref: String,
+}
+
+impl HookPush {
+    pub fn matches(&self, hook: &DataSourceHook) -> bool {
+        let branch = |git_ref: &str| {
+            git_ref
+                .strip_prefix("refs/heads/")
+                .unwrap_or(git_ref)
+                .to_owned()
+        };
+
+        self.repository.eq_ignore_ascii_case(&hook.repository)
+            && hook
+                .git_ref
+                .as_deref()
+                .is_none_or(|git_ref| branch(git_ref) == branch(&self.git_ref))
+    }
+}
+
+/// Data source triggered by a push
+#[derive(Debug, PartialEq)]
+pub struct HookTarget {
+    pub project: ProjectSlug,
+    pub branch: String,
+    /// Hook secret, the global secret is used if not set
+    pub secret: Option<String>,
+}
+
+/// Data sources triggered by the push, all data sources with a hook if the push is not set
+pub fn find_targets(state: &AppState, push: Option<&HookPush>) -> Vec<HookTarget> {
+    state
+        .projects
+        .values()
+        .flat_map(|project| {
+            project.data_sources.iter().filter_map(|ds| {
+                let hook = &ds.hook.as_ref()?.value;
+                push.is_none_or(|push| push.matches(hook))
+                    .then(|| HookTarget {
+                        project: project.slug.clone(),
+                        branch: ds.branch.clone(),
+                        secret: hook.secret.clone(),
+                    })
+            })
+        })
+        .collect()
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hooks::{HookProvider, HookPush};
+    use crate::models::DataSourceHook;
+
+    #[test]
+    fn test_verify_and_parse_push() {
+        // Example from GitHub webhooks documentation
+        let secret = "It's a Secret to Everybody";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(HookProvider::GitHub.verify(secret, b"Hello, World!", Some(signature)));
+        assert!(!HookProvider::GitHub.verify(secret, b"Hello, World?", Some(signature)));
+        assert!(!HookProvider::GitHub.verify(secret, b"Hello, World!", None));
+        assert!(!HookProvider::GitHub.verify(secret, b"Hello, World!", Some("sha256=75zz")));
+        assert!(HookProvider::GitLab.verify(secret, b"", Some(secret)));
+
+        let body = br#"{"ref": "refs/heads/main", "after": "1f2e", "project": {"path_with_namespace": "Shop/Payments"}}"#;
+        let push = HookProvider::GitLab
+            .parse_push(Some("Push Hook"), body)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            push,
+            HookPush {
+                repository: "Shop/Payments".to_owned(),
+                git_ref: "refs/heads/main".to_owned(),
+            }
+        );
+        assert!(HookProvider::GitLab
+            .parse_push(Some("Tag Push Hook"), body)
+            .unwrap()
+            .is_none());
+
+        let hook = |git_ref: Option<&str>| DataSourceHook {
+            repository: "shop/payments".to_owned(),
+            git_ref: git_ref.map(str::to_owned),
+            secret: None,
+        };
+        assert!(push.matches(&hook(None)));
+        assert!(push.matches(&hook(Some("main"))));
+        assert!(!push.matches(&hook(Some("refs/heads/develop"))));
+    }
+}
@@ -0,0 +1,271 @@
+//! JWT bearer tokens validation with keys from a JWKS file or URL.
+//! Roles from the token claim are mapped to API token scopes.
+
+use anyhow::{anyhow, bail, Context};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::models::ProjectSlug;
+use crate::tokens::{Permission, TokenScope};
+
+/// Minimal interval between JWKS reloads triggered by unknown key ids
+const JWKS_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct JwtSettings {
+    /// JWKS file path or `http(s)://` URL
+    pub jwks: String,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    /// Claim with roles, nested claims are separated with dots: `realm_access.roles`
+    pub roles_claim: String,
+    /// Scopes granted to the role, roles without mapping are ignored
+    pub roles: HashMap<String, Vec<TokenScope>>,
+}
+
+impl JwtSettings {
+    /// Parses roles mapping in `role=scope,scope;role=scope` format
+    pub fn parse_roles(value: &str) -> anyhow::Result<HashMap<String, Vec<TokenScope>>> {
+        let mut roles = HashMap::new();
+
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((role, scopes)) = entry.split_once('=') else {
+                bail!("Invalid JWT role mapping `{entry}`, expected `role=scope,scope`")
+            };
+            let scopes = scopes
+                .split(',')
+                .map(|scope| scope.trim().parse())
+                .collect::<anyhow::Result<Vec<TokenScope>>>()?;
+            roles.insert(role.trim().to_owned(), scopes);
+        }
+
+        Ok(roles)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtPrincipal {
+    pub subject: String,
+    pub scopes: Vec<TokenScope>,
+}
+
+impl JwtPrincipal {
+    pub fn allows(&self, permission: Permission, project: Option<&ProjectSlug>) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.allows(permission, project))
+    }
+}
+
+pub struct JwtVerifier {
+    settings: JwtSettings,
+    jwks: RwLock<Option<(JwkSet, Instant)>>,
+}
+
+impl JwtVerifier {
+    pub fn new(settings: JwtSettings) -> Self {
+        Self {
+            settings,
+            jwks: RwLock::new(None),
+        }
+    }
+
+    async fn fetch_jwks(&self) -> anyhow::Result<JwkSet> {
+        let source = &self.settings.jwks;
+
+        let content = if source.starts_with("http://") || source.starts_with("https://") {
+            reqwest::get(source)
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec()
+        } else {
+            tokio::fs::read(source)
+                .await
+                .with_context(|| format!("Could not read JWKS file {source}"))?
+        };
+
+        serde_json::from_slice(&content).with_context(|| format!("Invalid JWKS in {source}"))
+    }
+
+    /// Finds the key and its algorithm in cached JWKS, which is reloaded if the key is not found there
+    async fn get_key(&self, kid: Option<&str>) -> anyhow::Result<(DecodingKey, Algorithm)> {
+        let find = |jwks: &JwkSet| match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None => jwks.keys.first().cloned(),
+        };
+
+        let reload = {
+            let jwks = self.jwks.read().await;
+            match jwks.as_ref() {
+                Some((jwks, _)) if find(jwks).is_some() => false,
+                Some((_, loaded_at)) => loaded_at.elapsed() >= JWKS_RELOAD_INTERVAL,
+                None => true,
+            }
+        };
+
+        if reload {
+            let jwks = self.fetch_jwks().await?;
+            *self.jwks.write().await = Some((jwks, Instant::now()));
+        }
+
+        let jwks = self.jwks.read().await;
+        let jwk = jwks
+            .as_ref()
+            .and_then(|(jwks, _)| find(jwks))
+            .ok_or(anyhow!("JWT signing key not found"))?;
+
+        let Some(key_algorithm) = jwk.common.key_algorithm else {
+            bail!("JWT signing key has no `alg`")
+        };
+        let algorithm: Algorithm = key_algorithm
+            .to_string()
+            .parse()
+            .map_err(|_| anyhow!("JWT signing key algorithm {key_algorithm} is not supported"))?;
+
+        Ok((DecodingKey::from_jwk(&jwk)?, algorithm))
+    }
+
+    pub async fn verify(&self, token: &str) -> anyhow::Result<JwtPrincipal> {
+        let header = jsonwebtoken::decode_header(token)?;
+
+        // The algorithm is taken from the key, the token header is not trusted
+        let (key, algorithm) = self.get_key(header.kid.as_deref()).await?;
+
+        // Shared secret algorithms could not be verified with public keys
+        if matches!(
+            algorithm,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            bail!("JWT algorithm {:?} is not supported", algorithm)
+        }
+        if header.alg != algorithm {
+            bail!(
+                "JWT algorithm {:?} does not match the key algorithm {:?}",
+                header.alg,
+                algorithm
+            )
+        }
+
+        let mut validation = Validation::new(algorithm);
+        match &self.settings.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.settings.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = jsonwebtoken::decode::<Value>(token, &key, &validation)?.claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(|sub| sub.as_str())
+            .unwrap_or_default()
+            .to_owned();
+
+        let claim = self
+            .settings
+            .roles_claim
+            .split('.')
+            .try_fold(&claims, |value, key| value.get(key));
+
+        let roles: Vec<&str> = match claim {
+            Some(Value::String(role)) => role.split(' ').collect(),
+            Some(Value::Array(roles)) => roles.iter().filter_map(|role| role.as_str()).collect(),
+            _ => vec![],
+        };
+
+        // Scopes are granted only by explicit mappings, so IdP roles named like scopes grant nothing
+        let scopes = roles
+            .into_iter()
+            .filter_map(|role| self.settings.roles.get(role))
+            .flatten()
+            .cloned()
+            .collect();
+
+        Ok(JwtPrincipal { subject, scopes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jwt::{JwtSettings, JwtVerifier};
+    use crate::models::ProjectSlug;
+    use crate::tokens::Permission;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_verify() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        let jwks = json!({"keys": [{
+            "kty": "OKP", "crv": "Ed25519", "kid": "test", "alg": "EdDSA", "use": "sig",
+            "x": URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+        }]});
+        let jwks_path = std::env::temp_dir().join(format!("sd-jwks-{}.json", std::process::id()));
+        std::fs::write(&jwks_path, jwks.to_string()).unwrap();
+
+        let key = EncodingKey::from_ed_der(pkcs8.as_ref());
+        let sign = |claims: serde_json::Value| {
+            let mut header = Header::new(Algorithm::EdDSA);
+            header.kid = Some("test".to_owned());
+            jsonwebtoken::encode(&header, &claims, &key).unwrap()
+        };
+
+        let verifier = JwtVerifier::new(JwtSettings {
+            jwks: jwks_path.to_string_lossy().into_owned(),
+            issuer: Some("https://idp".to_owned()),
+            audience: Some("schemadoc".to_owned()),
+            roles_claim: "realm_access.roles".to_owned(),
+            roles: JwtSettings::parse_roles("payments-ci=versions:write:payments").unwrap(),
+        });
+
+        let exp = chrono::Utc::now().timestamp() + 60;
+
+        let principal = verifier
+            .verify(&sign(json!({
+                "sub": "ci", "iss": "https://idp", "aud": "schemadoc", "exp": exp,
+                "realm_access": {"roles": ["payments-ci", "admin", "projects:write", "unknown"]}
+            })))
+            .await
+            .unwrap();
+
+        // Roles named like scopes are not mapped, so they grant nothing
+        let payments = ProjectSlug::new("payments".to_owned());
+        assert_eq!(principal.subject, "ci");
+        assert_eq!(principal.scopes.len(), 1);
+        assert!(principal.allows(Permission::VersionsWrite, Some(&payments)));
+        assert!(!principal.allows(Permission::ProjectsWrite, None));
+        assert!(!principal.allows(Permission::Admin, None));
+
+        // The header algorithm must match the key one
+        let token = sign(json!({"iss": "https://idp", "aud": "schemadoc", "exp": exp}));
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","kid":"test"}"#);
+        let forged = format!("{header}{}", &token[token.find('.').unwrap()..]);
+        let err = verifier.verify(&forged).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "JWT algorithm RS256 does not match the key algorithm EdDSA"
+        );
+
+        let wrong_audience = sign(json!({"iss": "https://idp", "aud": "other", "exp": exp}));
+        assert!(verifier.verify(&wrong_audience).await.is_err());
+
+        let expired = sign(json!({"iss": "https://idp", "aud": "schemadoc", "exp": exp - 3600}));
+        assert!(verifier.verify(&expired).await.is_err());
+
+        std::fs::remove_file(jwks_path).unwrap();
+    }
+}
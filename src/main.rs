@@ -1,18 +1,40 @@
 pub mod alerts;
 pub mod app_state;
+pub mod archive;
+pub mod audit;
 pub mod branches;
+pub mod compatibility;
+pub mod config_check;
+pub mod config_file;
+pub mod config_reload;
 pub mod constants;
 pub mod datasources;
 pub mod dependencies;
+pub mod events;
+pub mod graph;
+pub mod hooks;
+pub mod jwt;
+pub mod metrics;
 pub mod models;
 pub mod persistence;
+pub mod projects;
 pub mod scheduler;
+pub mod secrets;
 pub mod settings;
 pub mod storage;
+pub mod telemetry;
+pub mod tokens;
+pub mod usage;
 pub mod versions;
 pub mod web;
 
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::app_state::AppState;
+use crate::models::ProjectSlug;
+use crate::settings::Settings;
+use crate::tokens::TokenScope;
 
 /// Simple program to greet a person
 #[derive(Parser)]
@@ -31,6 +53,7 @@ enum Commands {
         host: String,
         #[arg(short, long, default_value = "9753")]
         port: u16,
+        /// Pull data sources on their schedules and apply retention policies
         #[arg(short, long, action)]
         schedule: bool,
     },
@@ -45,6 +68,59 @@ enum Commands {
         #[arg(short, long, default_value = "1")]
         interval: u64,
     },
+
+    /// Export project archive to file
+    Export {
+        slug: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Import project archive from file
+    Import {
+        file: PathBuf,
+        /// Import project with the slug instead of the archived one
+        #[arg(short, long)]
+        slug: Option<String>,
+    },
+
+    /// Manage `schemadoc.yaml` configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Manage API tokens
+    Tokens {
+        #[command(subcommand)]
+        command: TokensCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Validate configuration and report all problems
+    Check {
+        /// Configuration file, `schemadoc.yaml` from config persistence path by default
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokensCommands {
+    /// Create token and print its value
+    Create {
+        name: String,
+        /// `admin`, `projects:write[:project]` or `versions:write[:project]`
+        #[arg(short, long = "scope", required = true)]
+        scopes: Vec<TokenScope>,
+        #[arg(short, long)]
+        expires_in_days: Option<i64>,
+    },
+    /// List tokens
+    List,
+    /// Revoke token
+    Revoke { name: String },
 }
 
 #[tokio::main]
@@ -56,22 +132,109 @@ async fn main() -> anyhow::Result<()> {
             host,
             port,
             schedule,
+        } => web::serve(&host, port, schedule).await,
+        Commands::Schedule {
+            host,
+            port,
+            interval,
+            force,
+        } => {
+            let settings = Settings::from_env()?;
+            let _telemetry = telemetry::init(&settings)?;
+
+            scheduler::schedule(&host, port, interval, force).await
+        }
+        Commands::Export { slug, output } => {
+            let settings = Settings::from_env()?;
+            let state = AppState::from_settings(&settings).await?;
+
+            let slug = ProjectSlug::new(slug);
+            let archive = archive::export_project(&state, &slug).await?;
+
+            let output = output.unwrap_or_else(|| format!("{}.schemadoc.json", slug).into());
+            tokio::fs::write(&output, serde_json::to_vec(&archive)?).await?;
+
+            println!("Exported {} to {:?}", slug, output);
+            Ok(())
+        }
+        Commands::Import { file, slug } => {
+            let settings = Settings::from_env()?;
+            let mut state = AppState::from_settings(&settings).await?;
+
+            let content = tokio::fs::read(&file).await?;
+            let archive = serde_json::from_slice(&content)?;
+
+            let result =
+                archive::import_project(&mut state, archive, slug.map(ProjectSlug::new)).await?;
+
+            if result.created {
+                println!("Imported {:?} as {}", file, result.slug);
+            } else {
+                println!("Project {} is already imported", result.slug);
+            }
+            Ok(())
+        }
+        Commands::Config {
+            command: ConfigCommands::Check { file },
         } => {
-            tokio::select! {
-                r = web::serve(&host, port) => {
-                    println!("Server exited: {:?}", r)
+            let file = match file {
+                Some(file) => file,
+                None => {
+                    let settings = Settings::from_env()?;
+                    PathBuf::from(settings.config_persistence_path).join("schemadoc.yaml")
+                }
+            };
+
+            let content = tokio::fs::read_to_string(&file).await?;
+
+            let issues = config_check::check_config(&content);
+            for issue in &issues {
+                println!("{}: {}", file.display(), issue);
+            }
+
+            if !issues.is_empty() {
+                anyhow::bail!("Found {} problem(s) in {:?}", issues.len(), file);
+            }
+
+            println!("{:?} is valid", file);
+            Ok(())
+        }
+        Commands::Tokens { command } => {
+            let settings = Settings::from_env()?;
+            let state = AppState::from_settings(&settings).await?;
+
+            match command {
+                TokensCommands::Create {
+                    name,
+                    scopes,
+                    expires_in_days,
+                } => {
+                    let expires_at = expires_in_days.map(tokens::expires_in_days).transpose()?;
+                    let (_, value) =
+                        tokens::create_token(&state.storage, &name, scopes, expires_at).await?;
+                    println!("{}", value);
                 }
-                r = scheduler::schedule("localhost", port, 1, false), if schedule => {
-                    println!("Scheduler exited: {:?}", r)
+                TokensCommands::List => {
+                    for token in tokens::load_tokens(&state.storage).await? {
+                        let scopes = token
+                            .scopes
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect::<Vec<_>>();
+                        let expires = match token.expires_at {
+                            Some(_) if token.is_expired() => "expired".to_owned(),
+                            Some(expires_at) => format!("expires {}", expires_at),
+                            None => "never expires".to_owned(),
+                        };
+                        println!("{}\t{}\t{}", token.name, scopes.join(","), expires);
+                    }
+                }
+                TokensCommands::Revoke { name } => {
+                    tokens::revoke_token(&state.storage, &name).await?;
+                    println!("Revoked {}", name);
                 }
             }
             Ok(())
         }
-        Commands::Schedule {
-            host,
-            port,
-            interval,
-            force,
-        } => scheduler::schedule(&host, port, interval, force).await,
     }
 }
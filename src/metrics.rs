@@ -0,0 +1,54 @@
+//! Prometheus metrics labelled by project, rendered by `GET /metrics`.
+//! Metrics are not collected until the recorder is installed by the server.
+
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+use crate::models::ProjectSlug;
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+pub fn install_recorder() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_owned()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+    Ok(handle)
+}
+
+/// Data source pull with the fetch latency
+pub fn record_pull(project: &ProjectSlug, success: bool, duration: Duration) {
+    let project = project.to_string();
+    let outcome = if success { "success" } else { "failure" };
+    counter!("schemadoc_pulls_total", "project" => project.clone(), "outcome" => outcome)
+        .increment(1);
+    histogram!("schemadoc_pull_duration_seconds", "project" => project).record(duration);
+}
+
+pub fn record_diff(project: &ProjectSlug, duration: Duration) {
+    histogram!("schemadoc_diff_duration_seconds", "project" => project.to_string())
+        .record(duration);
+}
+
+/// Created version with its spec size and the number of breaking changes
+pub fn record_version(project: &ProjectSlug, spec_size: usize, breaking_changes: usize) {
+    let project = project.to_string();
+    counter!("schemadoc_versions_created_total", "project" => project.clone()).increment(1);
+    counter!("schemadoc_breaking_changes_total", "project" => project.clone())
+        .increment(breaking_changes as u64);
+    gauge!("schemadoc_spec_size_bytes", "project" => project).set(spec_size as f64);
+}
+
+pub fn record_alert(project: &ProjectSlug, service: &str, sent: bool) {
+    let name = if sent {
+        "schemadoc_alerts_sent_total"
+    } else {
+        "schemadoc_alerts_failed_total"
+    };
+    counter!(name, "project" => project.to_string(), "service" => service.to_owned()).increment(1);
+}
@@ -1,12 +1,18 @@
 use crate::branches;
+use anyhow::anyhow;
 use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
 use schemadoc_diff::schema_diff::HttpSchemaDiff;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::fmt;
 
-use crate::persistence::{load_data_file, persist_data_file, PersistentDataFile, Versioned};
+use crate::persistence::{
+    load_data_file, persist_data_file, update_data_file, PersistentDataFile, Versioned,
+};
+use crate::secrets::Interpolated;
 use crate::storage::Storer;
 use crate::versions::statistics::DiffStatistics;
 
@@ -68,23 +74,80 @@ pub struct Project {
     pub branches: Vec<Branch>,
     pub data_sources: Vec<DataSource>,
     pub dependencies: Vec<Dependency>,
+
+    pub retention: Vec<RetentionPolicy>,
+    /// Breaking changes checkers ids, all checkers are used when empty
+    pub rules: Vec<String>,
+
+    pub labels: IndexMap<String, String>,
+    pub owners: Vec<Owner>,
+    /// Whether global alerts matching the project labels are applied
+    pub inherit_alerts: bool,
 }
 
 impl Project {
+    /// Whether the branch exists or is created by the first pull or version push
+    pub fn has_branch(&self, branch_name: &str) -> bool {
+        self.default_branch == branch_name
+            || self.branches.iter().any(|b| b.name == branch_name)
+            || self.data_sources.iter().any(|ds| ds.branch == branch_name)
+    }
+
+    /// Checks that project has all the labels, empty value matches any label value
+    pub fn has_labels(&self, labels: &IndexMap<String, String>) -> bool {
+        labels
+            .iter()
+            .all(|(key, value)| match self.labels.get(key) {
+                Some(label) => value.is_empty() || label == value,
+                None => false,
+            })
+    }
+
+    /// Checkers ids to pass to `validate`
+    pub fn checkers(&self) -> Vec<&str> {
+        if self.rules.is_empty() {
+            vec!["*"]
+        } else {
+            self.rules.iter().map(String::as_str).collect()
+        }
+    }
+
+    /// Own alerts followed by matching global alerts,
+    /// own alert with the same name overrides global one
+    pub fn effective_alerts<'a>(&'a self, global_alerts: &'a [GlobalAlert]) -> Vec<Cow<'a, Alert>> {
+        let mut alerts: Vec<_> = self.alerts.iter().map(Cow::Borrowed).collect();
+
+        if self.inherit_alerts {
+            alerts.extend(
+                global_alerts
+                    .iter()
+                    .filter(|global| self.has_labels(&global.selector))
+                    .filter(|global| !self.alerts.iter().any(|a| a.name == global.alert.name))
+                    .map(|global| global.route(self)),
+            );
+        }
+
+        alerts
+    }
+
     pub async fn load_persistent_data<S>(&mut self, storage: &S) -> anyhow::Result<()>
     where
         S: Storer,
     {
         let branches_file_path = format!("projects/{}/branches.yaml", self.slug);
-        self.branches =
-            load_data_file::<Vec<Branch>, _, _, PersistentDataFile<_>>(storage, branches_file_path)
-                .await
-                .unwrap_or_default();
+        // Do not fall back to empty branches here, it would overwrite versions history
+        self.branches = load_data_file::<Vec<Branch>, _, _, PersistentDataFile<_>>(
+            storage,
+            &branches_file_path,
+        )
+        .await
+        .map_err(|err| anyhow!("Could not load {}: {}", branches_file_path, err))?;
 
         if self.branches.is_empty() {
-            println!(
+            tracing::info!(
                 "{} - Add default branch: {} ",
-                self.slug, self.default_branch
+                self.slug,
+                self.default_branch
             );
             self.branches.push(Branch {
                 name: self.default_branch.clone(),
@@ -103,13 +166,24 @@ impl Project {
                 self.slug, branch_name,
             );
 
-            let data_source_status =
+            let mut data_source_status =
                 load_data_file::<DataSourceStatus, _, _, PersistentDataFile<_>>(
                     storage,
-                    data_source_status_file_path,
+                    &data_source_status_file_path,
                 )
                 .await
-                .unwrap_or_default();
+                .unwrap_or_else(|err| {
+                    tracing::warn!(
+                        "Could not load {}, status is reset: {}",
+                        data_source_status_file_path,
+                        err
+                    );
+                    DataSourceStatus::default()
+                });
+
+            if let Some(minutes) = data_source.pull_interval_minutes {
+                data_source_status.pull_interval_minutes = minutes;
+            }
 
             data_source.status = Some(data_source_status);
         }
@@ -132,6 +206,19 @@ impl Project {
         Ok(())
     }
 
+    /// Applies the update to the persisted branches under the file lock and replaces
+    /// in-memory branches with the result, so concurrent updates of other processes are kept
+    pub async fn update_branches<S, F>(&mut self, storage: &S, update: F) -> anyhow::Result<()>
+    where
+        S: Storer,
+        F: FnOnce(&mut Vec<Branch>) -> anyhow::Result<()>,
+    {
+        let path = format!("projects/{}/branches.yaml", self.slug);
+        self.branches = update_data_file::<Vec<Branch>, _, _, _>(storage, path, update).await?;
+
+        Ok(())
+    }
+
     pub async fn persist_datasource<S>(&self, storage: &S, branch_name: &str) -> anyhow::Result<()>
     where
         S: Storer,
@@ -177,6 +264,7 @@ impl Project {
         Ok(file_path)
     }
 
+    /// Persists diff in compact format, see `DiffFileFormat::Compact`
     pub async fn persist_version_diff<S: Storer>(
         &self,
         storage: &S,
@@ -191,8 +279,10 @@ impl Project {
             self.slug,
         );
 
+        let content = schemadoc_diff::compact::compact(diff)?;
+
         storage
-            .put_file(&diff_file_path, &serde_json::to_vec(diff)?)
+            .put_file(&diff_file_path, &serde_json::to_vec(&content)?)
             .await?;
 
         Ok(diff_file_path)
@@ -248,13 +338,33 @@ pub struct Dependency {
     pub project: ProjectSlug,
     pub branch: String,
 
-    pub version: u32,
+    /// Pinned version id or the newest version matching `range`, `None` until the range is resolved
+    pub version: Option<u32>,
+    /// Semver range of `info.version`
+    pub range: Option<String>,
+    /// Operations used by the project, all operations are considered used if not set
+    pub usage: Option<DependencyUsage>,
 
     // calculated fields
     pub breaking: Option<bool>,
     pub outdated: Option<bool>,
 }
 
+/// Operations of the dependency used by the project
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DependencyUsage {
+    /// `METHOD /path` or `/path` with `*` wildcards, e.g. `GET /users/*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operations: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operation_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Use operations of the project own latest schema, e.g. uploaded client spec
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub spec: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Alert {
     pub name: String,
@@ -266,7 +376,38 @@ pub struct Alert {
     pub is_active: bool,
 
     pub service: String,
-    pub service_config: Value,
+    pub service_config: Interpolated<Value>,
+}
+
+/// Alert from top level `alerts` of `schemadoc.yaml`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GlobalAlert {
+    /// Labels project must have, empty selector matches all projects
+    pub selector: IndexMap<String, String>,
+    pub alert: Alert,
+
+    /// Label which value selects service config from `routes`
+    pub route_by: Option<String>,
+    pub routes: IndexMap<String, Interpolated<Value>>,
+}
+
+impl GlobalAlert {
+    /// Alert with service config of the project route, default one if there is no route
+    pub fn route(&self, project: &Project) -> Cow<'_, Alert> {
+        let route = self
+            .route_by
+            .as_ref()
+            .and_then(|label| project.labels.get(label))
+            .and_then(|value| self.routes.get(value));
+
+        match route {
+            Some(service_config) => Cow::Owned(Alert {
+                service_config: service_config.clone(),
+                ..self.alert.clone()
+            }),
+            None => Cow::Borrowed(&self.alert),
+        }
+    }
 }
 
 impl Alert {
@@ -281,6 +422,24 @@ impl Alert {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub branches: Vec<String>,
+
+    pub keep_last: Option<u32>,
+    pub keep_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn includes_branch(&self, branch: &str) -> bool {
+        self.branches.is_empty()
+            || self
+                .branches
+                .iter()
+                .any(|b| b.as_str() == "*" || b.as_str() == branch)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Default)]
 pub enum AlertKind {
     #[serde(rename = "all")]
@@ -318,21 +477,55 @@ pub struct Version {
 
     pub diff_file_path: String,
     pub diff_file_version: String,
+    #[serde(default)]
+    pub diff_file_format: DiffFileFormat,
 
     pub statistics: DiffStatistics,
+    /// Breaking changes by the project rules at the time the diff was computed,
+    /// not set for versions created before it was stored
+    #[serde(default)]
+    pub breaking_changes: Option<u32>,
 
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffFileFormat {
+    /// Whole `HttpSchemaDiff` including unchanged nodes, used before compact diffs
+    #[default]
+    Full,
+    /// Only changed subtrees, see `schemadoc_diff::compact`
+    Compact,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DataSource {
     pub name: String,
     pub branch: String,
-    pub source: DataSourceSource,
+    pub source: Interpolated<DataSourceSource>,
+    /// Overrides persisted `pull_interval_minutes` of the status
+    pub pull_interval_minutes: Option<u32>,
+    /// Cron expression, takes precedence over the pull interval
+    pub pull_cron: Option<String>,
+    pub hook: Option<Interpolated<DataSourceHook>>,
     // persisted field
     pub status: Option<DataSourceStatus>,
 }
 
+/// Git repository which push webhooks trigger the data source pull
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataSourceHook {
+    /// GitHub `owner/name` or GitLab `group/project` path
+    pub repository: String,
+    /// Git branch, pushes to any branch trigger the pull if not set
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Overrides `SD_HOOK_SECRET` for pushes of the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DataSourceSource {
     Url { url: String },
@@ -373,3 +566,89 @@ pub struct Link {
     pub name: String,
     pub url: String,
 }
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Owner {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app_state::{project_from_config, ProjectConfig};
+    use crate::models::{Alert, AlertKind, AlertSource, GlobalAlert, ProjectSlug};
+    use crate::secrets::Interpolated;
+    use std::collections::HashMap;
+
+    fn global_alert(name: &str, selector: &[(&str, &str)]) -> GlobalAlert {
+        GlobalAlert {
+            selector: selector
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            alert: Alert {
+                name: name.to_owned(),
+                kind: AlertKind::Breaking,
+                source: AlertSource::Own,
+                branches: vec![],
+                is_active: true,
+                service: "Slack".to_owned(),
+                service_config: Interpolated {
+                    value: serde_yaml::Value::Null,
+                    template: None,
+                },
+            },
+            route_by: None,
+            routes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_effective_alerts() {
+        let config: ProjectConfig = serde_yaml::from_str(
+            r#"
+name: Payments
+labels: { team: core }
+alerts:
+  - name: Breaking
+    kind: breaking
+    source: own
+    is_active: false
+    service: Slack
+    service_config: { hook: https://hooks }
+"#,
+        )
+        .unwrap();
+        let slug = ProjectSlug::new("payments".to_owned());
+        let mut project = project_from_config(slug, config, &HashMap::new()).unwrap();
+
+        let mut team_alert = global_alert("Team", &[("team", "")]);
+        team_alert.route_by = Some("team".to_owned());
+        team_alert.routes.insert(
+            "core".to_owned(),
+            Interpolated {
+                value: "core-channel".into(),
+                template: None,
+            },
+        );
+
+        let global_alerts = vec![
+            global_alert("Breaking", &[]),
+            global_alert("Core", &[("team", "core")]),
+            global_alert("Web", &[("team", "web")]),
+            team_alert,
+        ];
+
+        let alerts = project.effective_alerts(&global_alerts);
+        let names: Vec<_> = alerts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Breaking", "Core", "Team"]);
+        assert!(!alerts[0].is_active);
+        assert_eq!(alerts[2].service_config.value, "core-channel");
+
+        project.inherit_alerts = false;
+        assert_eq!(project.effective_alerts(&global_alerts).len(), 1);
+    }
+}
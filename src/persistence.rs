@@ -1,14 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::storage::Storer;
 
 #[derive(thiserror::Error, Debug)]
 pub enum PersistenceError {
-    #[error("IO error")]
+    #[error("IO error: {0}")]
     Io(#[from] io::Error),
-    #[error("Deserialization error")]
+    #[error("Deserialization error: {0}")]
     Serde(#[from] serde_yaml::Error),
 }
 
@@ -47,39 +47,33 @@ impl<T> PersistentData<T> for PersistentDataFile<T> {
     }
 }
 
-pub async fn persist_data_file<'s, T, S, P, C>(
+async fn write_data_file<'s, T, S, C>(
     storage: &S,
-    path: P,
+    path: &Path,
     state: &'s T,
 ) -> Result<(), PersistenceError>
 where
     T: Versioned,
     S: Storer,
-    P: Into<PathBuf>,
     C: PersistentData<&'s T> + Serialize,
 {
-    let path = path.into();
-
     let file = C::new(T::latest(), state);
 
     let data = serde_yaml::to_string(&file)?;
 
-    storage.put_file(&path, data.as_bytes()).await?;
+    storage.put_file(path, data.as_bytes()).await?;
 
     Ok(())
 }
 
-pub async fn load_data_file<T, S, P, C>(storage: &S, path: P) -> Result<T, PersistenceError>
+async fn read_data_file<T, S, C>(storage: &S, path: &Path) -> Result<T, PersistenceError>
 where
-    T: Versioned + Default, // + for<'a> Deserialize<'a>,
+    T: Versioned + Default,
     S: Storer,
-    P: Into<PathBuf>,
     C: PersistentData<T> + for<'a> Deserialize<'a>,
 {
-    let path = path.into();
-
-    let state = if storage.exists(&path).await? {
-        let data = storage.read_file(&path).await?;
+    let state = if storage.exists(path).await? {
+        let data = storage.read_file(path).await?;
         let state: C = serde_yaml::from_slice(data.as_slice())?;
         state.data()
     } else {
@@ -88,3 +82,55 @@ where
 
     Ok(state)
 }
+
+pub async fn persist_data_file<'s, T, S, P, C>(
+    storage: &S,
+    path: P,
+    state: &'s T,
+) -> Result<(), PersistenceError>
+where
+    T: Versioned,
+    S: Storer,
+    P: Into<PathBuf>,
+    C: PersistentData<&'s T> + Serialize,
+{
+    let path = path.into();
+
+    let _lock = storage.lock(&path, true).await?;
+
+    write_data_file::<T, S, C>(storage, &path, state).await
+}
+
+pub async fn load_data_file<T, S, P, C>(storage: &S, path: P) -> Result<T, PersistenceError>
+where
+    T: Versioned + Default, // + for<'a> Deserialize<'a>,
+    S: Storer,
+    P: Into<PathBuf>,
+    C: PersistentData<T> + for<'a> Deserialize<'a>,
+{
+    let path = path.into();
+
+    let _lock = storage.lock(&path, false).await?;
+
+    read_data_file::<T, S, C>(storage, &path).await
+}
+
+/// Reads, updates and writes the data file under a single exclusive lock, so changes
+/// written by other processes after the data was loaded are not overwritten
+pub async fn update_data_file<T, S, P, F>(storage: &S, path: P, update: F) -> anyhow::Result<T>
+where
+    T: Versioned + Default + Serialize + for<'a> Deserialize<'a>,
+    S: Storer,
+    P: Into<PathBuf>,
+    F: FnOnce(&mut T) -> anyhow::Result<()>,
+{
+    let path = path.into();
+
+    let _lock = storage.lock(&path, true).await?;
+
+    let mut state = read_data_file::<T, S, PersistentDataFile<T>>(storage, &path).await?;
+    update(&mut state)?;
+    write_data_file::<T, S, PersistentDataFile<&T>>(storage, &path, &state).await?;
+
+    Ok(state)
+}
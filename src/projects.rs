@@ -0,0 +1,230 @@
+use anyhow::{anyhow, bail};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use crate::app_state::{
+    project_from_config, validate_dependencies_branches, validate_project, AppState, ProjectConfig,
+};
+use crate::config_check::check_project_config;
+use crate::dependencies::update_dependent_projects;
+use crate::models::{Project, ProjectSlug};
+
+pub(crate) fn validate_slug(slug: &ProjectSlug) -> anyhow::Result<()> {
+    let re = Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_-]*$").unwrap();
+    if !re.is_match(slug.as_str()) {
+        bail!("Project slug `{slug}` must contain only latin letters, digits, `-` and `_`")
+    }
+    Ok(())
+}
+
+fn validate_config(
+    state: &AppState,
+    slug: &ProjectSlug,
+    config: &ProjectConfig,
+) -> anyhow::Result<()> {
+    let known_projects = state
+        .projects
+        .keys()
+        .map(|slug| slug.as_str())
+        .collect::<HashSet<_>>();
+
+    let issues = check_project_config(slug, config, &known_projects);
+    if !issues.is_empty() {
+        let issues = issues.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        bail!("Invalid project configuration: {}", issues.join("; "))
+    }
+
+    Ok(())
+}
+
+fn build_project(
+    state: &AppState,
+    slug: &ProjectSlug,
+    config: ProjectConfig,
+) -> anyhow::Result<Project> {
+    validate_config(state, slug, &config)?;
+
+    let default_branches: HashMap<_, _> = state
+        .projects
+        .values()
+        .map(|p| (p.slug.clone(), p.default_branch.clone()))
+        .collect();
+
+    let project = project_from_config(slug.clone(), config, &default_branches)?;
+
+    validate_project(&project)?;
+    validate_dependencies_branches(&project, |slug| state.projects.get(slug))?;
+
+    Ok(project)
+}
+
+/// Recomputes dependencies of the project and of projects depending on it
+pub(crate) async fn update_project_dependencies(
+    state: &mut AppState,
+    project_slug: &ProjectSlug,
+) -> anyhow::Result<()> {
+    let targets = state
+        .projects
+        .get(project_slug)
+        .map(|p| {
+            p.dependencies
+                .iter()
+                .map(|d| d.project.clone())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for tgt_project_slug in &targets {
+        update_dependent_projects(state, tgt_project_slug, None).await?;
+    }
+
+    update_dependent_projects(state, project_slug, None).await?;
+
+    Ok(())
+}
+
+pub async fn create_project<'s>(
+    state: &'s mut AppState,
+    slug: &ProjectSlug,
+    config: ProjectConfig,
+) -> anyhow::Result<&'s Project> {
+    validate_slug(slug)?;
+
+    if state.projects.contains_key(slug) {
+        bail!("Project {slug} already exists")
+    }
+
+    let mut project = build_project(state, slug, config.clone())?;
+
+    state.persist_project_config(slug, &config).await?;
+
+    // Data of previously removed project with the same slug is picked up here
+    project.load_persistent_data(&state.storage).await?;
+
+    state.projects.insert(slug.clone(), project);
+
+    update_project_dependencies(state, slug).await?;
+
+    Ok(&state.projects[slug])
+}
+
+pub async fn update_project<'s>(
+    state: &'s mut AppState,
+    slug: &ProjectSlug,
+    config: ProjectConfig,
+) -> anyhow::Result<&'s Project> {
+    let Some(existing) = state.projects.get(slug) else {
+        bail!("Project {slug} not found")
+    };
+    let prev_default_branch = existing.default_branch.clone();
+
+    let mut project = build_project(state, slug, config.clone())?;
+
+    state.persist_project_config(slug, &config).await?;
+
+    project.load_persistent_data(&state.storage).await?;
+
+    // Dependents follow the default branch of the project
+    if project.default_branch != prev_default_branch {
+        for dependent in state.projects.values_mut() {
+            dependent
+                .dependencies
+                .iter_mut()
+                .filter(|d| &d.project == slug && d.branch == prev_default_branch)
+                .for_each(|d| d.branch = project.default_branch.clone());
+        }
+    }
+
+    state.projects.insert(slug.clone(), project);
+
+    update_project_dependencies(state, slug).await?;
+
+    Ok(&state.projects[slug])
+}
+
+/// Applies JSON merge patch (RFC 7396) to the current project configuration
+pub async fn patch_project<'s>(
+    state: &'s mut AppState,
+    slug: &ProjectSlug,
+    patch: serde_json::Value,
+) -> anyhow::Result<&'s Project> {
+    let Some(project) = state.projects.get(slug) else {
+        bail!("Project {slug} not found")
+    };
+
+    let mut value = serde_json::to_value(ProjectConfig::from(project))?;
+    merge_patch(&mut value, patch);
+
+    let config = serde_json::from_value::<ProjectConfig>(value)
+        .map_err(|err| anyhow!("Invalid project configuration: {err}"))?;
+
+    update_project(state, slug, config).await
+}
+
+pub async fn delete_project(state: &mut AppState, slug: &ProjectSlug) -> anyhow::Result<()> {
+    if !state.projects.contains_key(slug) {
+        bail!("Project {slug} not found")
+    }
+
+    let dependents = state
+        .projects
+        .values()
+        .filter(|p| p.dependencies.iter().any(|d| &d.project == slug))
+        .map(|p| p.slug.to_string())
+        .collect::<Vec<_>>();
+    if !dependents.is_empty() {
+        bail!(
+            "Project {slug} could not be deleted, it is used by: {}",
+            dependents.join(", ")
+        )
+    }
+
+    state.remove_project_config(slug).await?;
+
+    // Versions history is kept in the storage
+    state.projects.shift_remove(slug);
+
+    Ok(())
+}
+
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+
+    let target = target.as_object_mut().expect("Target is an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(serde_json::Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::projects::merge_patch;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut config = json!({
+            "name": "Payments",
+            "description": "Payments API",
+            "links": [{"name": "Docs", "url": "https://docs"}],
+        });
+
+        merge_patch(
+            &mut config,
+            json!({"name": "Payments v2", "description": null, "links": []}),
+        );
+
+        assert_eq!(config, json!({"name": "Payments v2", "links": []}));
+    }
+}
@@ -1,9 +1,159 @@
-use anyhow::bail;
+//! Data sources pulling schedule. The server runs the scheduler in-process with `serve --schedule`,
+//! `schedule` command polls a remote server API instead.
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use croner::Cron;
 use serde::Deserialize;
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::models::ProjectKind;
+use crate::datasources::{self, PullLocks};
+use crate::models::{DataSource, ProjectKind};
+use crate::secrets;
+use crate::settings::Settings;
+use crate::versions::retention;
 use crate::web::auth::BasicAuth;
+use crate::web::AppStateType;
+
+/// Interval of checking data sources due to be pulled
+const SCHEDULER_TICK: Duration = Duration::from_secs(15);
+
+/// Interval of applying retention policies to all projects
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximal delay of scheduled pulls, spreads pulls scheduled at the same time
+const MAX_JITTER_SECONDS: u64 = 60;
+
+pub fn parse_cron(expression: &str) -> anyhow::Result<Cron> {
+    Cron::new(expression)
+        .parse()
+        .map_err(|err| anyhow!("Invalid cron expression `{expression}`: {err}"))
+}
+
+/// Stable per data source delay, at most a tenth of the pull interval
+fn jitter(data_source: &DataSource, interval_seconds: Option<u64>) -> chrono::Duration {
+    let max = interval_seconds
+        .map(|seconds| seconds / 10)
+        .unwrap_or(MAX_JITTER_SECONDS)
+        .min(MAX_JITTER_SECONDS);
+    if max == 0 {
+        return chrono::Duration::zero();
+    }
+
+    let hash = Sha256::new()
+        .chain_update(&data_source.name)
+        .chain_update(&data_source.branch)
+        .finalize();
+    let hash = u64::from_be_bytes(hash[..8].try_into().expect("Hash is longer than 8 bytes"));
+
+    chrono::Duration::seconds((hash % max) as i64)
+}
+
+/// Time of the next scheduled pull, `None` if pulling is disabled.
+/// Never pulled data sources are due immediately.
+pub fn next_pull_at(data_source: &DataSource) -> Option<DateTime<Utc>> {
+    let status = data_source.status.as_ref()?;
+    if !status.pull_enabled {
+        return None;
+    }
+
+    let Some(pull_last_at) = status.pull_last_at else {
+        return Some(Utc::now());
+    };
+
+    let next = match &data_source.pull_cron {
+        Some(expression) => {
+            let cron = parse_cron(expression).ok()?;
+            let next = cron.find_next_occurrence(&pull_last_at, false).ok()?;
+            next + jitter(data_source, None)
+        }
+        None => {
+            let interval = status.pull_interval_minutes as u64 * 60;
+            pull_last_at
+                + chrono::Duration::seconds(interval as i64)
+                + jitter(data_source, Some(interval))
+        }
+    };
+
+    Some(next)
+}
+
+/// Pulls due data sources of server projects and periodically applies retention policies.
+/// Pulls of the same project never overlap, a due project is skipped while its pull is running.
+pub async fn run(settings: Arc<Settings>, locks: Arc<PullLocks>, state: Arc<AppStateType>) {
+    let mut retention_applied_at: Option<Instant> = None;
+
+    let mut tick = tokio::time::interval(SCHEDULER_TICK);
+    loop {
+        tick.tick().await;
+
+        let now = Utc::now();
+
+        let due_projects: Vec<_> = {
+            let state = state.read().await;
+            // Projects could be removed by configuration reloads
+            locks.prune(&state);
+            state
+                .projects
+                .values()
+                .filter(|project| project.kind.is_server())
+                .filter(|project| {
+                    project
+                        .data_sources
+                        .iter()
+                        .any(|ds| next_pull_at(ds).is_some_and(|next| next <= now))
+                })
+                .map(|project| project.slug.clone())
+                .collect()
+        };
+
+        for project_slug in due_projects {
+            let Some(project_lock) = locks.try_lock_project(&project_slug) else {
+                continue;
+            };
+
+            let settings = settings.clone();
+            let locks = locks.clone();
+            let state = state.clone();
+            tokio::spawn(async move {
+                tracing::info!("Pulling datasource: {}", project_slug);
+                let result = datasources::pull_locked_project_datasources(
+                    &settings,
+                    &locks,
+                    &state,
+                    &project_slug,
+                    false,
+                    project_lock,
+                )
+                .await;
+                if let Err(err) = result {
+                    tracing::warn!(
+                        "Scheduled pull of {} failed: {}",
+                        project_slug,
+                        secrets::redact(&err.to_string())
+                    );
+                }
+            });
+        }
+
+        if retention_applied_at.is_none_or(|at| at.elapsed() >= RETENTION_INTERVAL) {
+            retention_applied_at = Some(Instant::now());
+
+            let slugs: Vec<_> = state.read().await.projects.keys().cloned().collect();
+            for project_slug in slugs {
+                if let Err(err) = retention::apply_project_retention(&state, &project_slug).await {
+                    tracing::warn!(
+                        "Retention of {} failed: {}",
+                        project_slug,
+                        secrets::redact(&err.to_string())
+                    );
+                }
+            }
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct ListProjectsResponse {
@@ -17,9 +167,7 @@ struct ProjectBody {
 }
 
 pub async fn schedule(host: &str, port: u16, interval: u64, force: bool) -> anyhow::Result<()> {
-    // Wait until system startup
-    // TODO: add healthcheck endpoint and use it here
-    tokio::time::sleep(Duration::from_secs(15)).await;
+    wait_ready(host, port).await;
 
     let auth = BasicAuth::user_pass()?;
 
@@ -28,7 +176,18 @@ pub async fn schedule(host: &str, port: u16, interval: u64, force: bool) -> anyh
         interval.tick().await;
 
         if let Err(err) = pull(host, port, &auth, force).await {
-            println!("Pull end: {:?}", err);
+            tracing::warn!(error = %secrets::redact(&err.to_string()), "Pull failed");
+        }
+    }
+}
+
+/// Waits until the server reports it is ready
+async fn wait_ready(host: &str, port: u16) {
+    let url = format!("http://{host}:{port}/readyz");
+    loop {
+        match reqwest::get(&url).await {
+            Ok(response) if response.status().is_success() => return,
+            _ => tokio::time::sleep(Duration::from_secs(1)).await,
         }
     }
 }
@@ -45,19 +204,13 @@ async fn pull(host: &str, port: u16, auth: &(String, String), force: bool) -> an
         bail!("Error receiving project data")
     }
 
-    let projects: Vec<_> = response
-        .json::<ListProjectsResponse>()
-        .await?
-        .result
-        .into_iter()
-        .filter(|p| p.kind.is_server())
-        .collect();
+    let projects = response.json::<ListProjectsResponse>().await?.result;
 
-    println!("Found {} projects to try to pull data from", projects.len());
+    let servers: Vec<_> = projects.iter().filter(|p| p.kind.is_server()).collect();
 
-    for project in projects {
-        println!("Pulling datasource: {}", project.slug);
+    tracing::info!("Found {} projects to try to pull data from", servers.len());
 
+    for project in servers {
         let url = format!("http://{host}:{port}/api/v1/projects/{}/pull", project.slug);
 
         let response = client
@@ -67,8 +220,98 @@ async fn pull(host: &str, port: u16, auth: &(String, String), force: bool) -> an
             .send()
             .await;
 
-        println!("content {:?}", &response);
+        log_response("Pull", &project.slug, response).await;
+    }
+
+    for project in &projects {
+        let url = format!(
+            "http://{host}:{port}/api/v1/projects/{}/retention",
+            project.slug
+        );
+
+        let response = client
+            .post(url)
+            .basic_auth(&auth.0, Some(&auth.1))
+            .send()
+            .await;
+
+        log_response("Retention", &project.slug, response).await;
     }
 
     Ok(())
 }
+
+/// Logs the outcome with the server request id, so the server logs of the request could be found
+async fn log_response(action: &str, project: &str, response: reqwest::Result<reqwest::Response>) {
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            let error = secrets::redact(&err.to_string()).into_owned();
+            tracing::warn!(project, error, "{action} request failed");
+            return;
+        }
+    };
+
+    let status = response.status();
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+
+    if status.is_success() {
+        tracing::info!(project, request_id, "{action} finished");
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        let error = secrets::redact(&body).into_owned();
+        tracing::warn!(
+            project,
+            request_id,
+            status = status.as_u16(),
+            error,
+            "{action} failed"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{DataSource, DataSourceSource, DataSourceStatus};
+    use crate::scheduler::next_pull_at;
+    use crate::secrets::Interpolated;
+    use chrono::{DateTime, Duration, Timelike, Utc};
+
+    #[test]
+    fn test_next_pull_at() {
+        let pull_last_at: DateTime<Utc> = "2024-05-01T10:20:00Z".parse().unwrap();
+
+        let mut data_source = DataSource {
+            name: "API".to_owned(),
+            branch: "main".to_owned(),
+            source: Interpolated::resolve(DataSourceSource::Url {
+                url: "https://example.com/openapi.json".to_owned(),
+            })
+            .unwrap(),
+            pull_interval_minutes: None,
+            pull_cron: None,
+            hook: None,
+            status: Some(DataSourceStatus {
+                pull_last_at: Some(pull_last_at),
+                ..Default::default()
+            }),
+        };
+
+        let next = next_pull_at(&data_source).unwrap();
+        assert!(next >= pull_last_at + Duration::minutes(15));
+        assert!(next < pull_last_at + Duration::minutes(15) + Duration::seconds(60));
+
+        data_source.pull_cron = Some("0 */2 * * *".to_owned());
+        let next = next_pull_at(&data_source).unwrap();
+        assert_eq!(next.hour(), 12);
+        assert_eq!(next.minute(), 0);
+
+        data_source.status.as_mut().unwrap().pull_enabled = false;
+        assert!(next_pull_at(&data_source).is_none());
+    }
+}
@@ -0,0 +1,187 @@
+//! `${ENV_VAR}` and `${file:/path/to/secret}` placeholders resolution in config values.
+//! Resolved values are registered as secrets to be redacted from logs and errors.
+
+use anyhow::{anyhow, Context};
+use regex::{Captures, Regex};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::borrow::Cow;
+use std::sync::RwLock;
+
+const REDACTED: &str = "******";
+const FILE_PREFIX: &str = "file:";
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Config value with resolved placeholders. Template is kept to persist
+/// and show the value without secrets, it is `None` if there are no placeholders.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interpolated<T> {
+    pub value: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<T>,
+}
+
+impl<T> Interpolated<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn resolve(template: T) -> anyhow::Result<Self> {
+        let raw = serde_yaml::to_value(&template)?;
+
+        let mut secrets = vec![];
+        let resolved = interpolate_value(&raw, &mut secrets)?;
+
+        if resolved == raw {
+            return Ok(Self {
+                value: template,
+                template: None,
+            });
+        }
+
+        register_secrets(secrets);
+
+        Ok(Self {
+            value: serde_yaml::from_value(resolved)?,
+            template: Some(template),
+        })
+    }
+}
+
+impl<T> Interpolated<T> {
+    /// Value with placeholders instead of resolved secrets
+    pub fn redacted(&self) -> &T {
+        self.template.as_ref().unwrap_or(&self.value)
+    }
+}
+
+fn interpolate_value(value: &Value, secrets: &mut Vec<String>) -> anyhow::Result<Value> {
+    let value = match value {
+        Value::String(s) => Value::String(interpolate(s, secrets)?.into_owned()),
+        Value::Sequence(items) => Value::Sequence(
+            items
+                .iter()
+                .map(|item| interpolate_value(item, secrets))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), interpolate_value(v, secrets)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Tagged(tagged) => {
+            let mut tagged = tagged.clone();
+            tagged.value = interpolate_value(&tagged.value, secrets)?;
+            Value::Tagged(tagged)
+        }
+        value => value.clone(),
+    };
+
+    Ok(value)
+}
+
+/// Resolves placeholders in string, `$${...}` is kept as literal `${...}`
+fn interpolate<'s>(value: &'s str, secrets: &mut Vec<String>) -> anyhow::Result<Cow<'s, str>> {
+    let re = Regex::new(r"\$(\$)?\{([^}]*)\}").unwrap();
+
+    let mut error = None;
+
+    let result = re.replace_all(value, |captures: &Captures| {
+        let placeholder = &captures[2];
+
+        if captures.get(1).is_some() {
+            return format!("${{{placeholder}}}");
+        }
+
+        match resolve_placeholder(placeholder) {
+            Ok(secret) => {
+                secrets.push(secret.clone());
+                secret
+            }
+            Err(err) => {
+                error.get_or_insert(err);
+                String::new()
+            }
+        }
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+fn resolve_placeholder(placeholder: &str) -> anyhow::Result<String> {
+    match placeholder.strip_prefix(FILE_PREFIX) {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Could not read secret file {path}"))?;
+            Ok(content.trim_end_matches(['\r', '\n']).to_owned())
+        }
+        None => std::env::var(placeholder)
+            .map_err(|_| anyhow!("Environment variable {placeholder} is not set")),
+    }
+}
+
+fn register_secrets(secrets: Vec<String>) {
+    let mut registered = SECRETS.write().unwrap_or_else(|err| err.into_inner());
+    for secret in secrets {
+        if !secret.is_empty() && !registered.contains(&secret) {
+            registered.push(secret);
+        }
+    }
+}
+
+/// Replaces resolved secrets in the text
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap_or_else(|err| err.into_inner());
+
+    let mut result = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if result.contains(secret.as_str()) {
+            result = Cow::Owned(result.replace(secret.as_str(), REDACTED));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::DataSourceSource;
+    use crate::secrets::{redact, Interpolated};
+
+    #[test]
+    fn test_resolve_and_redact() {
+        std::env::set_var("SD_TEST_SECRET_TOKEN", "s3cr3t");
+
+        let source = DataSourceSource::Url {
+            url: "https://api/openapi.json?token=${SD_TEST_SECRET_TOKEN}&v=$${v}".to_owned(),
+        };
+
+        let interpolated = Interpolated::resolve(source.clone()).unwrap();
+        assert_eq!(
+            interpolated.value,
+            DataSourceSource::Url {
+                url: "https://api/openapi.json?token=s3cr3t&v=${v}".to_owned()
+            }
+        );
+        assert_eq!(interpolated.redacted(), &source);
+
+        assert_eq!(
+            redact("GET https://api/openapi.json?token=s3cr3t failed"),
+            "GET https://api/openapi.json?token=****** failed"
+        );
+
+        let plain = DataSourceSource::Url {
+            url: "https://api".to_owned(),
+        };
+        assert_eq!(Interpolated::resolve(plain).unwrap().template, None);
+
+        let missing = DataSourceSource::Url {
+            url: "${SD_TEST_SECRET_MISSING}".to_owned(),
+        };
+        assert!(Interpolated::resolve(missing).is_err());
+    }
+}
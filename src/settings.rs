@@ -1,4 +1,7 @@
+use crate::jwt::JwtSettings;
 use crate::models::ProjectSlug;
+use crate::storage::Compression;
+use crate::telemetry::LogFormat;
 use anyhow::anyhow;
 use std::str::FromStr;
 
@@ -7,12 +10,29 @@ pub struct Settings {
     pub(crate) frontend_origin: String,
     pub(crate) frontend_static_files: String,
     pub(crate) pull_disable_after_attempt: u32,
+    /// Maximal number of data sources fetched at the same time
+    pub(crate) pull_concurrency: usize,
 
     pub(crate) persistence: PersistenceType,
     pub(crate) persistence_path: String,
+    pub(crate) persistence_compression: Compression,
 
     pub(crate) config_persistence: PersistenceType,
     pub(crate) config_persistence_path: String,
+    pub(crate) config_reload_interval_seconds: u64,
+
+    /// JWT bearer authentication is enabled if JWKS is configured
+    pub(crate) jwt: Option<JwtSettings>,
+    /// Whether read endpoints require authorization
+    pub(crate) protect_read: bool,
+    /// Webhooks signing secret, webhooks are disabled if not set
+    pub(crate) hook_secret: Option<String>,
+
+    /// `tracing_subscriber::EnvFilter` directives, e.g. `info,schemadoc_diff=warn`
+    pub(crate) log_filter: String,
+    pub(crate) log_format: LogFormat,
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`
+    pub(crate) otlp_endpoint: Option<String>,
 }
 
 #[derive(PartialEq, Debug, Default)]
@@ -52,6 +72,11 @@ impl Settings {
             .unwrap_or_default();
         let persistence_path =
             std::env::var("SD_PERSISTENCE_PATH").unwrap_or_else(|_| "./persistence".to_owned());
+        let persistence_compression = std::env::var("SD_PERSISTENCE_COMPRESSION")
+            .ok()
+            .map(|p| p.parse())
+            .transpose()?
+            .unwrap_or_default();
 
         let config_persistence = std::env::var("SD_CONFIG_PERSISTENCE")
             .ok()
@@ -61,21 +86,74 @@ impl Settings {
         let config_persistence_path = std::env::var("SD_CONFIG_PERSISTENCE_PATH")
             .unwrap_or_else(|_| "./persistence".to_owned());
 
+        let config_reload_interval_seconds: u64 = std::env::var("SD_CONFIG_RELOAD_INTERVAL")
+            .unwrap_or_else(|_| "10".to_owned())
+            .parse()
+            .map_err(|_| anyhow!("SD_CONFIG_RELOAD_INTERVAL must be a number of seconds"))?;
+
         let pull_disable_after_attempt: u32 = std::env::var("SD_PULL_DISABLE_AFTER_ATTEMPT")
             .unwrap_or_else(|_| "0".to_owned())
             .parse()
             .expect("SD_PULL_DISABLE_AFTER_ATTEMPT must be u32");
 
+        let pull_concurrency: usize = std::env::var("SD_PULL_CONCURRENCY")
+            .unwrap_or_else(|_| "4".to_owned())
+            .parse()
+            .expect("SD_PULL_CONCURRENCY must be usize");
+
+        let jwt = match std::env::var("SD_JWT_JWKS") {
+            Ok(jwks) => Some(JwtSettings {
+                jwks,
+                issuer: std::env::var("SD_JWT_ISSUER").ok(),
+                audience: std::env::var("SD_JWT_AUDIENCE").ok(),
+                roles_claim: std::env::var("SD_JWT_ROLES_CLAIM")
+                    .unwrap_or_else(|_| "roles".to_owned()),
+                roles: JwtSettings::parse_roles(
+                    &std::env::var("SD_JWT_ROLES").unwrap_or_default(),
+                )?,
+            }),
+            Err(_) => None,
+        };
+
+        let protect_read: bool = std::env::var("SD_AUTH_PROTECT_READ")
+            .unwrap_or_else(|_| "false".to_owned())
+            .parse()
+            .expect("SD_AUTH_PROTECT_READ must be bool");
+
+        let hook_secret = std::env::var("SD_HOOK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+
+        let log_filter =
+            std::env::var("SD_LOG").unwrap_or_else(|_| "info,schemadoc_diff=warn".to_owned());
+        let log_format = std::env::var("SD_LOG_FORMAT")
+            .ok()
+            .map(|f| f.parse())
+            .transpose()?
+            .unwrap_or_default();
+        let otlp_endpoint = std::env::var("SD_OTLP_ENDPOINT").ok();
+
         Ok(Self {
             frontend_origin,
             frontend_static_files,
             pull_disable_after_attempt,
+            pull_concurrency,
 
             persistence,
             persistence_path,
+            persistence_compression,
 
             config_persistence,
             config_persistence_path,
+            config_reload_interval_seconds,
+
+            jwt,
+            protect_read,
+            hook_secret,
+
+            log_filter,
+            log_format,
+            otlp_endpoint,
         })
     }
 
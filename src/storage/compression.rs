@@ -0,0 +1,88 @@
+use anyhow::anyhow;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Only schemas and diffs are compressed, data files stay human-readable
+const COMPRESSED_EXTENSIONS: [&str; 1] = ["json"];
+
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            _ => Err(anyhow!("Value `{}` not supported.", s)),
+        }
+    }
+}
+
+impl Compression {
+    pub fn compress(&self, path: &Path, data: &[u8]) -> io::Result<Vec<u8>> {
+        let compressible = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| COMPRESSED_EXTENSIONS.contains(&ext))
+            .unwrap_or(false);
+
+        match self {
+            Compression::Gzip if compressible => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            _ => Ok(data.to_vec()),
+        }
+    }
+
+    /// Detects compression by file header, so files written before compression
+    /// was enabled are returned as is
+    pub fn decompress(data: Vec<u8>) -> io::Result<Vec<u8>> {
+        if data.starts_with(&GZIP_MAGIC) {
+            let mut decoded = Vec::new();
+            GzDecoder::new(data.as_slice()).read_to_end(&mut decoded)?;
+            Ok(decoded)
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::compression::Compression;
+    use std::path::Path;
+
+    #[test]
+    fn test_compression_roundtrip() {
+        let content = br#"{"openapi": "3.0.3", "paths": {}}"#;
+
+        let compressed = Compression::Gzip
+            .compress(Path::new("diffs/1.json"), content)
+            .unwrap();
+        assert_ne!(compressed, content);
+        assert_eq!(Compression::decompress(compressed).unwrap(), content);
+
+        // plain files are read as is
+        assert_eq!(Compression::decompress(content.to_vec()).unwrap(), content);
+
+        // data files are not compressed
+        let data = b"version: '0.1'";
+        let stored = Compression::Gzip
+            .compress(Path::new("branches.yaml"), data)
+            .unwrap();
+        assert_eq!(stored, data);
+    }
+}
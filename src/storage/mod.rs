@@ -1,47 +1,193 @@
+mod compression;
+
+use fs2::FileExt;
 use std::io;
 use std::path::{Path, PathBuf};
-use tokio::io::AsyncWriteExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+pub use compression::Compression;
+
+/// Suffix of temporary files used for atomic writes
+const TMP_FILE_SUFFIX: &str = ".sd-tmp";
+const LOCK_FILE_SUFFIX: &str = ".lock";
+/// Temporary files are written and renamed within seconds, younger ones could be
+/// in-flight writes of another process sharing the persistence
+const STALE_TMP_FILE_AGE: Duration = Duration::from_secs(15 * 60);
+
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Advisory lock over a stored file, released on drop
+#[derive(Debug)]
+pub struct StorageLock {
+    _file: std::fs::File,
+}
 
 #[async_trait::async_trait]
 pub trait Storer {
     async fn put_file<P: AsRef<Path> + Send>(&self, path: P, file: &[u8]) -> io::Result<()>;
+    /// Appends data to the end of the file, the file is created if it does not exist.
+    /// Appended data is never compressed.
+    async fn append_file<P: AsRef<Path> + Send>(&self, path: P, data: &[u8]) -> io::Result<()>;
     async fn read_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<Vec<u8>>;
+    /// Size of the file in bytes as it is stored
+    async fn file_size<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<u64>;
+    /// Last modification time of the file
+    async fn modified_at<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<SystemTime>;
+    /// Reads up to `len` bytes from `offset` as they are stored, used for appended files
+    /// which are not compressed
+    async fn read_file_range<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>>;
     async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()>;
     async fn exists<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<bool>;
+    async fn lock<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        exclusive: bool,
+    ) -> io::Result<StorageLock>;
+    /// Removes stale partially written files left after crashes, returns removed files paths
+    async fn recover(&self) -> io::Result<Vec<PathBuf>>;
+    /// File system path to watch for the file changes, if storage supports it
+    fn watch_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf>;
 }
 
 #[derive(Debug, Clone)]
 pub struct LocalStorage {
     location: PathBuf,
+    compression: Compression,
 }
 
 impl LocalStorage {
-    pub fn new<L: Into<PathBuf>>(location: L) -> Self {
+    pub fn new<L: Into<PathBuf>>(location: L, compression: Compression) -> Self {
         Self {
             location: location.into(),
+            compression,
         }
     }
 }
 
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+fn find_stale_tmp_files(
+    folder: &Path,
+    now: SystemTime,
+    found: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            find_stale_tmp_files(&path, now, found)?;
+        } else if path.to_string_lossy().ends_with(TMP_FILE_SUFFIX) {
+            let modified = entry.metadata()?.modified()?;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age >= STALE_TMP_FILE_AGE {
+                found.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
 #[async_trait::async_trait]
 impl Storer for LocalStorage {
     async fn put_file<P: AsRef<Path> + Send>(&self, path: P, file: &[u8]) -> io::Result<()> {
         let path = self.location.join(path);
+
+        let file = self.compression.compress(&path, file)?;
+
         // Create intermediate folders
         if let Some(folder) = path.parent() {
             tokio::fs::create_dir_all(folder).await?;
         }
 
-        let mut fd = tokio::fs::File::create(path).await?;
+        // Write to temporary file and rename it, so readers never see partially written file
+        let tmp_path = with_suffix(
+            &path,
+            &format!(
+                ".{}.{}{TMP_FILE_SUFFIX}",
+                std::process::id(),
+                TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ),
+        );
+
+        let result = async {
+            let mut fd = tokio::fs::File::create(&tmp_path).await?;
+            fd.write_all(&file).await?;
+            fd.sync_all().await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return result;
+        }
+
+        // Persist rename itself
+        #[cfg(unix)]
+        if let Some(folder) = path.parent() {
+            tokio::fs::File::open(folder).await?.sync_all().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn append_file<P: AsRef<Path> + Send>(&self, path: P, data: &[u8]) -> io::Result<()> {
+        let path = self.location.join(path);
+
+        if let Some(folder) = path.parent() {
+            tokio::fs::create_dir_all(folder).await?;
+        }
 
-        fd.write_all(file).await?;
-        fd.sync_all().await
+        let mut fd = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        fd.write_all(data).await?;
+        fd.sync_data().await
     }
 
     async fn read_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<Vec<u8>> {
         let path = self.location.join(path);
-        println!("Read from local storage: {:?}", path);
-        tokio::fs::read(path).await
+        tracing::debug!("Read from local storage: {:?}", path);
+        let file = tokio::fs::read(path).await?;
+        Compression::decompress(file)
+    }
+
+    async fn file_size<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<u64> {
+        let path = self.location.join(path);
+        Ok(tokio::fs::metadata(path).await?.len())
+    }
+
+    async fn modified_at<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<SystemTime> {
+        let path = self.location.join(path);
+        tokio::fs::metadata(path).await?.modified()
+    }
+
+    async fn read_file_range<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let path = self.location.join(path);
+        let mut fd = tokio::fs::File::open(path).await?;
+        fd.seek(io::SeekFrom::Start(offset)).await?;
+
+        let mut data = Vec::with_capacity(len);
+        fd.take(len as u64).read_to_end(&mut data).await?;
+        Ok(data)
     }
 
     async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()> {
@@ -53,6 +199,65 @@ impl Storer for LocalStorage {
         let path = self.location.join(path);
         tokio::fs::try_exists(path).await
     }
+
+    async fn lock<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        exclusive: bool,
+    ) -> io::Result<StorageLock> {
+        let path = with_suffix(&self.location.join(path), LOCK_FILE_SUFFIX);
+
+        if let Some(folder) = path.parent() {
+            tokio::fs::create_dir_all(folder).await?;
+        }
+
+        tokio::task::spawn_blocking(move || {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+
+            if exclusive {
+                file.lock_exclusive()?;
+            } else {
+                file.lock_shared()?;
+            }
+
+            Ok(StorageLock { _file: file })
+        })
+        .await?
+    }
+
+    async fn recover(&self) -> io::Result<Vec<PathBuf>> {
+        let location = self.location.clone();
+
+        let files = tokio::task::spawn_blocking(move || {
+            let mut found = Vec::new();
+            if location.exists() {
+                find_stale_tmp_files(&location, SystemTime::now(), &mut found)?;
+            }
+            Ok::<_, io::Error>(found)
+        })
+        .await??;
+
+        let mut removed = Vec::with_capacity(files.len());
+        for file in files {
+            match tokio::fs::remove_file(&file).await {
+                Ok(()) => removed.push(file),
+                // Removed by another process recovering the same persistence
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn watch_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        Some(self.location.join(path))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +274,13 @@ impl Storer for Storage {
         .await
     }
 
+    async fn append_file<P: AsRef<Path> + Send>(&self, path: P, data: &[u8]) -> io::Result<()> {
+        match self {
+            Storage::Local(ls) => ls.append_file(path, data),
+        }
+        .await
+    }
+
     async fn read_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<Vec<u8>> {
         match self {
             Storage::Local(ls) => ls.read_file(path),
@@ -76,6 +288,32 @@ impl Storer for Storage {
         .await
     }
 
+    async fn file_size<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<u64> {
+        match self {
+            Storage::Local(ls) => ls.file_size(path),
+        }
+        .await
+    }
+
+    async fn modified_at<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<SystemTime> {
+        match self {
+            Storage::Local(ls) => ls.modified_at(path),
+        }
+        .await
+    }
+
+    async fn read_file_range<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        match self {
+            Storage::Local(ls) => ls.read_file_range(path, offset, len),
+        }
+        .await
+    }
+
     async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> io::Result<()> {
         match self {
             Storage::Local(ls) => ls.remove_file(path),
@@ -89,4 +327,63 @@ impl Storer for Storage {
         }
         .await
     }
+
+    async fn lock<P: AsRef<Path> + Send>(
+        &self,
+        path: P,
+        exclusive: bool,
+    ) -> io::Result<StorageLock> {
+        match self {
+            Storage::Local(ls) => ls.lock(path, exclusive),
+        }
+        .await
+    }
+
+    async fn recover(&self) -> io::Result<Vec<PathBuf>> {
+        match self {
+            Storage::Local(ls) => ls.recover(),
+        }
+        .await
+    }
+
+    fn watch_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        match self {
+            Storage::Local(ls) => ls.watch_path(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{Compression, LocalStorage, Storer, STALE_TMP_FILE_AGE};
+    use std::time::SystemTime;
+
+    #[tokio::test]
+    async fn test_put_file_and_recover() {
+        let location = std::env::temp_dir().join(format!("sd-storage-{}", std::process::id()));
+        let storage = LocalStorage::new(&location, Compression::Gzip);
+
+        storage.put_file("a/b.yaml", b"first").await.unwrap();
+        storage.put_file("a/b.yaml", b"second").await.unwrap();
+        assert_eq!(storage.read_file("a/b.yaml").await.unwrap(), b"second");
+
+        // in-flight write of another process is kept
+        std::fs::write(location.join("a/b.yaml.2.0.sd-tmp"), b"in-flight").unwrap();
+
+        // emulate crash during write
+        let crashed = std::fs::File::create(location.join("a/b.yaml.1.0.sd-tmp")).unwrap();
+        crashed
+            .set_modified(SystemTime::now() - STALE_TMP_FILE_AGE)
+            .unwrap();
+
+        let recovered = storage.recover().await.unwrap();
+        assert_eq!(recovered, vec![location.join("a/b.yaml.1.0.sd-tmp")]);
+        assert!(storage.recover().await.unwrap().is_empty());
+        assert!(storage.exists("a/b.yaml.2.0.sd-tmp").await.unwrap());
+
+        let _lock = storage.lock("a/b.yaml", true).await.unwrap();
+        assert!(storage.exists("a/b.yaml.lock").await.unwrap());
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
 }
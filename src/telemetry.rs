@@ -0,0 +1,103 @@
+//! Tracing subscriber setup: text or JSON logs and, with `otlp` feature,
+//! spans export to an OpenTelemetry collector
+
+use anyhow::bail;
+use std::str::FromStr;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::settings::Settings;
+
+#[derive(PartialEq, Debug, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("Value `{}` not supported.", s),
+        }
+    }
+}
+
+/// Flushes exported spans when dropped
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("Could not flush spans: {err}");
+            }
+        }
+    }
+}
+
+pub fn init(settings: &Settings) -> anyhow::Result<Telemetry> {
+    let filter = EnvFilter::try_new(&settings.log_filter)?;
+
+    let fmt_layer = match settings.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        use opentelemetry::trace::TracerProvider;
+        use opentelemetry_otlp::WithExportConfig;
+
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                opentelemetry_sdk::Resource::builder()
+                    .with_service_name("schemadoc")
+                    .build(),
+            )
+            .build();
+
+        let tracer = provider.tracer("schemadoc");
+
+        registry
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .try_init()?;
+
+        return Ok(Telemetry {
+            provider: Some(provider),
+        });
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    if settings.otlp_endpoint.is_some() {
+        bail!("SD_OTLP_ENDPOINT requires schemadoc built with `otlp` feature")
+    }
+
+    registry.try_init()?;
+
+    Ok(Telemetry {
+        #[cfg(feature = "otlp")]
+        provider: None,
+    })
+}
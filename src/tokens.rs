@@ -0,0 +1,416 @@
+//! Named API tokens with scopes and expiry. Only token hashes are persisted,
+//! the token itself is shown once on creation.
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+
+use crate::models::ProjectSlug;
+use crate::persistence::{load_data_file, update_data_file, PersistentDataFile, Versioned};
+use crate::storage::Storer;
+
+pub const TOKENS_FILE_NAME: &str = "tokens.yaml";
+
+pub const TOKEN_PREFIX: &str = "sd_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Any action including tokens management
+    Admin,
+    /// Create, update, delete, pull, export and import projects
+    ProjectsWrite,
+    /// Push and delete versions and branches
+    VersionsWrite,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Admin => "admin",
+            Permission::ProjectsWrite => "projects:write",
+            Permission::VersionsWrite => "versions:write",
+        }
+    }
+}
+
+/// Permission granted for all projects or for the specific one,
+/// formatted as `versions:write` or `versions:write:{project}`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TokenScope {
+    pub permission: Permission,
+    pub project: Option<ProjectSlug>,
+}
+
+impl TokenScope {
+    pub fn allows(&self, permission: Permission, project: Option<&ProjectSlug>) -> bool {
+        if self.permission == Permission::Admin {
+            return true;
+        }
+
+        self.permission == permission
+            && match &self.project {
+                None => true,
+                Some(scope_project) => project == Some(scope_project),
+            }
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == Permission::Admin.as_str() {
+            return Ok(Self {
+                permission: Permission::Admin,
+                project: None,
+            });
+        }
+
+        for permission in [Permission::ProjectsWrite, Permission::VersionsWrite] {
+            let Some(rest) = value.strip_prefix(permission.as_str()) else {
+                continue;
+            };
+            let project = match rest.strip_prefix(':') {
+                Some(slug) if !slug.is_empty() => Some(ProjectSlug::new(slug.to_owned())),
+                None if rest.is_empty() => None,
+                _ => break,
+            };
+            return Ok(Self {
+                permission,
+                project,
+            });
+        }
+
+        bail!(
+            "Invalid token scope `{value}`, expected `admin`, `projects:write[:project]` or `versions:write[:project]`"
+        )
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.permission.as_str())?;
+        if let Some(project) = &self.project {
+            write!(f, ":{project}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for TokenScope {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TokenScope> for String {
+    fn from(value: TokenScope) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub name: String,
+    /// SHA-256 hex digest of the token
+    pub hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    pub fn allows(&self, permission: Permission, project: Option<&ProjectSlug>) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.allows(permission, project))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Tokens(Vec<ApiToken>);
+
+impl Versioned for Tokens {
+    fn latest() -> &'static str {
+        "0.1"
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub async fn load_tokens<S: Storer>(storage: &S) -> anyhow::Result<Vec<ApiToken>> {
+    let tokens = load_data_file::<Tokens, _, _, PersistentDataFile<_>>(storage, TOKENS_FILE_NAME)
+        .await
+        .map_err(|err| anyhow!("Could not load {TOKENS_FILE_NAME}: {err}"))?;
+    Ok(tokens.0)
+}
+
+async fn update_tokens<S, F>(storage: &S, update: F) -> anyhow::Result<()>
+where
+    S: Storer,
+    F: FnOnce(&mut Vec<ApiToken>) -> anyhow::Result<()>,
+{
+    update_data_file::<Tokens, _, _, _>(storage, TOKENS_FILE_NAME, |tokens| update(&mut tokens.0))
+        .await?;
+    Ok(())
+}
+
+/// Expiry time of a token created now
+pub fn expires_in_days(days: i64) -> anyhow::Result<DateTime<Utc>> {
+    if days <= 0 {
+        bail!("Token expiry days must be positive, got {days}")
+    }
+    Ok(Utc::now() + chrono::Duration::days(days))
+}
+
+/// Creates the token and returns it with the plain token value
+pub async fn create_token<S: Storer>(
+    storage: &S,
+    name: &str,
+    scopes: Vec<TokenScope>,
+    expires_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<(ApiToken, String)> {
+    if name.trim().is_empty() {
+        bail!("Token name must not be empty")
+    }
+    if scopes.is_empty() {
+        bail!("Token must have at least one scope")
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        bail!("Token expiry must be in the future")
+    }
+
+    let value = format!(
+        "{TOKEN_PREFIX}{}",
+        rand::random::<[u8; 24]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    );
+
+    let token = ApiToken {
+        name: name.to_owned(),
+        hash: hash_token(&value),
+        scopes,
+        created_at: Utc::now(),
+        expires_at,
+    };
+
+    update_tokens(storage, |tokens| {
+        if tokens.iter().any(|t| t.name == name) {
+            bail!("Token {name} already exists")
+        }
+        tokens.push(token.clone());
+        Ok(())
+    })
+    .await?;
+
+    Ok((token, value))
+}
+
+pub async fn revoke_token<S: Storer>(storage: &S, name: &str) -> anyhow::Result<()> {
+    update_tokens(storage, |tokens| {
+        let count = tokens.len();
+        tokens.retain(|t| t.name != name);
+        if tokens.len() == count {
+            bail!("Token {name} not found")
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Modification time and size of the tokens file, `None` if there is no file yet
+type TokensFileStamp = Option<(SystemTime, u64)>;
+
+async fn tokens_file_stamp<S: Storer>(storage: &S) -> anyhow::Result<TokensFileStamp> {
+    let modified_at = match storage.modified_at(TOKENS_FILE_NAME).await {
+        Ok(modified_at) => modified_at,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(Some((
+        modified_at,
+        storage.file_size(TOKENS_FILE_NAME).await?,
+    )))
+}
+
+/// File modification times are as coarse as the system clock tick
+const MODIFIED_AT_PRECISION: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct CachedTokens {
+    stamp: TokensFileStamp,
+    loaded_at: SystemTime,
+    tokens: Arc<Vec<ApiToken>>,
+}
+
+impl CachedTokens {
+    /// Files changed within the modification time precision after loading could have
+    /// the same stamp, so tokens loaded right after the change are reloaded
+    fn is_valid(&self, stamp: &TokensFileStamp) -> bool {
+        &self.stamp == stamp
+            && stamp
+                .is_none_or(|(modified_at, _)| modified_at + MODIFIED_AT_PRECISION < self.loaded_at)
+    }
+}
+
+/// Tokens cached for bearer authorization. They are reloaded when the tokens file changes,
+/// so tokens created or revoked by the CLI apply to a running server immediately.
+#[derive(Default)]
+pub struct TokensCache(RwLock<Option<CachedTokens>>);
+
+impl TokensCache {
+    async fn tokens<S: Storer>(&self, storage: &S) -> anyhow::Result<Arc<Vec<ApiToken>>> {
+        let stamp = tokens_file_stamp(storage).await?;
+
+        if let Some(cached) = self.0.read().await.as_ref() {
+            if cached.is_valid(&stamp) {
+                return Ok(cached.tokens.clone());
+            }
+        }
+
+        let mut cached = self.0.write().await;
+        if let Some(cached) = cached.as_ref() {
+            if cached.is_valid(&stamp) {
+                return Ok(cached.tokens.clone());
+            }
+        }
+        // The stamp is taken before loading, so changes made meanwhile are reloaded next time
+        let loaded_at = SystemTime::now();
+        let tokens = Arc::new(load_tokens(storage).await?);
+        *cached = Some(CachedTokens {
+            stamp,
+            loaded_at,
+            tokens: tokens.clone(),
+        });
+        Ok(tokens)
+    }
+
+    pub async fn invalidate(&self) {
+        *self.0.write().await = None;
+    }
+
+    /// Finds the token by its plain value, expired tokens are rejected
+    pub async fn find_token<S: Storer>(
+        &self,
+        storage: &S,
+        value: &str,
+    ) -> anyhow::Result<ApiToken> {
+        let hash = hash_token(value);
+
+        let token = self
+            .tokens(storage)
+            .await?
+            .iter()
+            .find(|t| t.hash == hash)
+            .cloned()
+            .ok_or(anyhow!("Invalid token"))?;
+
+        if token.is_expired() {
+            bail!("Token {} is expired", token.name)
+        }
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::models::ProjectSlug;
+    use crate::storage::{Compression, LocalStorage};
+    use crate::tokens::{
+        create_token, expires_in_days, revoke_token, update_tokens, Permission, TokenScope,
+        TokensCache,
+    };
+
+    #[test]
+    fn test_token_scope() {
+        let payments = ProjectSlug::new("payments".to_owned());
+        let orders = ProjectSlug::new("orders".to_owned());
+
+        let scope: TokenScope = "versions:write:payments".parse().unwrap();
+        assert_eq!(scope.to_string(), "versions:write:payments");
+        assert!(scope.allows(Permission::VersionsWrite, Some(&payments)));
+        assert!(!scope.allows(Permission::VersionsWrite, Some(&orders)));
+        assert!(!scope.allows(Permission::VersionsWrite, None));
+        assert!(!scope.allows(Permission::ProjectsWrite, Some(&payments)));
+
+        let scope: TokenScope = "projects:write".parse().unwrap();
+        assert!(scope.allows(Permission::ProjectsWrite, None));
+        assert!(scope.allows(Permission::ProjectsWrite, Some(&orders)));
+
+        let scope: TokenScope = "admin".parse().unwrap();
+        assert!(scope.allows(Permission::Admin, None));
+        assert!(scope.allows(Permission::VersionsWrite, Some(&orders)));
+
+        assert!("versions:writer".parse::<TokenScope>().is_err());
+        assert!("versions:write:".parse::<TokenScope>().is_err());
+        assert!("admin:payments".parse::<TokenScope>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_lifecycle() {
+        let location = std::env::temp_dir().join(format!("sd-tokens-{}", std::process::id()));
+        let storage = LocalStorage::new(&location, Compression::None);
+        let cache = TokensCache::default();
+
+        assert!(expires_in_days(-1).is_err());
+        let scopes = vec!["admin".parse::<TokenScope>().unwrap()];
+        let past = Some(Utc::now() - Duration::days(1));
+        assert!(create_token(&storage, "ci", scopes.clone(), past)
+            .await
+            .is_err());
+
+        let expires_at = Some(expires_in_days(30).unwrap());
+        // The cache is loaded before the token is created
+        assert!(cache.find_token(&storage, "sd_unknown").await.is_err());
+        let (_, value) = create_token(&storage, "ci", scopes.clone(), expires_at)
+            .await
+            .unwrap();
+        assert_eq!(cache.find_token(&storage, &value).await.unwrap().name, "ci");
+
+        // Tokens changed out of band, e.g. by the CLI, apply without the cache invalidation
+        let (_, other) = create_token(&storage, "deploy", scopes, expires_at)
+            .await
+            .unwrap();
+        revoke_token(&storage, "ci").await.unwrap();
+        let err = cache.find_token(&storage, &value).await.unwrap_err();
+        assert_eq!(err.to_string(), "Invalid token");
+        assert!(revoke_token(&storage, "ci").await.is_err());
+
+        update_tokens(&storage, |tokens| {
+            tokens[0].expires_at = past;
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let err = cache.find_token(&storage, &other).await.unwrap_err();
+        assert_eq!(err.to_string(), "Token deploy is expired");
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
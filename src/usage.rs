@@ -0,0 +1,315 @@
+//! Operations of a dependency schema used by the consumer project
+
+use regex::Regex;
+use schemadoc_diff::checker::ValidationIssue;
+use schemadoc_diff::core::DiffResult;
+use schemadoc_diff::exporters::{display_method, display_uri};
+use schemadoc_diff::path_pointer::PathPointer;
+use schemadoc_diff::schema_diff::{HttpSchemaDiff, OperationDiff};
+use schemadoc_diff::visitor::{dispatch_visitor, DiffVisitor};
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use crate::branches;
+use crate::models::{DependencyUsage, Project};
+use crate::storage::Storer;
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Glob with `*` wildcards
+fn glob_regex(pattern: &str) -> Regex {
+    let pattern = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    Regex::new(&format!("^{pattern}$")).expect("Escaped glob must be valid regex")
+}
+
+/// Path parameters names are ignored, `/users/{id}` is the same as `/users/{user_id}`
+fn normalize_path(path: &str) -> String {
+    let re = Regex::new(r"\{[^}]*\}").unwrap();
+    re.replace_all(path, "{}").into_owned()
+}
+
+struct OperationPattern {
+    /// Any method if not set
+    method: Option<String>,
+    path: Regex,
+}
+
+impl OperationPattern {
+    /// Parses `METHOD /path` or `/path`, both parts could contain `*` wildcards
+    fn parse(pattern: &str) -> anyhow::Result<Self> {
+        let (method, path) = match pattern.trim().split_once(' ') {
+            Some((method, path)) => (method.to_lowercase(), path.trim()),
+            None => ("*".to_owned(), pattern.trim()),
+        };
+
+        if method != "*" && !METHODS.contains(&method.as_str()) {
+            anyhow::bail!("Unknown operation method `{method}`")
+        }
+        if !path.starts_with(['/', '*']) {
+            anyhow::bail!("Operation path `{path}` must start with `/`")
+        }
+
+        Ok(Self {
+            method: Some(method).filter(|m| m != "*"),
+            path: glob_regex(&normalize_path(path)),
+        })
+    }
+
+    fn matches(&self, method: &str, path: &str) -> bool {
+        self.method.as_ref().is_none_or(|m| m == method)
+            && self.path.is_match(&normalize_path(path))
+    }
+}
+
+pub fn validate_operation_pattern(pattern: &str) -> anyhow::Result<()> {
+    OperationPattern::parse(pattern).map(|_| ())
+}
+
+struct Operation {
+    path: String,
+    method: String,
+    operation_ids: Vec<String>,
+    tags: Vec<String>,
+    changed: bool,
+}
+
+#[derive(Default)]
+struct OperationsVisitor {
+    operations: RefCell<Vec<Operation>>,
+}
+
+impl<'s> DiffVisitor<'s> for OperationsVisitor {
+    fn visit_operation(
+        &self,
+        pointer: &PathPointer,
+        method: &str,
+        operation_diff_result: &'s DiffResult<OperationDiff>,
+    ) -> bool {
+        let Some(operation) = operation_diff_result.get() else {
+            return false;
+        };
+
+        let mut operation_ids: Vec<_> = operation.operation_id.get().cloned().into_iter().collect();
+        if let DiffResult::Updated(_, Some(old)) = &operation.operation_id {
+            operation_ids.push(old.to_string());
+        }
+
+        let tags = operation
+            .tags
+            .get()
+            .map(|tags| tags.iter().filter_map(|tag| tag.get().cloned()).collect())
+            .unwrap_or_default();
+
+        self.operations.borrow_mut().push(Operation {
+            path: display_uri(pointer),
+            method: method.to_owned(),
+            operation_ids,
+            tags,
+            changed: !operation_diff_result.is_same(),
+        });
+
+        false
+    }
+}
+
+/// Operations of the dependency diff used by the consumer
+#[derive(Debug, Default)]
+pub struct UsedOperations {
+    operations: HashSet<(String, String)>,
+    changed: bool,
+}
+
+impl UsedOperations {
+    /// Whether any of the used operations was changed in the diff
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn contains(&self, issue: &ValidationIssue) -> bool {
+        let operation = (display_uri(&issue.path), display_method(&issue.path));
+        self.operations.contains(&operation)
+    }
+
+    /// Endpoints filters for diff exporters
+    pub fn endpoints(&self) -> Vec<String> {
+        self.operations
+            .iter()
+            .map(|(path, method)| format!("paths/{path}/{method}"))
+            .collect()
+    }
+}
+
+/// Operations of the latest consumer own schema on its default branch
+async fn spec_operations<S: Storer>(
+    storage: &S,
+    consumer: &Project,
+) -> anyhow::Result<Vec<OperationPattern>> {
+    let version = branches::get_branch(consumer, &consumer.default_branch)
+        .and_then(|branch| branch.versions.last());
+
+    let Some(version) = version else {
+        tracing::warn!(
+            "Usage spec of {} has no versions on {} branch",
+            consumer.slug,
+            consumer.default_branch
+        );
+        return Ok(vec![]);
+    };
+
+    let content = storage.read_file(&version.file_path).await?;
+    let schema: serde_yaml::Value = serde_yaml::from_slice(&content)?;
+
+    let Some(paths) = schema.get("paths").and_then(|paths| paths.as_mapping()) else {
+        return Ok(vec![]);
+    };
+
+    let mut operations = vec![];
+    for (path, item) in paths {
+        let Some(path) = path.as_str() else {
+            continue;
+        };
+        for method in METHODS.iter().filter(|method| item.get(**method).is_some()) {
+            operations.push(OperationPattern {
+                method: Some(method.to_string()),
+                path: Regex::new(&format!("^{}$", regex::escape(&normalize_path(path))))?,
+            });
+        }
+    }
+
+    Ok(operations)
+}
+
+/// Resolves usage declared by the consumer to the operations of the diff
+pub async fn used_operations<S: Storer>(
+    storage: &S,
+    consumer: &Project,
+    usage: &DependencyUsage,
+    diff: &HttpSchemaDiff,
+) -> anyhow::Result<UsedOperations> {
+    let mut patterns = usage
+        .operations
+        .iter()
+        .map(|pattern| OperationPattern::parse(pattern))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if usage.spec {
+        patterns.extend(spec_operations(storage, consumer).await?);
+    }
+
+    let operation_ids: Vec<_> = usage.operation_ids.iter().map(|p| glob_regex(p)).collect();
+    let tags: Vec<_> = usage.tags.iter().map(|p| glob_regex(p)).collect();
+
+    let visitor = OperationsVisitor::default();
+    dispatch_visitor(diff, &visitor);
+
+    let mut used = UsedOperations::default();
+    for operation in visitor.operations.into_inner() {
+        let is_used = patterns
+            .iter()
+            .any(|p| p.matches(&operation.method, &operation.path))
+            || operation
+                .operation_ids
+                .iter()
+                .any(|id| operation_ids.iter().any(|re| re.is_match(id)))
+            || operation
+                .tags
+                .iter()
+                .any(|tag| tags.iter().any(|re| re.is_match(tag)));
+
+        if is_used {
+            used.changed |= operation.changed;
+            used.operations.insert((operation.path, operation.method));
+        }
+    }
+
+    Ok(used)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app_state::{project_from_config, ProjectConfig};
+    use crate::models::{DependencyUsage, ProjectSlug};
+    use crate::storage::{Compression, LocalStorage};
+    use crate::usage::used_operations;
+    use crate::versions::services::compare_schemas_content;
+    use std::collections::HashMap;
+
+    const SRC: &str = r#"{"openapi": "3.0.0", "info": {"title": "API", "version": "1"}, "paths": {
+        "/users/{id}": {"get": {"operationId": "getUser", "tags": ["users"], "responses": {}}},
+        "/orders": {
+            "get": {"operationId": "listOrders", "tags": ["orders"], "responses": {}},
+            "post": {"operationId": "createOrder", "tags": ["orders"], "responses": {}}
+        }
+    }}"#;
+
+    const TGT: &str = r#"{"openapi": "3.0.0", "info": {"title": "API", "version": "2"}, "paths": {
+        "/orders": {
+            "get": {"operationId": "listOrders", "tags": ["orders"], "responses": {},
+                "parameters": [{"name": "page", "in": "query", "required": true}]},
+            "post": {"operationId": "createOrder", "tags": ["orders"], "responses": {}}
+        }
+    }}"#;
+
+    #[tokio::test]
+    async fn test_used_operations() {
+        let storage = LocalStorage::new(std::env::temp_dir(), Compression::None);
+        let config: ProjectConfig = serde_yaml::from_str("name: Client").unwrap();
+        let consumer = project_from_config(
+            ProjectSlug::new("client".to_owned()),
+            config,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        let diff = compare_schemas_content(SRC, TGT).unwrap().take().unwrap();
+        let issues = schemadoc_diff::checker::validate(&diff, &["*"]);
+        assert_eq!(issues.len(), 2);
+
+        let used = |usage: DependencyUsage| {
+            let (storage, consumer, diff) = (&storage, &consumer, &diff);
+            async move {
+                used_operations(storage, consumer, &usage, diff)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let users = used(DependencyUsage {
+            operations: vec!["GET /users/{user_id}".to_owned()],
+            ..Default::default()
+        })
+        .await;
+        assert!(users.is_changed());
+        let kinds: Vec<_> = issues
+            .iter()
+            .filter(|i| users.contains(i))
+            .map(|i| i.kind)
+            .collect();
+        assert_eq!(kinds, vec!["removed-operation"]);
+
+        let orders = used(DependencyUsage {
+            tags: vec!["ord*".to_owned()],
+            ..Default::default()
+        })
+        .await;
+        let kinds: Vec<_> = issues
+            .iter()
+            .filter(|i| orders.contains(i))
+            .map(|i| i.kind)
+            .collect();
+        assert_eq!(kinds, vec!["added-required-parameter"]);
+
+        let create = used(DependencyUsage {
+            operation_ids: vec!["createOrder".to_owned()],
+            ..Default::default()
+        })
+        .await;
+        assert!(!create.is_changed());
+        assert!(!issues.iter().any(|i| create.contains(i)));
+    }
+}
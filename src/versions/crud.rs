@@ -30,6 +30,33 @@ pub fn get_version<'s>(
     versions.iter().find(|v| v.id == id)
 }
 
+/// Returns version which the specified version diff was computed against:
+/// previous version in the branch or the branch base version for the first one
+pub fn get_source_version<'s>(
+    state: &'s AppState,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    id: u32,
+) -> Option<&'s Version> {
+    let project = state.projects.get(project_slug)?;
+
+    let branch = project.branches.iter().find(|b| b.name == branch_name)?;
+
+    let previous = branch
+        .versions
+        .iter()
+        .filter(|v| v.id < id)
+        .max_by_key(|v| v.id);
+
+    if previous.is_some() {
+        return previous;
+    }
+
+    let base = branch.base.as_ref()?;
+
+    get_version(state, project_slug, &base.name, base.version_id)
+}
+
 pub async fn delete_version(
     state: &mut AppState,
     project_slug: &ProjectSlug,
@@ -40,21 +67,21 @@ pub async fn delete_version(
         return Ok(false);
     };
 
-    let versions = project
-        .branches
-        .iter_mut()
-        .find(|b| b.name == branch_name)
-        .map(|x| &mut x.versions);
-
-    let Some(versions) = versions else {
+    if !project.branches.iter().any(|b| b.name == branch_name) {
         return Ok(false);
-    };
+    }
 
-    let len = versions.len();
-    versions.retain(|v| v.id != id);
-    let len_after = versions.len();
-
-    project.persist_branches(&state.storage).await?;
+    let mut deleted = false;
+    project
+        .update_branches(&state.storage, |branches| {
+            if let Some(branch) = branches.iter_mut().find(|b| b.name == branch_name) {
+                let len = branch.versions.len();
+                branch.versions.retain(|v| v.id != id);
+                deleted = len != branch.versions.len();
+            }
+            Ok(())
+        })
+        .await?;
 
-    Ok(len != len_after)
+    Ok(deleted)
 }
@@ -1,3 +1,4 @@
 pub mod crud;
+pub mod retention;
 pub mod services;
 pub mod statistics;
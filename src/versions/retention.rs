@@ -0,0 +1,445 @@
+use anyhow::bail;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::app_state::AppState;
+use crate::models::{DiffFileFormat, Project, ProjectSlug, RetentionPolicy, Version};
+use crate::storage::Storer;
+use crate::versions::services::{compare_schemas_content, count_breaking_changes};
+use crate::web::AppStateType;
+use crate::{branches, versions};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionResult {
+    pub branch: String,
+    pub removed: Vec<u32>,
+    pub rechained: Vec<u32>,
+}
+
+/// Returns ids of versions which are not covered by retention policy.
+/// The latest version and `protected` versions are always kept.
+pub fn get_expired_versions(
+    versions: &[Version],
+    policy: &RetentionPolicy,
+    protected: &HashSet<u32>,
+    now: DateTime<Utc>,
+) -> Vec<u32> {
+    if policy.keep_last.is_none() && policy.keep_days.is_none() {
+        return vec![];
+    }
+
+    let Some(latest) = versions.iter().max_by_key(|v| v.id) else {
+        return vec![];
+    };
+
+    let mut ids: Vec<_> = versions.iter().map(|v| v.id).collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+
+    let kept_by_count: HashSet<_> = policy
+        .keep_last
+        .map(|keep_last| ids.iter().take(keep_last as usize).copied().collect())
+        .unwrap_or_default();
+
+    let threshold = policy
+        .keep_days
+        .map(|keep_days| now - Duration::days(keep_days as i64));
+
+    versions
+        .iter()
+        .filter(|v| v.id != latest.id)
+        .filter(|v| !protected.contains(&v.id))
+        .filter(|v| !kept_by_count.contains(&v.id))
+        .filter(|v| threshold.map(|t| v.created_at < t).unwrap_or(true))
+        .map(|v| v.id)
+        .collect()
+}
+
+/// Collects versions of the project branch referenced by dependencies or used as branch bases
+fn get_protected_versions(state: &AppState, project: &Project, branch_name: &str) -> HashSet<u32> {
+    let dependencies = state
+        .projects
+        .values()
+        .flat_map(|p| &p.dependencies)
+        .filter(|d| d.project == project.slug && d.branch == branch_name)
+        .filter_map(|d| d.version);
+
+    let bases = project
+        .branches
+        .iter()
+        .filter_map(|b| b.base.as_ref())
+        .filter(|base| base.name == branch_name)
+        .map(|base| base.version_id);
+
+    dependencies.chain(bases).collect()
+}
+
+/// Versions of the branch left after retention, computed without modifying the state
+struct BranchRetention {
+    branch: String,
+    /// Ids of the branch versions retention was computed for
+    ids: Vec<u32>,
+    kept: Vec<Version>,
+    removed: Vec<Version>,
+    rechained: Vec<u32>,
+}
+
+/// Removes expired versions of the project branches. Kept versions and their recomputed diffs
+/// are computed under the read lock, the write lock is only taken to persist them
+pub async fn apply_project_retention(
+    state: &AppStateType,
+    project_slug: &ProjectSlug,
+) -> anyhow::Result<Vec<RetentionResult>> {
+    let (storage, retentions) = {
+        let state = state.read().await;
+        let retentions = get_project_retention(&state, project_slug).await?;
+        (state.storage.clone(), retentions)
+    };
+
+    if retentions.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let unused_files = {
+        let mut state = state.write().await;
+        commit_project_retention(&mut state, project_slug, &retentions).await?
+    };
+
+    // Files are removed only after branches are persisted, so they never point to removed files
+    for file_path in unused_files {
+        if let Err(err) = storage.remove_file(&file_path).await {
+            tracing::warn!("Could not remove {}: {}", file_path, err);
+        }
+    }
+
+    let results = retentions
+        .into_iter()
+        .map(|retention| {
+            let removed: Vec<_> = retention.removed.iter().map(|v| v.id).collect();
+
+            tracing::info!(
+                "Retention {}/{}: removed {:?}, rechained {:?}",
+                project_slug,
+                retention.branch,
+                removed,
+                retention.rechained
+            );
+
+            RetentionResult {
+                branch: retention.branch,
+                removed,
+                rechained: retention.rechained,
+            }
+        })
+        .collect();
+
+    Ok(results)
+}
+
+async fn get_project_retention(
+    state: &AppState,
+    project_slug: &ProjectSlug,
+) -> anyhow::Result<Vec<BranchRetention>> {
+    let Some(project) = state.projects.get(project_slug) else {
+        bail!("Project {project_slug} not found")
+    };
+
+    let now = Utc::now();
+
+    let mut retentions = Vec::new();
+
+    for branch in &project.branches {
+        let Some(policy) = project
+            .retention
+            .iter()
+            .find(|p| p.includes_branch(&branch.name))
+        else {
+            continue;
+        };
+
+        let protected = get_protected_versions(state, project, &branch.name);
+
+        let expired = get_expired_versions(&branch.versions, policy, &protected, now);
+        if !expired.is_empty() {
+            retentions.push(get_branch_retention(state, project, &branch.name, &expired).await?);
+        }
+    }
+
+    Ok(retentions)
+}
+
+/// Persists branches without the removed versions, checking they were not changed since
+/// retention was computed. Returns files which are not used anymore.
+async fn commit_project_retention(
+    state: &mut AppState,
+    project_slug: &ProjectSlug,
+    retentions: &[BranchRetention],
+) -> anyhow::Result<Vec<String>> {
+    let Some(project) = state.projects.get(project_slug) else {
+        bail!("Project {project_slug} not found")
+    };
+
+    for retention in retentions {
+        let ids: Vec<_> = branches::get_branch(project, &retention.branch)
+            .into_iter()
+            .flat_map(|b| &b.versions)
+            .map(|v| v.id)
+            .collect();
+
+        let protected = get_protected_versions(state, project, &retention.branch);
+
+        // New versions could only be appended to the branch while retention was computed
+        if !ids.starts_with(&retention.ids)
+            || retention.removed.iter().any(|v| protected.contains(&v.id))
+        {
+            bail!(
+                "Versions of {}/{} changed during retention",
+                project_slug,
+                retention.branch
+            )
+        }
+    }
+
+    let project = state
+        .projects
+        .get_mut(project_slug)
+        .expect("Project is checked above");
+
+    project
+        .update_branches(&state.storage, |branches| {
+            for retention in retentions {
+                let Some(branch) = branches.iter_mut().find(|b| b.name == retention.branch) else {
+                    continue;
+                };
+                let appended = branch
+                    .versions
+                    .split_off(retention.ids.len().min(branch.versions.len()));
+                branch.versions = retention.kept.clone();
+                branch.versions.extend(appended);
+            }
+            Ok(())
+        })
+        .await?;
+
+    // Schema files could be shared by versions of other branches
+    let used_files: HashSet<_> = project
+        .branches
+        .iter()
+        .flat_map(|b| &b.versions)
+        .map(|v| v.file_path.as_str())
+        .collect();
+
+    let removed = retentions.iter().flat_map(|r| &r.removed);
+
+    let diff_files = removed.clone().map(|v| v.diff_file_path.clone());
+
+    let schema_files: HashSet<_> = removed
+        .map(|v| v.file_path.as_str())
+        .filter(|file_path| !used_files.contains(file_path))
+        .collect();
+
+    Ok(diff_files
+        .chain(schema_files.into_iter().map(str::to_owned))
+        .collect())
+}
+
+/// Computes versions of the branch left after removing versions and recomputes diffs of
+/// remaining versions whose predecessor was removed. Only diff files are written.
+async fn get_branch_retention(
+    state: &AppState,
+    project: &Project,
+    branch_name: &str,
+    ids: &[u32],
+) -> anyhow::Result<BranchRetention> {
+    let storage = &state.storage;
+
+    let Some(branch) = branches::get_branch(project, branch_name) else {
+        bail!("Project branch {}/{branch_name} not found", project.slug)
+    };
+
+    let base_file_path = branch
+        .base
+        .as_ref()
+        .and_then(|base| {
+            branches::get_branch(project, &base.name)
+                .and_then(|b| b.versions.iter().find(|v| v.id == base.version_id))
+        })
+        .map(|v| v.file_path.clone());
+
+    let mut kept = Vec::with_capacity(branch.versions.len());
+    let mut removed = Vec::new();
+    let mut rechained = Vec::new();
+
+    // Schema file of the previous kept version, initially the branch base one
+    let mut previous_file_path = base_file_path;
+    let mut predecessor_removed = false;
+
+    for version in &branch.versions {
+        if ids.contains(&version.id) {
+            predecessor_removed = true;
+            removed.push(version.clone());
+            continue;
+        }
+
+        let mut version = version.clone();
+
+        if predecessor_removed {
+            // For the first version without base compare it to itself
+            let src_file_path = previous_file_path.as_deref().unwrap_or(&version.file_path);
+            let src_content = storage.read_file(src_file_path).await?;
+            let tgt_content = storage.read_file(&version.file_path).await?;
+
+            let diff = compare_schemas_content(
+                &String::from_utf8_lossy(&src_content),
+                &String::from_utf8_lossy(&tgt_content),
+            )?;
+
+            let diff = diff.take().expect(
+                "Root diff of two schemas must not be empty.\
+                     Probably two null schemas were provided.",
+            );
+
+            version.diff_file_path = project
+                .persist_version_diff(storage, branch_name, version.id, &diff)
+                .await?;
+            version.diff_file_version = diff.get_diff_version();
+            version.diff_file_format = DiffFileFormat::Compact;
+            version.statistics = versions::statistics::get_diff_statistics(&diff);
+            version.breaking_changes = Some(count_breaking_changes(&diff, &project.checkers()));
+
+            rechained.push(version.id);
+        }
+
+        predecessor_removed = false;
+        previous_file_path = Some(version.file_path.clone());
+        kept.push(version);
+    }
+
+    Ok(BranchRetention {
+        branch: branch_name.to_owned(),
+        ids: branch.versions.iter().map(|v| v.id).collect(),
+        kept,
+        removed,
+        rechained,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use std::collections::HashSet;
+
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::models::{ProjectSlug, RetentionPolicy, Version};
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::versions::retention::{apply_project_retention, get_expired_versions};
+    use crate::versions::services::create_version;
+    use crate::versions::statistics::DiffStatistics;
+
+    const CONFIG: &str = r#"
+version: "0.1"
+projects:
+  payments:
+    name: Payments
+    retention:
+      - keep_last: 1
+"#;
+
+    fn version(id: u32, days_ago: i64) -> Version {
+        Version {
+            id,
+            version: None,
+            message: None,
+            file_path: format!("{id}.json"),
+            diff_file_path: format!("diffs/{id}.json"),
+            diff_file_version: "0.1".to_owned(),
+            diff_file_format: Default::default(),
+            statistics: DiffStatistics {
+                total: 0,
+                added: 0,
+                removed: 0,
+                updated: 0,
+            },
+            breaking_changes: None,
+            created_at: Utc::now() - Duration::days(days_ago),
+        }
+    }
+
+    #[test]
+    fn test_expired_versions() {
+        let versions: Vec<_> = (0..6).map(|id| version(id, 20 - 3 * id as i64)).collect();
+
+        let policy = RetentionPolicy {
+            branches: vec![],
+            keep_last: Some(2),
+            keep_days: Some(7),
+        };
+
+        let protected = HashSet::from([1]);
+
+        let expired = get_expired_versions(&versions, &policy, &protected, Utc::now());
+
+        // only 5 is newer than 7 days, 4 is kept by count and 1 is protected
+        assert_eq!(expired, vec![0, 2, 3]);
+
+        let policy = RetentionPolicy {
+            branches: vec![],
+            keep_last: Some(0),
+            keep_days: None,
+        };
+
+        let expired = get_expired_versions(&versions, &policy, &HashSet::new(), Utc::now());
+
+        // the latest version is always kept
+        assert_eq!(expired, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_apply_project_retention() {
+        let location = std::env::temp_dir().join(format!("sd-retention-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage.clone(), None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+        let slug = ProjectSlug::new("payments".to_owned());
+
+        for path in ["/users", "/orders", "/items"] {
+            let content = format!(
+                r#"{{"openapi": "3.0.0", "info": {{"title": "Payments", "version": "1"}},
+                    "paths": {{"{path}": {{"get": {{"responses": {{}}}}}}}}}}"#
+            );
+            create_version(&settings, &state, &slug, "main", None, &content)
+                .await
+                .unwrap();
+        }
+
+        let removed: Vec<_> = state.read().await.projects[&slug].branches[0].versions[..2]
+            .iter()
+            .map(|v| (v.file_path.clone(), v.diff_file_path.clone()))
+            .collect();
+
+        let results = apply_project_retention(&state, &slug).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].removed, vec![0, 1]);
+        assert_eq!(results[0].rechained, vec![2]);
+
+        // Branches are persisted before the files of removed versions are removed
+        let state = AppState::read(storage, None).await.unwrap();
+        let versions = &state.projects[&slug].branches[0].versions;
+        assert_eq!(versions.iter().map(|v| v.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(versions[0].statistics.added, 0);
+        for (file_path, diff_file_path) in removed {
+            assert!(!location.join(file_path).exists());
+            assert!(!location.join(diff_file_path).exists());
+        }
+        assert!(location.join(&versions[0].file_path).exists());
+
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
 use anyhow::bail;
 use chrono::Utc;
+use indexmap::IndexMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use schemadoc_diff::checker::validate;
 use schemadoc_diff::core::DiffResult;
@@ -9,8 +12,11 @@ use crate::app_state::AppState;
 use crate::settings::Settings;
 use crate::storage::Storer;
 
-use crate::models::{ProjectSlug, Version};
-use crate::{alerts, branches, dependencies, versions};
+use crate::alerts::AlertInfo;
+use crate::events::{self, Event};
+use crate::models::{DiffFileFormat, Project, ProjectSlug, Version};
+use crate::web::AppStateType;
+use crate::{alerts, branches, dependencies, metrics, usage, versions};
 
 pub struct CreatedVersion {
     pub version: Version,
@@ -19,22 +25,33 @@ pub struct CreatedVersion {
     pub src_branch_name: String,
 }
 
-async fn create_version_inner(
-    state: &mut AppState,
+/// Version diffed with its source version, which is not added to the branch yet
+struct PreparedVersion {
+    src_branch_name: String,
+    src_version: Option<Version>,
+    diff: HttpSchemaDiff,
+    file_path: String,
+    breaking_changes: u32,
+}
+
+/// Commits of versions prepared while another version was added to the branch are retried
+const COMMIT_ATTEMPTS: u32 = 3;
+
+/// Diffs the schema with the branch source version without modifying the state,
+/// returns `None` if the schema has no changes
+async fn prepare_version(
+    state: &AppState,
     project_slug: &ProjectSlug,
     branch_name: &str,
-    message: Option<String>,
     content: &str,
-) -> anyhow::Result<Option<CreatedVersion>> {
+) -> anyhow::Result<Option<PreparedVersion>> {
     let (src_branch_name, src_version) = get_source_version(state, project_slug, branch_name)?;
 
-    let Some(project) = state.projects.get_mut(project_slug) else {
+    let Some(project) = state.projects.get(project_slug) else {
         bail!("Project {project_slug} not found")
     };
 
-    let Some(branch) = branches::get_branch(project, branch_name) else {
-        bail!("Project branch {project_slug}/{branch_name} not found")
-    };
+    let started_at = Instant::now();
 
     let diff = match src_version.as_ref() {
         Some(src_version) => {
@@ -47,6 +64,8 @@ async fn create_version_inner(
         }
     };
 
+    metrics::record_diff(project_slug, started_at.elapsed());
+
     // Skip this version if it has no changes and there are any versions before it
     if diff.is_same_or_none() && src_version.is_some() {
         return Ok(None);
@@ -57,6 +76,52 @@ async fn create_version_inner(
              Probably two null schemas were provided.",
     );
 
+    let breaking_changes = count_breaking_changes(&diff, &project.checkers());
+
+    // Schema files are content addressed, so they could be written before the version is added
+    let file_path = project.persist_version(&state.storage, content).await?;
+
+    Ok(Some(PreparedVersion {
+        src_branch_name,
+        src_version,
+        diff,
+        file_path,
+        breaking_changes,
+    }))
+}
+
+/// Adds the prepared version to the branch, returns `None` if the branch source version
+/// was changed since the version was prepared
+async fn commit_version(
+    state: &mut AppState,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    message: Option<String>,
+    prepared: PreparedVersion,
+) -> anyhow::Result<Option<CreatedVersion>> {
+    let (src_branch_name, src_version) = get_source_version(state, project_slug, branch_name)?;
+    if src_branch_name != prepared.src_branch_name
+        || src_version.map(|v| v.id) != prepared.src_version.as_ref().map(|v| v.id)
+    {
+        return Ok(None);
+    }
+
+    let Some(project) = state.projects.get_mut(project_slug) else {
+        bail!("Project {project_slug} not found")
+    };
+
+    let Some(branch) = branches::get_branch(project, branch_name) else {
+        bail!("Project branch {project_slug}/{branch_name} not found")
+    };
+
+    let PreparedVersion {
+        src_branch_name,
+        src_version,
+        diff,
+        file_path,
+        breaking_changes,
+    } = prepared;
+
     let next_id = branch
         .versions
         .iter()
@@ -68,8 +133,6 @@ async fn create_version_inner(
         .persist_version_diff(&state.storage, branch_name, next_id, &diff)
         .await?;
 
-    let file_path = project.persist_version(&state.storage, content).await?;
-
     let diff_file_version = diff.get_diff_version();
 
     let version = diff.info.get().and_then(|info| info.version.get().cloned());
@@ -82,17 +145,28 @@ async fn create_version_inner(
         message,
         file_path,
         statistics,
+        breaking_changes: Some(breaking_changes),
         diff_file_path,
         diff_file_version,
+        diff_file_format: DiffFileFormat::Compact,
         created_at: Utc::now(),
     };
 
-    branches::get_branch_mut(project, branch_name)
-        .expect("Must be created before this version")
-        .versions
-        .push(version.clone());
-
-    project.persist_branches(&state.storage).await?;
+    project
+        .update_branches(&state.storage, |branches| {
+            let Some(branch) = branches.iter_mut().find(|b| b.name == branch_name) else {
+                bail!("Project branch {project_slug}/{branch_name} not found")
+            };
+            if branch.versions.iter().any(|v| v.id == version.id) {
+                bail!(
+                    "Version {project_slug}/{branch_name}/{} was created concurrently",
+                    version.id
+                )
+            }
+            branch.versions.push(version.clone());
+            Ok(())
+        })
+        .await?;
 
     let src_version_id = src_version.as_ref().map(|v| v.id).unwrap_or(next_id);
 
@@ -144,80 +218,196 @@ fn get_source_version(
     Ok((base_branch.name.clone(), base_version.cloned()))
 }
 
+/// Creates the version, the state write lock is only taken to add the version and
+/// to update dependencies. Schemas are diffed and alerts are prepared under
+/// the read lock and sent after it is released.
+#[tracing::instrument(
+    name = "version.create",
+    skip_all,
+    fields(project = %project_slug, branch = branch_name)
+)]
 pub async fn create_version(
     settings: &Settings,
-    state: &mut AppState,
+    state: &AppStateType,
     project_slug: &ProjectSlug,
     branch_name: &str,
     message: Option<String>,
     content: &str,
 ) -> anyhow::Result<Option<Version>> {
-    let result = create_version_inner(state, project_slug, branch_name, message, content).await?;
-
-    let Some(result) = result else {
-        return Ok(None);
+    let mut attempt = 1;
+
+    let result = loop {
+        let prepared = {
+            let state = state.read().await;
+            prepare_version(&state, project_slug, branch_name, content).await?
+        };
+
+        let Some(prepared) = prepared else {
+            tracing::info!("Version skipped, schema has no changes");
+            return Ok(None);
+        };
+
+        let result = {
+            let mut state = state.write().await;
+            commit_version(
+                &mut state,
+                project_slug,
+                branch_name,
+                message.clone(),
+                prepared,
+            )
+            .await?
+        };
+
+        match result {
+            Some(result) => break result,
+            None if attempt < COMMIT_ATTEMPTS => {
+                tracing::info!("Branch was changed concurrently, diffing again");
+                attempt += 1;
+            }
+            None => bail!("Project branch {project_slug}/{branch_name} was changed concurrently"),
+        }
     };
 
-    let src_projects_slugs =
-        dependencies::update_dependent_projects(state, project_slug, Some(branch_name)).await?;
+    tracing::info!(version = result.version.id, "Version created");
 
-    //handle alerts
-    let project = state
-        .projects
-        .get(project_slug)
-        .expect("Project must not be removed during add version operation.");
+    let (dependents, updates, alerts) = {
+        let state = state.read().await;
 
-    let validations = validate(&result.diff, &["*"]);
+        let (dependents, updates) =
+            dependencies::get_dependency_updates(&state, project_slug, Some(branch_name)).await?;
 
-    // own alerts
-    if !project.alerts.is_empty() {
-        let alerts = alerts::get_own_alerts_info(
+        let alerts = get_version_alerts(
             settings,
-            project,
+            &state,
+            project_slug,
             branch_name,
-            result.version.id,
-            &result.diff,
-            &validations,
+            &result,
+            &dependents,
         )
         .await?;
-        for alert in alerts {
-            println!(
-                "Send own alert: {}/{} - {}",
-                project_slug, branch_name, alert.service
-            );
-            alerts::send_alert(alert).await?;
-        }
+
+        (dependents, updates, alerts)
+    };
+
+    if !dependents.is_empty() {
+        let mut state = state.write().await;
+        dependencies::apply_dependency_updates(&mut state, project_slug, updates);
+    }
+
+    notify_version_created(project_slug, branch_name, content, &result, alerts).await?;
+
+    Ok(Some(result.version))
+}
+
+/// Own alerts of the project and alerts of its dependents about the created version
+async fn get_version_alerts(
+    settings: &Settings,
+    state: &AppState,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    result: &CreatedVersion,
+    dependents: &[ProjectSlug],
+) -> anyhow::Result<Vec<AlertInfo>> {
+    let Some(project) = state.projects.get(project_slug) else {
+        bail!("Project {project_slug} not found")
     };
 
+    let validations = validate(&result.diff, &project.checkers());
+
+    // own alerts
+    let mut alerts = alerts::get_own_alerts_info(
+        settings,
+        project,
+        &state.alerts,
+        branch_name,
+        result.version.id,
+        &result.diff,
+        &validations,
+    )
+    .await?;
+
     // deps alerts
-    let dep_projects: Vec<_> = src_projects_slugs
+    let mut dep_projects = vec![];
+    for dep in dependents
         .iter()
         .filter_map(|slug| state.projects.get(slug))
-        .collect();
+    {
+        let usage = dep
+            .dependencies
+            .iter()
+            .find(|d| &d.project == project_slug && d.branch == branch_name)
+            .and_then(|d| d.usage.as_ref());
+
+        let used = match usage {
+            Some(usage) => {
+                Some(usage::used_operations(&state.storage, dep, usage, &result.diff).await?)
+            }
+            None => None,
+        };
+
+        dep_projects.push((dep, used));
+    }
     if !dep_projects.is_empty() {
-        let alerts = alerts::get_deps_alerts_info(
-            settings,
-            project,
-            &result.src_branch_name,
-            result.src_version_id,
-            branch_name,
-            result.version.id,
-            dep_projects,
-            &result.diff,
-            &validations,
-        )
-        .await?;
+        alerts.extend(
+            alerts::get_deps_alerts_info(
+                settings,
+                project,
+                &result.src_branch_name,
+                result.src_version_id,
+                branch_name,
+                result.version.id,
+                dep_projects,
+                &state.alerts,
+                &result.diff,
+                &validations,
+            )
+            .await?,
+        );
+    }
 
-        for alert in alerts {
-            println!(
-                "Send deps alert: {}/{} - {}",
-                project_slug, branch_name, alert.service
-            );
-            alerts::send_alert(alert).await?;
-        }
+    Ok(alerts)
+}
+
+/// Records metrics, publishes events and sends alerts of the created version
+async fn notify_version_created(
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    content: &str,
+    result: &CreatedVersion,
+    alerts: Vec<AlertInfo>,
+) -> anyhow::Result<()> {
+    let breaking_changes = result.version.breaking_changes.unwrap_or_default() as usize;
+
+    metrics::record_version(project_slug, content.len(), breaking_changes);
+
+    events::publish(Event::VersionCreated {
+        project: project_slug.clone(),
+        branch: branch_name.to_owned(),
+        version: result.version.id,
+        version_name: result.version.version.clone(),
+        statistics: result.version.statistics.clone(),
+    });
+    if breaking_changes > 0 {
+        events::publish(Event::BreakingChanges {
+            project: project_slug.clone(),
+            branch: branch_name.to_owned(),
+            version: result.version.id,
+            breaking_changes,
+        });
     }
 
-    Ok(Some(result.version))
+    for alert in alerts {
+        tracing::info!(
+            "Send alert: {}/{} - {}",
+            project_slug,
+            branch_name,
+            alert.service
+        );
+        alerts::send_alert(project_slug, alert).await?;
+    }
+
+    Ok(())
 }
 
 pub async fn compare_versions(
@@ -256,6 +446,190 @@ pub async fn compare_versions(
     Ok(diff)
 }
 
+/// Full diffs are large, so only a few recently requested ones are kept
+const DIFF_CACHE_CAPACITY: usize = 32;
+
+/// Schema files are content addressed, so cached diffs never get stale
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct DiffCacheKey {
+    src_file_path: String,
+    tgt_file_path: String,
+}
+
+/// Full diffs recomputed for versions stored with compact ones, entries are ordered from
+/// the least recently used
+#[derive(Debug, Default)]
+pub struct DiffCache(Mutex<IndexMap<DiffCacheKey, Arc<Vec<u8>>>>);
+
+impl DiffCache {
+    fn get(&self, key: &DiffCacheKey) -> Option<Arc<Vec<u8>>> {
+        let mut cache = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let (key, content) = cache.shift_remove_entry(key)?;
+        cache.insert(key, content.clone());
+        Some(content)
+    }
+
+    fn set(&self, key: DiffCacheKey, content: Arc<Vec<u8>>) {
+        let mut cache = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        if cache.len() >= DIFF_CACHE_CAPACITY {
+            cache.shift_remove_index(0);
+        }
+        cache.insert(key, content);
+    }
+}
+
+/// Returns version diff file content. Full diffs of versions stored with compact ones are not
+/// restored from the stored diff, they are recomputed by comparing the version schema with
+/// its source version schema, so they reflect the current differ and not `diff_file_version`
+pub async fn get_version_diff_content(
+    state: &AppState,
+    project_slug: &ProjectSlug,
+    branch_name: &str,
+    id: u32,
+    compact: bool,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(version) = versions::crud::get_version(state, project_slug, branch_name, id) else {
+        return Ok(None);
+    };
+
+    let content = state.storage.read_file(&version.diff_file_path).await?;
+
+    let content = match (version.diff_file_format, compact) {
+        (DiffFileFormat::Full, false) | (DiffFileFormat::Compact, true) => content,
+        (DiffFileFormat::Full, true) => {
+            let diff: serde_json::Value = serde_json::from_slice(&content)?;
+            serde_json::to_vec(&schemadoc_diff::compact::compact(&diff)?)?
+        }
+        (DiffFileFormat::Compact, false) => {
+            let src_version =
+                versions::crud::get_source_version(state, project_slug, branch_name, id);
+
+            let src_file_path = src_version
+                .map(|v| &v.file_path)
+                .unwrap_or(&version.file_path);
+
+            let key = DiffCacheKey {
+                src_file_path: src_file_path.clone(),
+                tgt_file_path: version.file_path.clone(),
+            };
+            if let Some(content) = state.diff_cache.get(&key) {
+                return Ok(Some(content.to_vec()));
+            }
+
+            let src_schema_content = state.storage.read_file(src_file_path).await?;
+            let tgt_schema_content = state.storage.read_file(&version.file_path).await?;
+
+            let diff = compare_schemas_content(
+                &String::from_utf8_lossy(&src_schema_content),
+                &String::from_utf8_lossy(&tgt_schema_content),
+            )?;
+
+            let content = serde_json::to_vec(&diff.get())?;
+            state.diff_cache.set(key, Arc::new(content.clone()));
+            content
+        }
+    };
+
+    Ok(Some(content))
+}
+
+/// Number of breaking changes found by the checkers
+pub fn count_breaking_changes(diff: &HttpSchemaDiff, checkers: &[&str]) -> u32 {
+    validate(diff, checkers)
+        .iter()
+        .filter(|v| v.breaking)
+        .count() as u32
+}
+
+/// Computes and persists breaking changes of versions created before they were stored,
+/// so they are computed once per version
+pub async fn backfill_breaking_changes(
+    state: &mut AppState,
+    project_slug: &ProjectSlug,
+) -> anyhow::Result<()> {
+    let Some(project) = state.projects.get(project_slug) else {
+        return Ok(());
+    };
+
+    let mut computed = vec![];
+    for branch in &project.branches {
+        for version in branch
+            .versions
+            .iter()
+            .filter(|v| v.breaking_changes.is_none())
+        {
+            match compute_breaking_changes(state, project, &branch.name, version).await {
+                Ok(count) => computed.push((branch.name.clone(), version.id, count)),
+                Err(err) => tracing::warn!(
+                    "Could not compute breaking changes of {}/{} version {}: {}",
+                    project_slug,
+                    branch.name,
+                    version.id,
+                    err
+                ),
+            }
+        }
+    }
+
+    if computed.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!(
+        "{} - Stored breaking changes of {} versions",
+        project_slug,
+        computed.len()
+    );
+
+    let storage = state.storage.clone();
+    let project = state
+        .projects
+        .get_mut(project_slug)
+        .expect("Project is checked above");
+
+    project
+        .update_branches(&storage, |branches| {
+            for (branch_name, id, count) in &computed {
+                let version = branches
+                    .iter_mut()
+                    .filter(|b| &b.name == branch_name)
+                    .flat_map(|b| b.versions.iter_mut())
+                    .find(|v| v.id == *id);
+                if let Some(version) = version {
+                    version.breaking_changes.get_or_insert(*count);
+                }
+            }
+            Ok(())
+        })
+        .await
+}
+
+async fn compute_breaking_changes(
+    state: &AppState,
+    project: &Project,
+    branch_name: &str,
+    version: &Version,
+) -> anyhow::Result<u32> {
+    let src_version =
+        versions::crud::get_source_version(state, &project.slug, branch_name, version.id);
+    let src_file_path = src_version
+        .map(|v| &v.file_path)
+        .unwrap_or(&version.file_path);
+
+    let src_schema_content = state.storage.read_file(src_file_path).await?;
+    let tgt_schema_content = state.storage.read_file(&version.file_path).await?;
+
+    let diff = compare_schemas_content(
+        &String::from_utf8_lossy(&src_schema_content),
+        &String::from_utf8_lossy(&tgt_schema_content),
+    )?;
+
+    Ok(diff
+        .get()
+        .map(|diff| count_breaking_changes(diff, &project.checkers()))
+        .unwrap_or(0))
+}
+
 pub fn compare_schemas_content(
     src_schema_content: &str,
     tgt_schema_content: &str,
@@ -267,3 +641,108 @@ pub fn compare_schemas_content(
 
     Ok(diff)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::models::ProjectSlug;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::versions::services::{create_version, get_version_diff_content};
+
+    #[tokio::test]
+    async fn test_version_diff_content() {
+        let location = std::env::temp_dir().join(format!("sd-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            "version: \"0.1\"\nprojects:\n  payments:\n    name: Payments\n",
+        )
+        .unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage, None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+        let slug = ProjectSlug::new("payments".to_owned());
+
+        for path in ["/users", "/orders"] {
+            let content = format!(
+                r#"{{"openapi": "3.0.0", "info": {{"title": "Payments", "version": "1"}},
+                    "paths": {{"{path}": {{"get": {{"responses": {{}}}}}}}}}}"#
+            );
+            create_version(&settings, &state, &slug, "main", None, &content)
+                .await
+                .unwrap();
+        }
+        let state = state.into_inner();
+
+        let compact = get_version_diff_content(&state, &slug, "main", 1, true)
+            .await
+            .unwrap()
+            .unwrap();
+        let full = get_version_diff_content(&state, &slug, "main", 1, false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(full.len() > compact.len());
+
+        // The recomputed diff is cached and served as is
+        assert_eq!(state.diff_cache.0.lock().unwrap().len(), 1);
+        let cached = get_version_diff_content(&state, &slug, "main", 1, false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached, full);
+        assert_eq!(state.diff_cache.0.lock().unwrap().len(), 1);
+
+        assert!(get_version_diff_content(&state, &slug, "main", 5, false)
+            .await
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_version_breaking_changes_rules() {
+        let location = std::env::temp_dir().join(format!("sd-rules-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            "version: \"0.1\"\nprojects:\n  payments:\n    name: Payments\n  \
+             orders:\n    name: Orders\n    rules: [removed-media-type]\n",
+        )
+        .unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = RwLock::new(AppState::read(storage, None).await.unwrap());
+        let settings = Settings::from_env().unwrap();
+
+        for slug in ["payments", "orders"] {
+            let slug = ProjectSlug::new(slug.to_owned());
+            for paths in [r#""/users": {"get": {"responses": {}}}"#, ""] {
+                let content = format!(
+                    r#"{{"openapi": "3.0.0", "info": {{"title": "API", "version": "1"}},
+                        "paths": {{{paths}}}}}"#
+                );
+                create_version(&settings, &state, &slug, "main", None, &content)
+                    .await
+                    .unwrap();
+            }
+        }
+        let state = state.into_inner();
+
+        // Removed operation is breaking only for the project checking it
+        let breaking_changes = |slug: &str| {
+            let slug = ProjectSlug::new(slug.to_owned());
+            let versions = crate::versions::crud::get_versions(&state, &slug, "main").unwrap();
+            versions[1].breaking_changes
+        };
+        assert_eq!(breaking_changes("payments"), Some(1));
+        assert_eq!(breaking_changes("orders"), Some(0));
+
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{error, get, web, FromRequest, HttpMessage, HttpRequest};
+use std::future::{ready, Ready};
+
+use crate::audit::{self, AuditOutcome, AuditQuery, AuditTarget};
+use crate::models::ProjectSlug;
+use crate::tokens::Permission;
+use crate::web::auth::Auth;
+use crate::web::response::ApiResponse;
+use crate::web::AppStateType;
+
+/// Audited endpoints by method and route pattern
+const AUDITED_ACTIONS: &[(&str, &str, &str)] = &[
+    (
+        "POST",
+        "/api/v1/common/invalidate-app-state",
+        "state.invalidate",
+    ),
+    ("POST", "/api/v1/projects", "project.create"),
+    ("POST", "/api/v1/projects/import", "project.import"),
+    ("PUT", "/api/v1/projects/{slug}", "project.update"),
+    ("PATCH", "/api/v1/projects/{slug}", "project.patch"),
+    ("DELETE", "/api/v1/projects/{slug}", "project.delete"),
+    ("POST", "/api/v1/projects/{slug}/pull", "project.pull"),
+    (
+        "POST",
+        "/api/v1/projects/{slug}/retention",
+        "project.retention",
+    ),
+    (
+        "POST",
+        "/api/v1/projects/{project_slug}/branches",
+        "branch.create",
+    ),
+    (
+        "DELETE",
+        "/api/v1/projects/{project_slug}/branches/{branch_name}",
+        "branch.delete",
+    ),
+    (
+        "POST",
+        "/api/v1/projects/{project_slug}/branches/{branch_name}/versions",
+        "version.create",
+    ),
+    ("POST", "/api/v1/tokens", "token.create"),
+    ("DELETE", "/api/v1/tokens/{name}", "token.revoke"),
+];
+
+/// Authorized identity of the request, set by `Auth` extractor
+#[derive(Clone)]
+pub struct AuditActor(pub String);
+
+/// Overrides the audit target taken from the request path, e.g. with a created version id
+pub struct Audit(HttpRequest);
+
+impl Audit {
+    pub fn target(&self, target: AuditTarget) {
+        self.0.extensions_mut().insert(target);
+    }
+}
+
+impl FromRequest for Audit {
+    type Error = error::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Audit(req.clone())))
+    }
+}
+
+fn path_target(req: &HttpRequest) -> AuditTarget {
+    let info = req.match_info();
+    AuditTarget {
+        project: info
+            .get("slug")
+            .or(info.get("project_slug"))
+            .map(|slug| ProjectSlug::new(slug.to_owned())),
+        branch: info.get("branch_name").map(str::to_owned),
+        version: None,
+        details: info.get("name").map(str::to_owned),
+    }
+}
+
+/// Records outcomes of audited endpoints, including denied and not found requests
+pub async fn record_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, error::Error> {
+    let res = next.call(req).await?;
+
+    let req = res.request();
+    let action = req.match_pattern().and_then(|pattern| {
+        AUDITED_ACTIONS
+            .iter()
+            .find(|(method, path, _)| *method == req.method().as_str() && *path == pattern)
+            .map(|(_, _, action)| *action)
+    });
+    let Some(action) = action else {
+        return Ok(res);
+    };
+
+    let outcome = match res.status() {
+        status if status.is_success() => AuditOutcome::Success,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AuditOutcome::Denied,
+        _ => AuditOutcome::Failure,
+    };
+    let error = res.response().error().map(|err| err.to_string());
+
+    let (actor, target) = {
+        let extensions = req.extensions();
+        let actor = extensions
+            .get::<AuditActor>()
+            .map(|actor| actor.0.clone())
+            .unwrap_or_else(|| "anonymous".to_owned());
+        let target = extensions.get::<AuditTarget>().cloned();
+        (actor, target.unwrap_or_else(|| path_target(req)))
+    };
+
+    if let Some(state) = req.app_data::<web::Data<AppStateType>>() {
+        let storage = state.read().await.storage.clone();
+        audit::append(&storage, actor, action, target, outcome, error).await;
+    }
+
+    Ok(res)
+}
+
+#[get("")]
+async fn list_audit_entries_endpoint(
+    auth: Auth,
+    query: web::Query<AuditQuery>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::Admin, None)?;
+
+    let storage = state.read().await.storage.clone();
+
+    let entries = audit::query(&storage, &query)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok((&entries,).into())
+}
+
+pub fn get_audit_api_scope() -> actix_web::Scope {
+    web::scope("audit").service(list_audit_entries_endpoint)
+}
@@ -1,7 +1,18 @@
-use actix_web::dev::Payload;
-use actix_web::{error, FromRequest, HttpRequest};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{error, web, FromRequest, HttpMessage, HttpRequest};
 use base64::Engine;
-use std::future::{ready, Ready};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+use crate::jwt::{JwtPrincipal, JwtVerifier};
+use crate::models::ProjectSlug;
+use crate::settings::Settings;
+use crate::tokens::{self, ApiToken, Permission, TokensCache};
+use crate::web::audit::AuditActor;
+use crate::web::AppStateType;
 
 pub struct BasicAuth;
 
@@ -80,3 +91,126 @@ impl FromRequest for BasicAuth {
         ready(req.try_into())
     }
 }
+
+/// Basic authorization credentials, API token or JWT. Basic authorization grants any permission.
+pub enum Auth {
+    Basic,
+    Token(ApiToken),
+    Jwt(JwtPrincipal),
+}
+
+impl Auth {
+    /// Identity recorded in the audit log
+    pub fn actor(&self) -> String {
+        match self {
+            Auth::Basic => "basic".to_owned(),
+            Auth::Token(token) => format!("token:{}", token.name),
+            Auth::Jwt(principal) => format!("jwt:{}", principal.subject),
+        }
+    }
+
+    pub fn require(
+        &self,
+        permission: Permission,
+        project: Option<&ProjectSlug>,
+    ) -> error::Result<()> {
+        let (principal, allowed) = match self {
+            Auth::Basic => return Ok(()),
+            Auth::Token(token) => (
+                format!("Token {}", token.name),
+                token.allows(permission, project),
+            ),
+            Auth::Jwt(principal) => (
+                format!("JWT subject {}", principal.subject),
+                principal.allows(permission, project),
+            ),
+        };
+
+        if allowed {
+            return Ok(());
+        }
+
+        Err(error::ErrorForbidden(format!(
+            "{} has no {} permission{}",
+            principal,
+            permission.as_str(),
+            project
+                .map(|p| format!(" for project {p}"))
+                .unwrap_or_default()
+        )))
+    }
+}
+
+impl FromRequest for Auth {
+    type Error = error::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let request = req.clone();
+        let authorize = Self::authorize(req);
+        Box::pin(async move {
+            let auth = authorize.await?;
+            request.extensions_mut().insert(AuditActor(auth.actor()));
+            Ok(auth)
+        })
+    }
+}
+
+impl Auth {
+    fn authorize(req: &HttpRequest) -> <Self as FromRequest>::Future {
+        let bearer = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().strip_prefix("Bearer "))
+            .map(|token| token.trim().to_owned());
+
+        let Some(bearer) = bearer else {
+            let result = BasicAuth::try_from(req).map(|_| Auth::Basic);
+            return Box::pin(ready(result));
+        };
+
+        let state = req.app_data::<web::Data<AppStateType>>().cloned();
+        let cache = req.app_data::<web::Data<TokensCache>>().cloned();
+        let verifier = req.app_data::<web::Data<JwtVerifier>>().cloned();
+
+        Box::pin(async move {
+            if !bearer.starts_with(tokens::TOKEN_PREFIX) {
+                let verifier = verifier.ok_or(error::ErrorUnauthorized("Invalid token"))?;
+                let principal = verifier
+                    .verify(&bearer)
+                    .await
+                    .map_err(|err| error::ErrorUnauthorized(format!("Invalid JWT: {err}")))?;
+                return Ok(Auth::Jwt(principal));
+            }
+
+            let (Some(state), Some(cache)) = (state, cache) else {
+                return Err(error::ErrorInternalServerError("State is not configured"));
+            };
+            let storage = state.read().await.storage.clone();
+
+            let token = cache
+                .find_token(&storage, &bearer)
+                .await
+                .map_err(error::ErrorUnauthorized)?;
+
+            Ok(Auth::Token(token))
+        })
+    }
+}
+
+/// Requires authorization for read requests if it is enabled in settings
+pub async fn protect_read(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, error::Error> {
+    let protect_read = req
+        .app_data::<web::Data<Settings>>()
+        .is_some_and(|settings| settings.protect_read);
+
+    if protect_read && matches!(*req.method(), Method::GET | Method::HEAD) {
+        req.extract::<Auth>().await?;
+    }
+
+    next.call(req).await
+}
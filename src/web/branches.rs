@@ -3,8 +3,11 @@ use actix_web::{delete, error, post, web, HttpResponse};
 use serde::Deserialize;
 use std::ops::DerefMut;
 
+use crate::audit::AuditTarget;
 use crate::branches;
-use crate::web::auth::BasicAuth;
+use crate::tokens::Permission;
+use crate::web::audit::Audit;
+use crate::web::auth::Auth;
 use crate::web::response::ApiResponse;
 
 #[derive(Deserialize, Debug)]
@@ -17,7 +20,8 @@ struct CreateBranchBody {
 
 #[post("")]
 async fn create_branch_endpoint(
-    _: BasicAuth,
+    auth: Auth,
+    audit: Audit,
     body: web::Json<CreateBranchBody>,
     path: web::Path<crate::models::ProjectSlug>,
     state: web::Data<crate::web::AppStateType>,
@@ -25,6 +29,9 @@ async fn create_branch_endpoint(
     let body = body.into_inner();
     let project_slug = path.as_ref();
 
+    audit.target(AuditTarget::branch(project_slug, &body.name));
+    auth.require(Permission::VersionsWrite, Some(project_slug))?;
+
     let mut state = state.write().await;
 
     let branch = branches::create_branch_if_not_exists(
@@ -49,17 +56,23 @@ struct DeleteBranchQuery {
 
 #[delete("/{branch_name}")]
 async fn delete_branch_endpoint(
-    _: BasicAuth,
+    auth: Auth,
+    audit: Audit,
     path: web::Path<(crate::models::ProjectSlug, String)>,
     query: web::Query<DeleteBranchQuery>,
     state: web::Data<crate::web::AppStateType>,
 ) -> Result<impl actix_web::Responder, error::Error> {
     let (project_slug, branch_name) = &path.into_inner();
 
-    let mut state = state.write().await;
-
     let force = query.force.unwrap_or(false);
 
+    audit.target(
+        AuditTarget::branch(project_slug, branch_name).with_details(format!("force={force}")),
+    );
+    auth.require(Permission::VersionsWrite, Some(project_slug))?;
+
+    let mut state = state.write().await;
+
     branches::delete_branch(state.deref_mut(), project_slug, branch_name, force, true)
         .await
         .map_err(error::ErrorBadRequest)?;
@@ -1,21 +1,27 @@
 use crate::app_state::AppState;
-use crate::web::auth::BasicAuth;
+use crate::tokens::{Permission, TokensCache};
+use crate::web::auth::Auth;
 use crate::web::AppStateType;
 use actix_web::{error, post, web};
 use std::ops::DerefMut;
 
 #[post("/invalidate-app-state")]
 async fn invalidate_app_state_endpoint(
-    _: BasicAuth,
+    auth: Auth,
     state: web::Data<AppStateType>,
+    cache: web::Data<TokensCache>,
 ) -> Result<&'static str, error::Error> {
+    auth.require(Permission::Admin, None)?;
+
     let mut lock = state.write().await;
 
     let state = lock.deref_mut();
 
-    *state = AppState::read(state.storage.clone(), state.config_storage.clone())
-        .await
-        .map_err(|_| error::ErrorInternalServerError("Error reading store"))?;
+    let result = AppState::read(state.storage.clone(), state.config_storage.clone()).await;
+
+    *state = result
+        .map_err(|err| error::ErrorInternalServerError(format!("Error reading store: {err}")))?;
+    cache.invalidate().await;
 
     Ok("Invalidated")
 }
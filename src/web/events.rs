@@ -0,0 +1,49 @@
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse};
+use futures_util::stream;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::{self, EventsFilter};
+
+/// Comment sent to idle clients, so proxies do not close the connection
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Streams events as Server-Sent Events, filtered by `project` and `type` query parameters
+#[get("")]
+async fn stream_events_endpoint(filter: web::Query<EventsFilter>) -> HttpResponse {
+    let receiver = events::subscribe();
+    let filter = filter.into_inner();
+
+    let events = stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        let message = loop {
+            match tokio::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Err(_) => break ": keep-alive\n\n".to_owned(),
+                Ok(Ok(event)) if filter.matches(&event) => break event.to_sse(),
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    break format!("event: lagged\ndata: {{\"skipped\":{skipped}}}\n\n")
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            }
+        };
+
+        Some((
+            Ok::<_, Infallible>(Bytes::from(message)),
+            (receiver, filter),
+        ))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression middleware buffers the stream, so it is disabled
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+        .streaming(events)
+}
+
+pub fn get_events_api_scope() -> actix_web::Scope {
+    web::scope("events").service(stream_events_endpoint)
+}
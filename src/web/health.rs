@@ -0,0 +1,109 @@
+use actix_web::{get, web, HttpResponse};
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::app_state::CONFIG_FILE_NAME;
+use crate::storage::Storer;
+use crate::web::AppStateType;
+
+#[get("/healthz")]
+async fn healthz_endpoint() -> HttpResponse {
+    HttpResponse::Ok().body("OK")
+}
+
+/// Ready if the persistence is reachable. The state always has a loaded `schemadoc.yaml`,
+/// a failed reload keeps the previous configuration, so it is reported without failing the check.
+#[get("/readyz")]
+async fn readyz_endpoint(state: web::Data<AppStateType>) -> HttpResponse {
+    let state = state.read().await;
+
+    if !matches!(state.storage.exists("").await, Ok(true)) {
+        return HttpResponse::ServiceUnavailable().body("Persistence is not reachable");
+    }
+
+    let loaded_at = state.config_loaded_at.to_rfc3339();
+    match &state.config_reload_error {
+        None => HttpResponse::Ok().body(format!("OK, {CONFIG_FILE_NAME} loaded at {loaded_at}")),
+        Some(err) => HttpResponse::Ok().body(format!(
+            "OK, {CONFIG_FILE_NAME} loaded at {loaded_at}, its last reload failed: {err}"
+        )),
+    }
+}
+
+#[get("/metrics")]
+async fn metrics_endpoint(handle: web::Data<PrometheusHandle>) -> HttpResponse {
+    handle.run_upkeep();
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(handle.render())
+}
+
+pub fn configure_health_services(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz_endpoint)
+        .service(readyz_endpoint)
+        .service(metrics_endpoint);
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::config_reload::reload_config;
+    use crate::metrics;
+    use crate::models::ProjectSlug;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::web::health::configure_health_services;
+
+    #[actix_web::test]
+    async fn test_health_and_metrics() {
+        let location = std::env::temp_dir().join(format!("sd-health-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(
+            location.join("schemadoc.yaml"),
+            "version: \"0.1\"\nprojects: {}\n",
+        )
+        .unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = web::Data::new(RwLock::new(AppState::read(storage, None).await.unwrap()));
+
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = web::Data::new(recorder.handle());
+        ::metrics::with_local_recorder(&recorder, || {
+            metrics::record_version(&ProjectSlug::new("payments".to_owned()), 512, 2)
+        });
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .app_data(handle)
+                .configure(configure_health_services),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let body = test::call_and_read_body(&app, get("/healthz")).await;
+        assert_eq!(body, "OK");
+
+        let body = test::call_and_read_body(&app, get("/readyz")).await;
+        assert!(body.starts_with(b"OK, schemadoc.yaml loaded at "));
+
+        // Readiness tracks the loaded configuration, not the file
+        std::fs::write(location.join("schemadoc.yaml"), "projects: [").unwrap();
+        assert!(reload_config(&mut *state.write().await).await.is_err());
+        let res = test::call_service(&app, get("/readyz")).await;
+        assert!(res.status().is_success());
+        let body = test::read_body(res).await;
+        assert!(String::from_utf8_lossy(&body).contains("its last reload failed"));
+
+        let body = test::call_and_read_body(&app, get("/metrics")).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains(r#"schemadoc_breaking_changes_total{project="payments"} 2"#));
+        assert!(body.contains(r#"schemadoc_spec_size_bytes{project="payments"} 512"#));
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{error, post, web, HttpRequest};
+
+use crate::audit::{self, AuditTarget};
+use crate::datasources::{self, PullLocks};
+use crate::hooks::{self, HookProvider};
+use crate::secrets;
+use crate::settings::Settings;
+use crate::web::response::ApiResponse;
+use crate::web::schema::HookTargetOut;
+use crate::web::AppStateType;
+
+/// Verifies the push webhook and starts pulls of the matching data sources
+#[post("/{provider}")]
+async fn receive_hook_endpoint(
+    path: web::Path<String>,
+    req: HttpRequest,
+    body: Bytes,
+    settings: web::Data<Settings>,
+    locks: web::Data<PullLocks>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    let provider: HookProvider = path.parse().map_err(error::ErrorNotFound)?;
+
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+
+    let event = provider.event_header().and_then(header);
+    // Hook secrets are known only after the pushed repository is matched
+    let push = provider
+        .parse_push(event, &body)
+        .map_err(error::ErrorBadRequest)?;
+
+    let (targets, storage) = {
+        let state = state.read().await;
+        (
+            hooks::find_targets(&state, push.as_ref()),
+            state.storage.clone(),
+        )
+    };
+
+    // Unknown repositories are rejected as invalid signatures, so configured ones could not be probed
+    let global_secret = settings.hook_secret.as_deref();
+    let signature = header(provider.signature_header());
+    let verify = |secret: Option<&str>| {
+        secret
+            .or(global_secret)
+            .is_some_and(|secret| provider.verify(secret, &body, signature))
+    };
+
+    let targets = targets
+        .into_iter()
+        .filter(|target| verify(target.secret.as_deref()))
+        .collect::<Vec<_>>();
+
+    if targets.is_empty() && !verify(None) {
+        return Err(error::ErrorUnauthorized("Invalid webhook signature"));
+    }
+
+    let Some(push) = push else {
+        return Ok(("Ignored",).into());
+    };
+
+    tracing::info!(
+        provider = provider.as_str(),
+        repository = %push.repository,
+        git_ref = %push.git_ref,
+        targets = targets.len(),
+        "Webhook received"
+    );
+
+    for target in &targets {
+        let settings = settings.clone();
+        let locks = locks.clone();
+        let state = state.clone();
+        let storage = storage.clone();
+        let project_slug = target.project.clone();
+        let branch_name = target.branch.clone();
+
+        tokio::spawn(async move {
+            let result = datasources::pull_project_datasource(
+                &settings,
+                &locks,
+                &state,
+                &project_slug,
+                &branch_name,
+            )
+            .await;
+
+            if let Err(err) = &result {
+                tracing::error!(
+                    project = %project_slug,
+                    error = %secrets::redact(&err.to_string()),
+                    "Webhook pull failed"
+                );
+            }
+
+            audit::record(
+                &storage,
+                format!("hook:{}", provider.as_str()),
+                "project.pull",
+                AuditTarget::branch(&project_slug, &branch_name),
+                &result,
+            )
+            .await;
+        });
+    }
+
+    let result = targets
+        .iter()
+        .map(|target| HookTargetOut {
+            project: &target.project,
+            branch: &target.branch,
+        })
+        .collect::<Vec<_>>();
+
+    Ok((result, StatusCode::ACCEPTED).into())
+}
+
+pub fn get_hooks_scope() -> actix_web::Scope {
+    web::scope("/hooks").service(receive_hook_endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use ring::hmac;
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::datasources::PullLocks;
+    use crate::settings::Settings;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::web::hooks::get_hooks_scope;
+
+    const CONFIG: &str = r#"
+version: "0.1"
+projects:
+  payments:
+    name: Payments
+    data_sources:
+      - name: Payments spec
+        source: !Url { url: http://localhost:1/openapi.json }
+        hook: { repository: shop/payments, secret: s3cret }
+"#;
+
+    #[actix_web::test]
+    async fn test_receive_hook_unauthorized() {
+        let location = std::env::temp_dir().join(format!("sd-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = web::Data::new(RwLock::new(AppState::read(storage, None).await.unwrap()));
+        let mut settings = Settings::from_env().unwrap();
+        settings.hook_secret = None;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .app_data(web::Data::new(settings))
+                .app_data(web::Data::new(PullLocks::new(1)))
+                .service(get_hooks_scope()),
+        )
+        .await;
+
+        let status = |repository: &str, secret: &str| {
+            let body = format!(r#"{{"repository": "{repository}", "ref": "main"}}"#);
+            let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+            let tag = hmac::sign(&key, body.as_bytes());
+            let signature: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+            let request = test::TestRequest::post()
+                .uri("/hooks/generic")
+                .insert_header(("X-Signature-256", format!("sha256={signature}")))
+                .set_payload(body)
+                .to_request();
+            let response = test::call_service(&app, request);
+            async { response.await.status().as_u16() }
+        };
+
+        // Unknown repository is not distinguishable from the invalid signature
+        assert_eq!(status("shop/payments", "wrong").await, 401);
+        assert_eq!(status("shop/orders", "s3cret").await, 401);
+        assert_eq!(status("shop/orders", "wrong").await, 401);
+
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+}
@@ -1,9 +1,16 @@
+mod audit;
 pub mod auth;
 mod branches;
 mod common;
+mod events;
+mod health;
+mod hooks;
+mod pagination;
 mod projects;
+mod request_id;
 pub mod response;
 pub mod schema;
+mod tokens;
 mod versions;
 
 use actix_cors::Cors;
@@ -11,13 +18,28 @@ use actix_files::{Files, NamedFile};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::web::PayloadConfig;
 use actix_web::{error, middleware, web, App, HttpResponse, HttpServer};
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tracing_actix_web::TracingLogger;
 
 use crate::app_state::AppState;
+use crate::config_reload;
+use crate::datasources::PullLocks;
+use crate::jwt::JwtVerifier;
+use crate::metrics;
+use crate::scheduler;
 use crate::settings::Settings;
+use crate::telemetry;
+use crate::tokens::TokensCache;
+use crate::web::audit::get_audit_api_scope;
 use crate::web::branches::get_branches_api_scope;
 use crate::web::common::get_common_api_scope;
+use crate::web::events::get_events_api_scope;
+use crate::web::health::configure_health_services;
+use crate::web::hooks::get_hooks_scope;
 use crate::web::projects::get_projects_api_scope;
+use crate::web::request_id::request_id_header;
+use crate::web::tokens::get_tokens_api_scope;
 use crate::web::versions::get_versions_api_scope;
 
 pub type AppStateType = RwLock<AppState>;
@@ -41,12 +63,42 @@ fn get_ui_service(settings: &Settings) -> Files {
         .default_handler(move |req| default_handler(req, index_path.clone()))
 }
 
-pub async fn serve(host: &str, port: u16) -> anyhow::Result<()> {
+pub async fn serve(host: &str, port: u16, schedule: bool) -> anyhow::Result<()> {
     let settings = web::Data::new(Settings::from_env()?);
 
+    let _telemetry = telemetry::init(&settings)?;
+
+    let metrics_handle = web::Data::new(metrics::install_recorder()?);
+
     let state = AppState::from_settings(&settings).await?;
     let state = web::Data::new(RwLock::new(state));
 
+    if settings.config_reload_interval_seconds != 0 {
+        let interval = Duration::from_secs(settings.config_reload_interval_seconds);
+        let state = state.clone().into_inner();
+        tokio::spawn(async move {
+            if let Err(err) = config_reload::watch_config(state, interval).await {
+                tracing::error!(error = ?err, "Config watcher exited");
+            }
+        });
+    }
+
+    let jwt_verifier = settings
+        .jwt
+        .clone()
+        .map(|jwt| web::Data::new(JwtVerifier::new(jwt)));
+
+    let pull_locks = web::Data::new(PullLocks::new(settings.pull_concurrency));
+    let tokens_cache = web::Data::new(TokensCache::default());
+
+    if schedule {
+        tokio::spawn(scheduler::run(
+            settings.clone().into_inner(),
+            pull_locks.clone().into_inner(),
+            state.clone().into_inner(),
+        ));
+    }
+
     HttpServer::new(move || {
         let cors = Cors::permissive();
 
@@ -56,7 +108,7 @@ pub async fn serve(host: &str, port: u16) -> anyhow::Result<()> {
             //     mime.type_() == Mime::TEXT && mime.subtype() == mime::PLAIN
             // })
             .error_handler(|err, _req| {
-                dbg!(&err);
+                tracing::warn!(error = %err, "Invalid JSON body");
                 // TODO: Handle different kinds of json errors
                 error::InternalError::from_response(err, HttpResponse::Conflict().finish()).into()
             });
@@ -64,19 +116,36 @@ pub async fn serve(host: &str, port: u16) -> anyhow::Result<()> {
         let payload_config = PayloadConfig::default().limit(10 * 1024 * 1024);
 
         App::new()
+            .wrap(middleware::from_fn(request_id_header))
+            .wrap(TracingLogger::default())
             .wrap(cors)
             .wrap(middleware::Compress::default())
             .app_data(state.clone())
             .app_data(settings.clone())
+            .app_data(pull_locks.clone())
+            .app_data(tokens_cache.clone())
+            .app_data(metrics_handle.clone())
             .app_data(json_config)
             .app_data(payload_config)
+            .configure(|cfg| {
+                if let Some(jwt_verifier) = &jwt_verifier {
+                    cfg.app_data(jwt_verifier.clone());
+                }
+            })
             .service(
                 web::scope("/api/v1")
+                    .wrap(middleware::from_fn(auth::protect_read))
+                    .wrap(middleware::from_fn(audit::record_requests))
                     .service(get_common_api_scope())
                     .service(get_versions_api_scope())
                     .service(get_branches_api_scope())
-                    .service(get_projects_api_scope()),
+                    .service(get_projects_api_scope())
+                    .service(get_tokens_api_scope())
+                    .service(get_audit_api_scope())
+                    .service(get_events_api_scope()),
             )
+            .service(get_hooks_scope())
+            .configure(configure_health_services)
             .service(get_ui_service(&settings))
     })
     .bind((host, port))?
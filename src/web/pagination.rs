@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Metadata of a list response page, `total` is the number of items matching the filters
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PageMeta {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Returns `limit` items starting from `offset`, all the remaining items if `limit` is not set
+pub fn paginate<T>(
+    items: Vec<T>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> (Vec<T>, PageMeta) {
+    let meta = PageMeta {
+        total: items.len(),
+        offset: offset.unwrap_or(0),
+        limit,
+    };
+
+    let page = items
+        .into_iter()
+        .skip(meta.offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    (page, meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web::pagination::{paginate, PageMeta};
+
+    #[test]
+    fn test_paginate() {
+        let (page, meta) = paginate((0..10).collect(), Some(8), Some(5));
+        assert_eq!(page, vec![8, 9]);
+        assert_eq!(
+            meta,
+            PageMeta {
+                total: 10,
+                offset: 8,
+                limit: Some(5)
+            }
+        );
+
+        let (page, _) = paginate((0..3).collect::<Vec<_>>(), None, None);
+        assert_eq!(page, vec![0, 1, 2]);
+    }
+}
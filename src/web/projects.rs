@@ -1,26 +1,186 @@
-use actix_web::{error, get, post, web};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{delete, error, get, patch, post, put, web, Either, HttpResponse};
+use indexmap::IndexMap;
 use serde::Deserialize;
 use std::ops::DerefMut;
 
-use crate::datasources;
-use crate::models::{Dependency, ProjectSlug};
+use crate::app_state::ProjectConfig;
+use crate::archive::{self, ExportError, ProjectArchiveFile};
+use crate::audit::AuditTarget;
+use crate::compatibility::{self, CompatibilityError};
+use crate::datasources::{self, PullLocks};
+use crate::graph::{self, DependencyGraph, ImpactError};
+use crate::models::{Dependency, Project, ProjectKind, ProjectSlug};
+use crate::projects;
 use crate::settings::Settings;
-use crate::web::auth::BasicAuth;
+use crate::tokens::Permission;
+use crate::versions::retention;
+use crate::web::audit::Audit;
+use crate::web::auth::Auth;
+use crate::web::pagination::{paginate, SortOrder};
 use crate::web::response::ApiResponse;
-use crate::web::schema::{DependencyOut, ProjectOut};
+use crate::web::schema::{DependencyOut, ProjectOut, ProjectsGroupOut};
 use crate::web::AppStateType;
 
+#[derive(Deserialize)]
+struct ProjectsQueryParams {
+    /// Comma separated `key:value` labels, `key` matches any label value
+    label: Option<String>,
+    kind: Option<ProjectKind>,
+}
+
+impl ProjectsQueryParams {
+    fn labels(&self) -> IndexMap<String, String> {
+        self.label
+            .iter()
+            .flat_map(|label| label.split(','))
+            .filter(|label| !label.is_empty())
+            .map(|label| match label.split_once(':') {
+                Some((key, value)) => (key.to_owned(), value.to_owned()),
+                None => (label.to_owned(), String::new()),
+            })
+            .collect()
+    }
+
+    fn matches(&self, project: &Project) -> bool {
+        project.has_labels(&self.labels())
+            && self.kind.as_ref().is_none_or(|kind| &project.kind == kind)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ProjectsSort {
+    Slug,
+    Name,
+}
+
+#[derive(Deserialize)]
+struct ListProjectsQueryParams {
+    #[serde(flatten)]
+    filter: ProjectsQueryParams,
+    /// Projects with (`true`) or without (`false`) breaking dependencies
+    breaking: Option<bool>,
+    /// Projects with (`true`) or without (`false`) outdated dependencies
+    outdated: Option<bool>,
+    /// Projects are listed in `schemadoc.yaml` order if not set
+    sort: Option<ProjectsSort>,
+    order: Option<SortOrder>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+impl ListProjectsQueryParams {
+    fn matches(&self, project: &Project) -> bool {
+        let any = |f: fn(&Dependency) -> Option<bool>| {
+            project.dependencies.iter().any(|d| f(d) == Some(true))
+        };
+
+        self.filter.matches(project)
+            && self.breaking.is_none_or(|b| any(|d| d.breaking) == b)
+            && self.outdated.is_none_or(|o| any(|d| d.outdated) == o)
+    }
+}
+
 #[get("")]
-async fn list_projects_endpoint(state: web::Data<AppStateType>) -> ApiResponse {
+async fn list_projects_endpoint(
+    state: web::Data<AppStateType>,
+    query: web::Query<ListProjectsQueryParams>,
+) -> ApiResponse {
     let state = state.read().await;
 
-    let projects = state.projects.values().collect::<Vec<_>>();
+    let mut projects = state
+        .projects
+        .values()
+        .filter(|p| query.matches(p))
+        .collect::<Vec<_>>();
+
+    match query.sort {
+        Some(ProjectsSort::Slug) => projects.sort_by(|a, b| a.slug.as_str().cmp(b.slug.as_str())),
+        Some(ProjectsSort::Name) => projects.sort_by_cached_key(|p| p.name.to_lowercase()),
+        None => {}
+    }
+    if query.order == Some(SortOrder::Desc) {
+        projects.reverse();
+    }
 
     let out: Vec<_> = projects.into_iter().map(ProjectOut::from).collect();
 
+    paginate(out, query.offset, query.limit).into()
+}
+
+#[derive(Deserialize)]
+struct GroupsQueryParams {
+    by: String,
+    #[serde(flatten)]
+    filter: ProjectsQueryParams,
+}
+
+#[get("/groups")]
+async fn list_projects_groups_endpoint(
+    state: web::Data<AppStateType>,
+    query: web::Query<GroupsQueryParams>,
+) -> ApiResponse {
+    let state = state.read().await;
+
+    let mut groups: IndexMap<Option<&str>, Vec<_>> = IndexMap::new();
+    for project in state.projects.values().filter(|p| query.filter.matches(p)) {
+        let value = project.labels.get(&query.by).map(|v| v.as_str());
+        groups
+            .entry(value)
+            .or_default()
+            .push(ProjectOut::from(project));
+    }
+
+    let out: Vec<_> = groups
+        .into_iter()
+        .map(|(value, projects)| ProjectsGroupOut { value, projects })
+        .collect();
+
     (&out,).into()
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GraphFormat {
+    Json,
+    Dot,
+    Mermaid,
+}
+
+#[derive(Deserialize)]
+struct GraphQueryParams {
+    format: Option<GraphFormat>,
+}
+
+#[get("/graph")]
+async fn get_projects_graph_endpoint(
+    state: web::Data<AppStateType>,
+    query: web::Query<GraphQueryParams>,
+) -> error::Result<Either<ApiResponse, HttpResponse>> {
+    let state = state.read().await;
+
+    let graph = DependencyGraph::new(&state);
+
+    let response = match query.format.as_ref().unwrap_or(&GraphFormat::Json) {
+        GraphFormat::Json => Either::Left((&graph,).into()),
+        GraphFormat::Dot => Either::Right(
+            HttpResponse::Ok()
+                .content_type("text/vnd.graphviz; charset=utf-8")
+                .body(graph.to_dot()),
+        ),
+        GraphFormat::Mermaid => Either::Right(
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .body(graph.to_mermaid()),
+        ),
+    };
+
+    Ok(response)
+}
+
 #[get("/{slug}")]
 async fn get_project_by_id_endpoint(
     path: web::Path<ProjectSlug>,
@@ -32,6 +192,106 @@ async fn get_project_by_id_endpoint(
     (&project.map(ProjectOut::from),).into()
 }
 
+#[derive(Deserialize)]
+struct CreateProjectBody {
+    slug: ProjectSlug,
+    #[serde(flatten)]
+    config: ProjectConfig,
+}
+
+#[post("")]
+async fn create_project_endpoint(
+    auth: Auth,
+    audit: Audit,
+    body: web::Json<CreateProjectBody>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    let body = body.into_inner();
+
+    audit.target(AuditTarget::project(&body.slug));
+    auth.require(Permission::ProjectsWrite, None)?;
+
+    let mut lock = state.write().await;
+
+    if lock.projects.contains_key(&body.slug) {
+        return Err(error::ErrorConflict("Project already exists"));
+    }
+
+    let project = projects::create_project(lock.deref_mut(), &body.slug, body.config)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    Ok((&ProjectOut::from(project), StatusCode::CREATED).into())
+}
+
+#[put("/{slug}")]
+async fn update_project_endpoint(
+    auth: Auth,
+    path: web::Path<ProjectSlug>,
+    body: web::Json<ProjectConfig>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::ProjectsWrite, Some(path.as_ref()))?;
+
+    let mut lock = state.write().await;
+
+    if !lock.projects.contains_key(path.as_ref()) {
+        return Err(error::ErrorNotFound("Project not found"));
+    }
+
+    let project = projects::update_project(lock.deref_mut(), path.as_ref(), body.into_inner())
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    Ok((&ProjectOut::from(project),).into())
+}
+
+#[patch("/{slug}")]
+async fn patch_project_endpoint(
+    auth: Auth,
+    path: web::Path<ProjectSlug>,
+    body: web::Json<serde_json::Value>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::ProjectsWrite, Some(path.as_ref()))?;
+
+    let mut lock = state.write().await;
+
+    if !lock.projects.contains_key(path.as_ref()) {
+        return Err(error::ErrorNotFound("Project not found"));
+    }
+
+    let project = projects::patch_project(lock.deref_mut(), path.as_ref(), body.into_inner())
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    Ok((&ProjectOut::from(project),).into())
+}
+
+#[delete("/{slug}")]
+async fn delete_project_endpoint(
+    auth: Auth,
+    path: web::Path<ProjectSlug>,
+    state: web::Data<AppStateType>,
+    locks: web::Data<PullLocks>,
+) -> error::Result<HttpResponse> {
+    auth.require(Permission::ProjectsWrite, Some(path.as_ref()))?;
+
+    let mut lock = state.write().await;
+
+    if !lock.projects.contains_key(path.as_ref()) {
+        return Err(error::ErrorNotFound("Project not found"));
+    }
+
+    projects::delete_project(lock.deref_mut(), path.as_ref())
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    locks.prune(&lock);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[get("/{slug}/dependents")]
 async fn get_dependents_project_by_id_endpoint(
     path: web::Path<ProjectSlug>,
@@ -55,6 +315,8 @@ async fn get_dependents_project_by_id_endpoint(
                     project: p.slug.clone(),
                     branch: d.branch.clone(),
                     version: d.version,
+                    range: d.range.clone(),
+                    usage: d.usage.clone(),
                     breaking: d.breaking,
                     outdated: d.outdated,
                 })
@@ -66,6 +328,75 @@ async fn get_dependents_project_by_id_endpoint(
     Ok((&body,).into())
 }
 
+#[derive(Deserialize)]
+struct ImpactQueryParams {
+    branch: Option<String>,
+    version: Option<u32>,
+}
+
+#[get("/{slug}/impact")]
+async fn get_project_version_impact_endpoint(
+    path: web::Path<ProjectSlug>,
+    state: web::Data<AppStateType>,
+    query: web::Query<ImpactQueryParams>,
+) -> error::Result<ApiResponse> {
+    let state = state.read().await;
+
+    let result = graph::get_version_impact(
+        &state,
+        path.as_ref(),
+        query.branch.as_deref(),
+        query.version,
+    )
+    .await
+    .map_err(|err| match err {
+        ImpactError::NotFound(_) => error::ErrorNotFound(err),
+        ImpactError::Other(_) => error::ErrorInternalServerError(err),
+    })?;
+
+    Ok((&result,).into())
+}
+
+#[derive(Deserialize)]
+struct CompatibilityQueryParams {
+    branch: Option<String>,
+    client: Option<ProjectSlug>,
+    limit: Option<usize>,
+}
+
+#[get("/{slug}/compatibility")]
+async fn get_project_compatibility_endpoint(
+    path: web::Path<ProjectSlug>,
+    state: web::Data<AppStateType>,
+    query: web::Query<CompatibilityQueryParams>,
+) -> error::Result<ApiResponse> {
+    let limit = query.limit.unwrap_or(10);
+    if limit > compatibility::MAX_MATRIX_VERSIONS {
+        return Err(error::ErrorBadRequest(format!(
+            "Compatibility matrix is limited to {} versions",
+            compatibility::MAX_MATRIX_VERSIONS
+        )));
+    }
+
+    let state = state.read().await;
+
+    let result = compatibility::get_compatibility_matrix(
+        &state,
+        path.as_ref(),
+        query.branch.as_deref(),
+        query.client.as_ref(),
+        limit,
+    )
+    .await
+    .map_err(|err| match err {
+        CompatibilityError::NotFound(_) => error::ErrorNotFound(err),
+        CompatibilityError::NotDependent { .. } => error::ErrorBadRequest(err),
+        CompatibilityError::Other(_) => error::ErrorInternalServerError(err),
+    })?;
+
+    Ok((&result,).into())
+}
+
 #[derive(Deserialize)]
 struct PullQueryParams {
     force: Option<bool>,
@@ -73,29 +404,246 @@ struct PullQueryParams {
 
 #[post("/{slug}/pull")]
 async fn pull_project_datasource_endpoint(
-    _: BasicAuth,
+    auth: Auth,
+    audit: Audit,
     path: web::Path<ProjectSlug>,
     settings: web::Data<Settings>,
+    locks: web::Data<PullLocks>,
     state: web::Data<AppStateType>,
     query: web::Query<PullQueryParams>,
 ) -> error::Result<ApiResponse> {
-    let mut lock = state.write().await;
+    let force = query.force.unwrap_or(false);
 
-    let state = lock.deref_mut();
+    audit.target(AuditTarget::project(path.as_ref()).with_details(format!("force={force}")));
+    auth.require(Permission::VersionsWrite, Some(path.as_ref()))?;
 
-    let force = query.force.unwrap_or(false);
+    datasources::pull_project_datasources(
+        settings.as_ref(),
+        locks.as_ref(),
+        state.as_ref(),
+        path.as_ref(),
+        force,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(("Pulled",).into())
+}
+
+#[post("/{slug}/retention")]
+async fn apply_project_retention_endpoint(
+    auth: Auth,
+    path: web::Path<ProjectSlug>,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::ProjectsWrite, Some(path.as_ref()))?;
 
-    datasources::pull_project_datasources(settings.as_ref(), state, path.as_ref(), force)
+    let result = retention::apply_project_retention(&state, path.as_ref())
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    Ok(("Pulled",).into())
+    Ok((&result,).into())
+}
+
+#[get("/{slug}/export")]
+async fn export_project_endpoint(
+    auth: Auth,
+    path: web::Path<ProjectSlug>,
+    state: web::Data<AppStateType>,
+) -> error::Result<HttpResponse> {
+    auth.require(Permission::ProjectsWrite, Some(path.as_ref()))?;
+
+    let state = state.read().await;
+
+    let project_slug = path.into_inner();
+
+    let archive = archive::export_project(&state, &project_slug)
+        .await
+        .map_err(|err| match err {
+            ExportError::NotFound(_) => error::ErrorNotFound(err),
+            ExportError::Other(_) => error::ErrorInternalServerError(err),
+        })?;
+
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!(
+            "{}.schemadoc.json",
+            project_slug
+        ))],
+    };
+
+    Ok(HttpResponse::Ok().insert_header(disposition).json(&archive))
+}
+
+#[derive(Deserialize)]
+struct ImportQueryParams {
+    slug: Option<ProjectSlug>,
+}
+
+#[post("/import")]
+async fn import_project_endpoint(
+    auth: Auth,
+    audit: Audit,
+    bytes: Bytes,
+    state: web::Data<AppStateType>,
+    query: web::Query<ImportQueryParams>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::ProjectsWrite, None)?;
+
+    let archive: ProjectArchiveFile =
+        serde_json::from_slice(&bytes).map_err(error::ErrorBadRequest)?;
+
+    let mut lock = state.write().await;
+
+    let state = lock.deref_mut();
+
+    let result = archive::import_project(state, archive, query.into_inner().slug)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+
+    audit.target(AuditTarget::project(&result.slug));
+
+    let status_code = if result.created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((&result, status_code).into())
 }
 
 pub fn get_projects_api_scope() -> actix_web::Scope {
     web::scope("projects")
         .service(list_projects_endpoint)
+        .service(list_projects_groups_endpoint)
+        .service(get_projects_graph_endpoint)
+        .service(create_project_endpoint)
+        .service(import_project_endpoint)
         .service(get_project_by_id_endpoint)
+        .service(update_project_endpoint)
+        .service(patch_project_endpoint)
+        .service(delete_project_endpoint)
         .service(pull_project_datasource_endpoint)
+        .service(apply_project_retention_endpoint)
+        .service(export_project_endpoint)
         .service(get_dependents_project_by_id_endpoint)
+        .service(get_project_version_impact_endpoint)
+        .service(get_project_compatibility_endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+    use tokio::sync::RwLock;
+
+    use crate::app_state::AppState;
+    use crate::storage::{Compression, LocalStorage, Storage};
+    use crate::web::projects::get_projects_api_scope;
+
+    const CONFIG: &str = r#"
+version: "0.1"
+projects:
+  payments: { name: Payments, labels: { team: core, tier: "1" } }
+  orders: { name: Orders, labels: { team: core, tier: "2" } }
+  web: { name: Web, labels: { team: web } }
+  docs: { name: Docs }
+"#;
+
+    #[actix_web::test]
+    async fn test_labels_filter_and_groups() {
+        let location = std::env::temp_dir().join(format!("sd-labels-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = web::Data::new(RwLock::new(AppState::read(storage, None).await.unwrap()));
+
+        let app =
+            test::init_service(App::new().app_data(state).service(get_projects_api_scope())).await;
+
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        let slugs = |body: &Value| {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p["slug"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let body: Value =
+            test::call_and_read_body_json(&app, get("/projects?label=team:core")).await;
+        assert_eq!(slugs(&body["result"]), vec!["payments", "orders"]);
+
+        // Label without value matches any value of the label
+        let body: Value = test::call_and_read_body_json(&app, get("/projects?label=tier")).await;
+        assert_eq!(slugs(&body["result"]), vec!["payments", "orders"]);
+
+        let body: Value =
+            test::call_and_read_body_json(&app, get("/projects?label=team:core,tier:2")).await;
+        assert_eq!(slugs(&body["result"]), vec!["orders"]);
+
+        let body: Value =
+            test::call_and_read_body_json(&app, get("/projects/groups?by=team")).await;
+        let groups: Vec<_> = body["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|group| (group["value"].clone(), slugs(&group["projects"])))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    json!("core"),
+                    vec!["payments".to_owned(), "orders".to_owned()]
+                ),
+                (json!("web"), vec!["web".to_owned()]),
+                (Value::Null, vec!["docs".to_owned()]),
+            ]
+        );
+
+        let body: Value =
+            test::call_and_read_body_json(&app, get("/projects/groups?by=tier&label=team:core"))
+                .await;
+        let values: Vec<_> = body["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|group| group["value"].clone())
+            .collect();
+        assert_eq!(values, vec![json!("1"), json!("2")]);
+
+        std::fs::remove_dir_all(location).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_compatibility_limit() {
+        let location = std::env::temp_dir().join(format!("sd-compat-limit-{}", std::process::id()));
+        std::fs::create_dir_all(&location).unwrap();
+        std::fs::write(location.join("schemadoc.yaml"), CONFIG).unwrap();
+
+        let storage = Storage::Local(LocalStorage::new(&location, Compression::None));
+        let state = web::Data::new(RwLock::new(AppState::read(storage, None).await.unwrap()));
+
+        let app =
+            test::init_service(App::new().app_data(state).service(get_projects_api_scope())).await;
+
+        let status = |uri: &'static str| {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request);
+            async { response.await.status().as_u16() }
+        };
+
+        assert_eq!(
+            status("/projects/payments/compatibility?limit=51").await,
+            400
+        );
+        assert_eq!(
+            status("/projects/payments/compatibility?limit=50").await,
+            200
+        );
+
+        std::fs::remove_dir_all(&location).unwrap();
+    }
 }
@@ -0,0 +1,55 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{error, HttpMessage};
+use tracing_actix_web::RequestId;
+
+/// Returns the request id of the request tracing span in `X-Request-Id` header
+pub async fn request_id_header(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, error::Error> {
+    let request_id = req.extensions().get::<RequestId>().copied();
+
+    let mut res = next.call(req).await?;
+
+    if let Some(request_id) = request_id {
+        if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+            res.headers_mut()
+                .insert(HeaderName::from_static("x-request-id"), value);
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware, test, web, App};
+    use tracing_actix_web::{RequestId, TracingLogger};
+
+    use crate::web::request_id::request_id_header;
+
+    #[actix_web::test]
+    async fn test_request_id_header() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(request_id_header))
+                .wrap(TracingLogger::default())
+                .route(
+                    "/",
+                    web::get().to(|request_id: RequestId| async move { request_id.to_string() }),
+                ),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let header = res.headers().get("x-request-id").cloned().unwrap();
+        let body = test::read_body(res).await;
+
+        // Response header has the id of the request span seen by handlers
+        assert_eq!(header.as_bytes(), &body[..]);
+        assert!(!body.is_empty());
+    }
+}
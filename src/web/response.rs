@@ -4,9 +4,13 @@ use actix_web::http::StatusCode;
 use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
 use serde::Serialize;
 
+use crate::web::pagination::PageMeta;
+
 #[derive(Serialize)]
 struct ApiResponseInner<T: Serialize> {
     result: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<PageMeta>,
 }
 
 pub struct ApiResponse {
@@ -14,10 +18,8 @@ pub struct ApiResponse {
     status_code: StatusCode,
 }
 
-impl<T: Serialize> From<(T, StatusCode)> for ApiResponse {
-    fn from((result, status_code): (T, StatusCode)) -> Self {
-        let inner = ApiResponseInner { result };
-
+impl ApiResponse {
+    fn new<T: Serialize>(inner: ApiResponseInner<T>, status_code: StatusCode) -> Self {
         if let Ok(bytes) = serde_json::to_vec(&inner) {
             ApiResponse { bytes, status_code }
         } else {
@@ -29,6 +31,23 @@ impl<T: Serialize> From<(T, StatusCode)> for ApiResponse {
     }
 }
 
+impl<T: Serialize> From<(T, StatusCode)> for ApiResponse {
+    fn from((result, status_code): (T, StatusCode)) -> Self {
+        ApiResponse::new(ApiResponseInner { result, meta: None }, status_code)
+    }
+}
+
+/// Page of a list with pagination metadata
+impl<T: Serialize> From<(T, PageMeta)> for ApiResponse {
+    fn from((result, meta): (T, PageMeta)) -> Self {
+        let inner = ApiResponseInner {
+            result,
+            meta: Some(meta),
+        };
+        ApiResponse::new(inner, StatusCode::OK)
+    }
+}
+
 impl<T: Serialize> From<(T,)> for ApiResponse {
     fn from(result: (T,)) -> Self {
         (result.0, StatusCode::OK).into()
@@ -1,9 +1,12 @@
 use crate::models::{
     Alert, AlertKind, AlertSource, DataSource, DataSourceSource, DataSourceStatus, Dependency,
-    Link, Project, ProjectSlug, Version,
+    DependencyUsage, DiffFileFormat, Link, Owner, Project, ProjectSlug, Version,
 };
+use crate::scheduler;
+use crate::tokens::{ApiToken, TokenScope};
 use crate::versions::statistics::DiffStatistics;
 use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -15,8 +18,10 @@ pub struct VersionOut<'s> {
 
     pub file_path: &'s String,
     pub diff_file_path: &'s String,
+    pub diff_file_format: DiffFileFormat,
 
     pub statistics: &'s DiffStatistics,
+    pub breaking_changes: Option<u32>,
 
     pub created_at: &'s DateTime<Utc>,
 }
@@ -29,8 +34,10 @@ impl<'s> From<&'s Version> for VersionOut<'s> {
             message: value.message.as_ref(),
             file_path: &value.file_path,
             diff_file_path: &value.diff_file_path,
+            diff_file_format: value.diff_file_format,
             created_at: &value.created_at,
             statistics: &value.statistics,
+            breaking_changes: value.breaking_changes,
         }
     }
 }
@@ -67,6 +74,8 @@ pub struct DataSourceStatusOut {
 
     pub pull_interval_minutes: u32,
     pub pull_last_at: Option<DateTime<Utc>>,
+    /// Scheduled pull time, `None` if pulling is disabled
+    pub pull_next_at: Option<DateTime<Utc>>,
     pub pull_error: bool,
 }
 
@@ -77,6 +86,7 @@ impl From<&DataSourceStatus> for DataSourceStatusOut {
             pull_attempt: status.pull_attempt,
             pull_interval_minutes: status.pull_interval_minutes,
             pull_last_at: status.pull_last_at,
+            pull_next_at: None,
             pull_error: status.pull_error,
         }
     }
@@ -88,6 +98,8 @@ pub struct DataSourceOut<'s> {
     pub name: &'s str,
     pub branch: &'s str,
     pub source: &'s DataSourceSource,
+    pub pull_cron: Option<&'s str>,
+    pub hook: Option<DataSourceHookOut<'s>>,
     pub status: Option<DataSourceStatusOut>,
 }
 
@@ -96,8 +108,20 @@ impl<'s> From<&'s DataSource> for DataSourceOut<'s> {
         Self {
             name: &data_source.name,
             branch: &data_source.branch,
-            source: &data_source.source,
-            status: data_source.status.as_ref().map(DataSourceStatusOut::from),
+            source: data_source.source.redacted(),
+            pull_cron: data_source.pull_cron.as_deref(),
+            hook: data_source.hook.as_ref().map(|hook| DataSourceHookOut {
+                repository: &hook.value.repository,
+                git_ref: hook.value.git_ref.as_deref(),
+                secret: hook.value.secret.is_some(),
+            }),
+            status: data_source
+                .status
+                .as_ref()
+                .map(|status| DataSourceStatusOut {
+                    pull_next_at: scheduler::next_pull_at(data_source),
+                    ..status.into()
+                }),
         }
     }
 }
@@ -107,7 +131,9 @@ impl<'s> From<&'s DataSource> for DataSourceOut<'s> {
 pub struct DependencyOut<'s> {
     pub project: &'s str,
     pub branch: &'s str,
-    pub version: u32,
+    pub version: Option<u32>,
+    pub range: Option<&'s str>,
+    pub usage: Option<&'s DependencyUsage>,
     pub outdated: Option<bool>,
     pub breaking: Option<bool>,
 }
@@ -116,6 +142,8 @@ impl<'s> From<&'s Dependency> for DependencyOut<'s> {
     fn from(dependency: &'s Dependency) -> DependencyOut<'s> {
         Self {
             version: dependency.version,
+            range: dependency.range.as_deref(),
+            usage: dependency.usage.as_ref(),
             outdated: dependency.outdated,
             breaking: dependency.breaking,
             branch: dependency.branch.as_str(),
@@ -133,6 +161,8 @@ pub struct ProjectOut<'s> {
     pub description: Option<&'s str>,
 
     pub links: Option<&'s Vec<Link>>,
+    pub labels: &'s IndexMap<String, String>,
+    pub owners: &'s Vec<Owner>,
 
     pub branches: Vec<String>,
     pub alerts: Vec<AlertOut<'s>>,
@@ -140,6 +170,14 @@ pub struct ProjectOut<'s> {
     pub dependencies: Vec<DependencyOut<'s>>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectsGroupOut<'s> {
+    /// Label value, `None` for projects without the label
+    pub value: Option<&'s str>,
+    pub projects: Vec<ProjectOut<'s>>,
+}
+
 impl<'s> From<&'s Project> for ProjectOut<'s> {
     fn from(project: &'s Project) -> ProjectOut<'s> {
         let alerts = project.alerts.iter().map(AlertOut::from).collect();
@@ -153,6 +191,8 @@ impl<'s> From<&'s Project> for ProjectOut<'s> {
             name: &project.name,
             kind: project.kind.as_str(),
             links: project.links.as_ref(),
+            labels: &project.labels,
+            owners: &project.owners,
             description: project.description.as_deref(),
             data_sources: project
                 .data_sources
@@ -167,3 +207,52 @@ impl<'s> From<&'s Project> for ProjectOut<'s> {
         }
     }
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenOut<'s> {
+    pub name: &'s str,
+    pub scopes: &'s [TokenScope],
+    pub created_at: &'s DateTime<Utc>,
+    pub expires_at: Option<&'s DateTime<Utc>>,
+    pub is_expired: bool,
+}
+
+impl<'s> From<&'s ApiToken> for TokenOut<'s> {
+    fn from(token: &'s ApiToken) -> Self {
+        Self {
+            name: &token.name,
+            scopes: &token.scopes,
+            created_at: &token.created_at,
+            expires_at: token.expires_at.as_ref(),
+            is_expired: token.is_expired(),
+        }
+    }
+}
+
+/// Created token with its value, which is not available afterwards
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedTokenOut<'s> {
+    #[serde(flatten)]
+    pub token: TokenOut<'s>,
+    pub token_value: &'s str,
+}
+
+/// Hook of a data source, its secret is not exposed
+#[derive(Serialize)]
+pub struct DataSourceHookOut<'s> {
+    pub repository: &'s str,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<&'s str>,
+    /// Hook has its own secret
+    pub secret: bool,
+}
+
+/// Data source triggered by a webhook
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookTargetOut<'s> {
+    pub project: &'s ProjectSlug,
+    pub branch: &'s str,
+}
@@ -0,0 +1,100 @@
+use actix_web::http::StatusCode;
+use actix_web::{delete, error, get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::audit::AuditTarget;
+use crate::tokens::{self, Permission, TokenScope, TokensCache};
+use crate::web::audit::Audit;
+use crate::web::auth::Auth;
+use crate::web::response::ApiResponse;
+use crate::web::schema::{CreatedTokenOut, TokenOut};
+use crate::web::AppStateType;
+
+#[get("")]
+async fn list_tokens_endpoint(
+    auth: Auth,
+    state: web::Data<AppStateType>,
+) -> error::Result<ApiResponse> {
+    auth.require(Permission::Admin, None)?;
+
+    let storage = state.read().await.storage.clone();
+
+    let tokens = tokens::load_tokens(&storage)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    let body: Vec<_> = tokens.iter().map(TokenOut::from).collect();
+
+    Ok((&body,).into())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateTokenBody {
+    name: String,
+    scopes: Vec<TokenScope>,
+    expires_at: Option<DateTime<Utc>>,
+    expires_in_days: Option<i64>,
+}
+
+#[post("")]
+async fn create_token_endpoint(
+    auth: Auth,
+    audit: Audit,
+    body: web::Json<CreateTokenBody>,
+    state: web::Data<AppStateType>,
+    cache: web::Data<TokensCache>,
+) -> error::Result<ApiResponse> {
+    let body = body.into_inner();
+
+    audit.target(AuditTarget::details(&body.name));
+    auth.require(Permission::Admin, None)?;
+
+    let expires_at = match body.expires_in_days {
+        Some(days) if body.expires_at.is_none() => {
+            Some(tokens::expires_in_days(days).map_err(error::ErrorBadRequest)?)
+        }
+        _ => body.expires_at,
+    };
+
+    let storage = state.read().await.storage.clone();
+
+    let (token, value) = tokens::create_token(&storage, &body.name, body.scopes, expires_at)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+    cache.invalidate().await;
+
+    let body = CreatedTokenOut {
+        token: TokenOut::from(&token),
+        token_value: &value,
+    };
+
+    Ok((&body, StatusCode::CREATED).into())
+}
+
+#[delete("/{name}")]
+async fn revoke_token_endpoint(
+    auth: Auth,
+    path: web::Path<String>,
+    state: web::Data<AppStateType>,
+    cache: web::Data<TokensCache>,
+) -> error::Result<HttpResponse> {
+    auth.require(Permission::Admin, None)?;
+
+    let storage = state.read().await.storage.clone();
+
+    tokens::revoke_token(&storage, path.as_ref())
+        .await
+        .map_err(error::ErrorNotFound)?;
+    cache.invalidate().await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn get_tokens_api_scope() -> actix_web::Scope {
+    web::scope("tokens")
+        .service(list_tokens_endpoint)
+        .service(create_token_endpoint)
+        .service(revoke_token_endpoint)
+}
@@ -3,20 +3,96 @@ use std::ops::DerefMut;
 use actix_web::http::StatusCode;
 use actix_web::web::Bytes;
 use actix_web::{error, get, post, web, HttpRequest, Responder};
+use chrono::{DateTime, Utc};
 use schemadoc_diff::schema_diff::HttpSchemaDiff;
+use semver::VersionReq;
+use serde::Deserialize;
 
-use crate::models::ProjectSlug;
+use crate::audit::AuditTarget;
+use crate::dependencies::parse_version;
+use crate::models::{ProjectSlug, Version};
 use crate::settings::Settings;
-use crate::storage::Storer;
+use crate::tokens::Permission;
 use crate::versions::{crud, services, statistics};
-use crate::web::auth::BasicAuth;
+use crate::web::audit::Audit;
+use crate::web::auth::Auth;
+use crate::web::pagination::{paginate, SortOrder};
 use crate::web::response::ApiResponse;
 use crate::web::schema::VersionOut;
 use crate::web::AppStateType;
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum VersionsSort {
+    Id,
+    CreatedAt,
+    Version,
+}
+
+#[derive(Deserialize)]
+struct VersionsQueryParams {
+    /// Only versions with (`true`) or without (`false`) breaking changes
+    breaking: Option<bool>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    /// Case insensitive part of the version message
+    message: Option<String>,
+    /// `info.version` or its semver range, e.g. `^2.3`
+    version: Option<String>,
+    sort: Option<VersionsSort>,
+    order: Option<SortOrder>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+impl VersionsQueryParams {
+    fn matches(&self, version: &Version) -> bool {
+        let message = self.message.as_ref().map(|m| m.to_lowercase());
+
+        self.since.is_none_or(|since| version.created_at >= since)
+            && self.until.is_none_or(|until| version.created_at < until)
+            && message.is_none_or(|message| {
+                version
+                    .message
+                    .as_ref()
+                    .is_some_and(|m| m.to_lowercase().contains(&message))
+            })
+            && self.version.as_ref().is_none_or(|query| {
+                version
+                    .version
+                    .as_deref()
+                    .is_some_and(|version| matches_version(query, version))
+            })
+            // Versions which breaking changes could not be computed are not matched
+            && self.breaking.is_none_or(|breaking| {
+                version
+                    .breaking_changes
+                    .is_some_and(|count| (count > 0) == breaking)
+            })
+    }
+}
+
+/// Versions are equal if both are parsed to the same semver, range operators are required for ranges
+fn matches_version(query: &str, version: &str) -> bool {
+    if query == version {
+        return true;
+    }
+
+    let is_range = query.starts_with(['^', '~', '<', '>', '=', '*']) || query.contains(',');
+    if is_range {
+        VersionReq::parse(query)
+            .ok()
+            .zip(parse_version(version))
+            .is_some_and(|(range, version)| range.matches(&version))
+    } else {
+        parse_version(query).is_some_and(|query| Some(query) == parse_version(version))
+    }
+}
+
 #[get("")]
 async fn list_versions_endpoint(
     path: web::Path<(ProjectSlug, String)>,
+    query: web::Query<VersionsQueryParams>,
     state: web::Data<AppStateType>,
 ) -> error::Result<ApiResponse> {
     let state = state.read().await;
@@ -25,17 +101,32 @@ async fn list_versions_endpoint(
 
     let Some(versions) = crud::get_versions(&state, project_slug, branch_name) else {
         // Versions could not be initialized, so just return empty vec
-        return Ok((Vec::<VersionOut>::new(),).into());
+        return Ok(paginate(Vec::<VersionOut>::new(), query.offset, query.limit).into());
     };
 
-    let result: Vec<_> = versions.iter().map(VersionOut::from).rev().collect();
+    let mut matched: Vec<_> = versions.iter().filter(|v| query.matches(v)).collect();
 
-    Ok((result,).into())
+    match query.sort.as_ref().unwrap_or(&VersionsSort::Id) {
+        VersionsSort::Id => matched.sort_by_key(|v| v.id),
+        VersionsSort::CreatedAt => matched.sort_by_key(|v| (v.created_at, v.id)),
+        VersionsSort::Version => {
+            matched.sort_by_cached_key(|v| (v.version.as_deref().and_then(parse_version), v.id))
+        }
+    }
+    // Newest versions first by default
+    if query.order.unwrap_or(SortOrder::Desc) == SortOrder::Desc {
+        matched.reverse();
+    }
+
+    let result: Vec<_> = matched.into_iter().map(VersionOut::from).collect();
+
+    Ok(paginate(result, query.offset, query.limit).into())
 }
 
 #[post("")]
 async fn add_version_endpoint(
-    _: BasicAuth,
+    auth: Auth,
+    audit: Audit,
     path: web::Path<(ProjectSlug, String)>,
     bytes: Bytes,
     req: HttpRequest,
@@ -44,7 +135,7 @@ async fn add_version_endpoint(
 ) -> error::Result<ApiResponse> {
     let (project_slug, branch_name) = path.as_ref();
 
-    let mut state = state.write().await;
+    auth.require(Permission::VersionsWrite, Some(project_slug))?;
 
     let message = req
         .headers()
@@ -69,11 +160,9 @@ async fn add_version_endpoint(
         .transpose()
         .map_err(error::ErrorBadRequest)?;
 
-    let version = {
-        let state = state.deref_mut();
-
+    let result = async {
         crate::branches::create_branch_if_not_exists(
-            state,
+            state.write().await.deref_mut(),
             project_slug,
             branch_name,
             branch_base_name,
@@ -86,15 +175,21 @@ async fn add_version_endpoint(
 
         services::create_version(
             &settings,
-            state,
+            &state,
             project_slug,
             branch_name,
             message,
             &content,
         )
         .await
-        .map_err(|e| error::ErrorInternalServerError(format!("Error creating version: {}", e)))?
-    };
+        .map_err(|e| error::ErrorInternalServerError(format!("Error creating version: {}", e)))
+    }
+    .await;
+
+    let version_id = result.as_ref().ok().and_then(|v| v.as_ref().map(|v| v.id));
+    audit.target(AuditTarget::branch(project_slug, branch_name).with_version(version_id));
+
+    let version = result?;
 
     let result = version.as_ref().map(VersionOut::from);
 
@@ -118,9 +213,15 @@ async fn get_version_by_id_endpoint(
     Ok((result,).into())
 }
 
+#[derive(Deserialize)]
+struct DiffQueryParams {
+    compact: Option<bool>,
+}
+
 #[get("/{id}/compare/{tgt_branch_name}/{tgt_id}")]
 async fn compare_two_versions_endpoint(
     path: web::Path<(ProjectSlug, String, u32, String, u32)>,
+    query: web::Query<DiffQueryParams>,
     state: web::Data<AppStateType>,
 ) -> error::Result<ApiResponse> {
     let (project_slug, src_branch_name, src_version_id, tgt_branch_name, tgt_version_id) =
@@ -142,33 +243,47 @@ async fn compare_two_versions_endpoint(
     })?;
 
     let Some(diff) = compare_result.get() else {
-        return Ok((None::<Response>, StatusCode::NO_CONTENT).into());
+        return Ok((None::<Response<&HttpSchemaDiff>>, StatusCode::NO_CONTENT).into());
     };
 
     let statistics = statistics::get_diff_statistics(diff);
 
     #[derive(serde::Serialize)]
-    struct Response<'s> {
-        diff: &'s HttpSchemaDiff,
+    struct Response<D: serde::Serialize> {
+        diff: D,
         statistics: statistics::DiffStatistics,
     }
 
+    if query.compact.unwrap_or(false) {
+        let diff = schemadoc_diff::compact::compact(diff).map_err(|e| {
+            error::ErrorInternalServerError(format!("Error while compacting diff: {}", e))
+        })?;
+        return Ok((Response { diff, statistics },).into());
+    }
+
     Ok((Response { diff, statistics },).into())
 }
 
 #[get("/{id}/diff")]
 async fn get_version_diff_content_endpoint(
     path: web::Path<(ProjectSlug, String, u32)>,
+    query: web::Query<DiffQueryParams>,
     state: web::Data<AppStateType>,
 ) -> error::Result<impl Responder> {
     let (project_slug, branch_name, id) = &path.into_inner();
 
     let state = state.read().await;
 
-    let version = crud::get_version(&state, project_slug, branch_name, *id)
-        .ok_or(error::ErrorNotFound("Version not found"))?;
+    let compact = query.compact.unwrap_or(false);
+
+    let content =
+        services::get_version_diff_content(&state, project_slug, branch_name, *id, compact)
+            .await
+            .map_err(|e| {
+                error::ErrorInternalServerError(format!("Error while reading diff: {}", e))
+            })?
+            .ok_or(error::ErrorNotFound("Version not found"))?;
 
-    let content = state.storage.read_file(&version.diff_file_path).await?;
     Ok(content)
 }
 